@@ -0,0 +1,50 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE ncch
+    ADD COLUMN short_title SMALLINT[16][64],
+    ADD COLUMN long_title SMALLINT[16][128],
+    ADD COLUMN publisher SMALLINT[16][64];
+
+-- Encodes a title to UTF-16 code units, padded with NUL to `len`
+CREATE FUNCTION pg_temp.text_to_utf16(title TEXT, len INTEGER)
+RETURNS SMALLINT[] AS $$
+DECLARE
+    result INTEGER[] := '{}';
+    c INTEGER;
+BEGIN
+    FOR i IN 1..char_length(title) LOOP
+        c := ascii(substr(title, i, 1));
+        IF c >= 65536 THEN
+            result := result || (55296 + ((c - 65536) >> 10)) || (56320 + ((c - 65536) & 1023));
+        ELSE
+            result := result || c;
+        END IF;
+    END LOOP;
+    WHILE cardinality(result) < len LOOP
+        result := result || 0;
+    END LOOP;
+    RETURN ARRAY(
+        SELECT CASE WHEN u >= 32768 THEN u - 65536 ELSE u END::SMALLINT
+        FROM unnest(result[1:len]) WITH ORDINALITY AS t(u, n)
+        ORDER BY n
+    );
+END;
+$$ LANGUAGE plpgsql;
+
+UPDATE ncch SET
+    short_title = (
+        SELECT array_agg(u ORDER BY t.language, n)
+        FROM ncch_title t, unnest(pg_temp.text_to_utf16(t.short_title, 64)) WITH ORDINALITY AS a(u, n)
+        WHERE t.ncch_id = ncch.id
+    ),
+    long_title = (
+        SELECT array_agg(u ORDER BY t.language, n)
+        FROM ncch_title t, unnest(pg_temp.text_to_utf16(t.long_title, 128)) WITH ORDINALITY AS a(u, n)
+        WHERE t.ncch_id = ncch.id
+    ),
+    publisher = (
+        SELECT array_agg(u ORDER BY t.language, n)
+        FROM ncch_title t, unnest(pg_temp.text_to_utf16(t.publisher, 64)) WITH ORDINALITY AS a(u, n)
+        WHERE t.ncch_id = ncch.id
+    );
+
+DROP TABLE ncch_title;
@@ -0,0 +1,59 @@
+CREATE TABLE ncch_title (
+    ncch_id TEXT NOT NULL REFERENCES ncch (id) ON DELETE CASCADE,
+    language SMALLINT NOT NULL,
+    short_title TEXT NOT NULL,
+    long_title TEXT NOT NULL,
+    publisher TEXT NOT NULL,
+    PRIMARY KEY (ncch_id, language)
+);
+
+-- Title searches match LOWER(column) LIKE '%...%', which only a trigram index can serve
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+CREATE INDEX ncch_title_short_title ON ncch_title USING GIN (LOWER(short_title) gin_trgm_ops);
+CREATE INDEX ncch_title_long_title ON ncch_title USING GIN (LOWER(long_title) gin_trgm_ops);
+CREATE INDEX ncch_title_publisher ON ncch_title USING GIN (LOWER(publisher) gin_trgm_ops);
+
+-- Decodes `len` UTF-16 code units starting at `first`, up to the first NUL
+CREATE FUNCTION pg_temp.utf16_to_text(units SMALLINT[], first INTEGER, len INTEGER)
+RETURNS TEXT AS $$
+DECLARE
+    result TEXT := '';
+    i INTEGER := first;
+    c INTEGER;
+    d INTEGER;
+BEGIN
+    WHILE i < first + len LOOP
+        c := units[i]::INTEGER & 65535;
+        EXIT WHEN c = 0;
+        IF c >= 55296 AND c < 56320 AND i + 1 < first + len THEN
+            d := units[i + 1]::INTEGER & 65535;
+            IF d >= 56320 AND d < 57344 THEN
+                result := result || chr(65536 + ((c - 55296) << 10) + (d - 56320));
+                i := i + 2;
+                CONTINUE;
+            END IF;
+        END IF;
+        IF c >= 55296 AND c < 57344 THEN
+            c := 65533;
+        END IF;
+        result := result || chr(c);
+        i := i + 1;
+    END LOOP;
+    RETURN result;
+END;
+$$ LANGUAGE plpgsql;
+
+INSERT INTO ncch_title (ncch_id, language, short_title, long_title, publisher)
+SELECT
+    id,
+    language,
+    pg_temp.utf16_to_text(short_title, language * 64 + 1, 64),
+    pg_temp.utf16_to_text(long_title, language * 128 + 1, 128),
+    pg_temp.utf16_to_text(publisher, language * 64 + 1, 64)
+FROM ncch, generate_series(0, 15) AS language
+WHERE short_title IS NOT NULL;
+
+ALTER TABLE ncch
+    DROP COLUMN short_title,
+    DROP COLUMN long_title,
+    DROP COLUMN publisher;
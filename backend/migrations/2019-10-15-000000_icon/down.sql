@@ -0,0 +1,23 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE ncch
+    ADD COLUMN small_icon SMALLINT[576],
+    ADD COLUMN large_icon SMALLINT[2304];
+
+CREATE FUNCTION pg_temp.icon_pixels(data BYTEA)
+RETURNS SMALLINT[] AS $$
+    SELECT array_agg(CASE WHEN u >= 32768 THEN u - 65536 ELSE u END::SMALLINT ORDER BY i)
+    FROM (
+        SELECT i, get_byte(data, i * 2) | (get_byte(data, i * 2 + 1) << 8) AS u
+        FROM generate_series(0, length(data) / 2 - 1) AS i
+    ) AS t
+$$ LANGUAGE SQL IMMUTABLE;
+
+UPDATE ncch SET
+    small_icon = (SELECT pg_temp.icon_pixels(data) FROM icon WHERE hash = small_icon_hash),
+    large_icon = (SELECT pg_temp.icon_pixels(data) FROM icon WHERE hash = large_icon_hash);
+
+ALTER TABLE ncch
+    DROP COLUMN small_icon_hash,
+    DROP COLUMN large_icon_hash;
+
+DROP TABLE icon;
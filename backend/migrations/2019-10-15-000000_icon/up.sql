@@ -0,0 +1,34 @@
+CREATE TABLE icon (
+    hash TEXT PRIMARY KEY,
+    data BYTEA NOT NULL,
+    png BYTEA
+);
+
+ALTER TABLE ncch
+    ADD COLUMN small_icon_hash TEXT REFERENCES icon (hash),
+    ADD COLUMN large_icon_hash TEXT REFERENCES icon (hash);
+
+-- Packs the pixels into little endian bytes
+CREATE FUNCTION pg_temp.icon_data(pixels SMALLINT[])
+RETURNS BYTEA AS $$
+    SELECT decode(string_agg(substr(h, 3, 2) || substr(h, 1, 2), '' ORDER BY n), 'hex')
+    FROM unnest(pixels) WITH ORDINALITY AS t(p, n),
+        lpad(to_hex(p::INTEGER & 65535), 4, '0') AS h
+$$ LANGUAGE SQL IMMUTABLE;
+
+-- PNG is left empty here and encoded by the server on first request
+INSERT INTO icon (hash, data)
+SELECT encode(sha256(data), 'hex'), data
+FROM (
+    SELECT pg_temp.icon_data(small_icon) AS data FROM ncch WHERE small_icon IS NOT NULL
+    UNION
+    SELECT pg_temp.icon_data(large_icon) AS data FROM ncch WHERE large_icon IS NOT NULL
+) AS icons;
+
+UPDATE ncch SET
+    small_icon_hash = encode(sha256(pg_temp.icon_data(small_icon)), 'hex'),
+    large_icon_hash = encode(sha256(pg_temp.icon_data(large_icon)), 'hex');
+
+ALTER TABLE ncch
+    DROP COLUMN small_icon,
+    DROP COLUMN large_icon;
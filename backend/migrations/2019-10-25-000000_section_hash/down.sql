@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE ncch
+    DROP COLUMN exheader_hash,
+    DROP COLUMN smdh_hash;
@@ -0,0 +1,4 @@
+-- Unknown for existing rows
+ALTER TABLE ncch
+    ADD COLUMN exheader_hash BYTEA,
+    ADD COLUMN smdh_hash BYTEA;
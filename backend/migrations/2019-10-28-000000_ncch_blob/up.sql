@@ -0,0 +1,8 @@
+-- Sections are zlib compressed. Rows uploaded before this have no blob.
+CREATE TABLE ncch_blob (
+    ncch_id TEXT PRIMARY KEY REFERENCES ncch (id) ON DELETE CASCADE,
+    header BYTEA NOT NULL,
+    exheader BYTEA,
+    exefs_header BYTEA,
+    smdh BYTEA
+);
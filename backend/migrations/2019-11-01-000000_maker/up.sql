@@ -0,0 +1,7 @@
+-- Maker codes are stored the same way as ncch.maker_code
+CREATE TABLE maker (
+    code SMALLINT PRIMARY KEY,
+    name TEXT NOT NULL
+);
+
+CREATE INDEX maker_name ON maker (name);
@@ -0,0 +1,12 @@
+-- Official releases from an imported release list, one row per matched NCCH
+CREATE TABLE ncch_release (
+    ncch_id TEXT NOT NULL REFERENCES ncch (id) ON DELETE CASCADE,
+    serial TEXT NOT NULL,
+    name TEXT NOT NULL,
+    region TEXT,
+    publisher TEXT,
+    release_date TEXT,
+    PRIMARY KEY (ncch_id, serial)
+);
+
+CREATE INDEX ncch_release_name ON ncch_release (name);
@@ -0,0 +1,3 @@
+-- Used by the program ID lookups, and by the DAT export, which pages through the NCCHs in
+-- program ID order
+CREATE INDEX ncch_program_id ON ncch (program_id, id);
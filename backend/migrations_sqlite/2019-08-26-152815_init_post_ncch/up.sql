@@ -0,0 +1,63 @@
+-- SQLite flavor of the Postgres migration. Arrays are stored as JSON text.
+CREATE TABLE IF NOT EXISTS ncch (
+    id TEXT PRIMARY KEY,
+    ncch_signature BLOB NOT NULL,
+    content_size INTEGER NOT NULL,
+    partition_id BIGINT NOT NULL,
+    maker_code SMALLINT NOT NULL,
+    ncch_verson SMALLINT NOT NULL,
+    program_id BIGINT NOT NULL,
+    product_code BLOB NOT NULL,
+    secondary_key_slot SMALLINT NOT NULL,
+    platform SMALLINT NOT NULL,
+    content_is_data BOOLEAN NOT NULL,
+    content_is_executable BOOLEAN NOT NULL,
+    content_category SMALLINT NOT NULL,
+    content_unit_size SMALLINT NOT NULL,
+    fixed_key BOOLEAN NOT NULL,
+    no_romfs BOOLEAN NOT NULL,
+    no_crypto BOOLEAN NOT NULL,
+    seed_crypto BOOLEAN NOT NULL,
+
+    exheader_name BLOB,
+    sd_app BOOLEAN,
+    remaster_version SMALLINT,
+    dependencies TEXT,
+    save_data_size BIGINT,
+    jump_id BIGINT,
+    exheader_program_id BIGINT,
+    core_version INTEGER,
+    enable_l2_cache BOOLEAN,
+    high_cpu_speed BOOLEAN,
+    system_mode SMALLINT,
+    n3ds_system_mode SMALLINT,
+    ideal_processor SMALLINT,
+    affinity_mask SMALLINT,
+    thread_priority SMALLINT,
+    resource_limit_desc TEXT,
+    extdata_id BIGINT,
+    system_savedata_id0 INTEGER,
+    system_savedata_id1 INTEGER,
+    storage_access_id BIGINT,
+    filesystem_flag BIGINT,
+    services TEXT,
+    resource_limit_category SMALLINT,
+    kernel_desc TEXT,
+    arm9_flag INT,
+    arm9_flag_version SMALLINT,
+
+    short_title TEXT,
+    long_title TEXT,
+    publisher TEXT,
+    ratings TEXT,
+    region_lockout INTEGER,
+    match_maker_id INTEGER,
+    match_maker_bit_id BIGINT,
+    smdh_flags INTEGER,
+    eula_version SMALLINT,
+    cec_id INTEGER,
+    small_icon TEXT,
+    large_icon TEXT,
+
+    keyword TEXT NOT NULL
+);
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS ncch_title (
+    ncch_id TEXT NOT NULL REFERENCES ncch (id) ON DELETE CASCADE,
+    language SMALLINT NOT NULL,
+    short_title TEXT NOT NULL,
+    long_title TEXT NOT NULL,
+    publisher TEXT NOT NULL,
+    PRIMARY KEY (ncch_id, language)
+);
+
+-- Title searches match LOWER(column) LIKE '%...%', which no SQLite index can serve, so only the
+-- primary key is indexed
+
+-- The title columns of ncch are converted by the server, and stay behind unused since SQLite can't
+-- drop columns
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS icon (
+    hash TEXT PRIMARY KEY,
+    data BLOB NOT NULL,
+    png BLOB
+);
+
+ALTER TABLE ncch ADD COLUMN small_icon_hash TEXT REFERENCES icon (hash);
+ALTER TABLE ncch ADD COLUMN large_icon_hash TEXT REFERENCES icon (hash);
+
+-- The icon columns of ncch are converted by the server, and stay behind unused since SQLite can't
+-- drop columns
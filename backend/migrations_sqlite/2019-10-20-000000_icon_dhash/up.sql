@@ -0,0 +1,2 @@
+-- Existing icons are hashed by the reprocess command
+ALTER TABLE icon ADD COLUMN dhash BIGINT;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE ncch DROP COLUMN exheader_hash;
+ALTER TABLE ncch DROP COLUMN smdh_hash;
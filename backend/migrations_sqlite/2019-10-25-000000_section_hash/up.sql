@@ -0,0 +1,3 @@
+-- Unknown for existing rows
+ALTER TABLE ncch ADD COLUMN exheader_hash BLOB;
+ALTER TABLE ncch ADD COLUMN smdh_hash BLOB;
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS ncch_blob (
+    ncch_id TEXT PRIMARY KEY REFERENCES ncch (id) ON DELETE CASCADE,
+    header BLOB NOT NULL,
+    exheader BLOB,
+    exefs_header BLOB,
+    smdh BLOB
+);
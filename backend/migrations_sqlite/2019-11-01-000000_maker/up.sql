@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS maker (
+    code SMALLINT PRIMARY KEY,
+    name TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS maker_name ON maker (name);
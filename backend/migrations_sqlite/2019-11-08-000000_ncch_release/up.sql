@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS ncch_release (
+    ncch_id TEXT NOT NULL REFERENCES ncch (id) ON DELETE CASCADE,
+    serial TEXT NOT NULL,
+    name TEXT NOT NULL,
+    region TEXT,
+    publisher TEXT,
+    release_date TEXT,
+    PRIMARY KEY (ncch_id, serial)
+);
+
+CREATE INDEX IF NOT EXISTS ncch_release_name ON ncch_release (name);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX ncch_signature_index;
+DROP TABLE ncch_hash;
@@ -0,0 +1,14 @@
+-- SHA-256 of the NCCH header, the whole Exheader and the SMDH, for identifying files. Header and
+-- Exheader hashes of existing rows are filled in by reprocessing them.
+CREATE TABLE IF NOT EXISTS ncch_hash (
+    ncch_id TEXT NOT NULL REFERENCES ncch (id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    hash BLOB NOT NULL,
+    PRIMARY KEY (ncch_id, kind)
+);
+
+CREATE INDEX IF NOT EXISTS ncch_hash_hash ON ncch_hash (hash);
+CREATE INDEX IF NOT EXISTS ncch_signature_index ON ncch (ncch_signature);
+
+INSERT OR IGNORE INTO ncch_hash (ncch_id, kind, hash)
+    SELECT id, 'smdh', smdh_hash FROM ncch WHERE smdh_hash IS NOT NULL;
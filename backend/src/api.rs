@@ -10,6 +10,7 @@ impl ToHttpResponse for NcchInfoResponse {
         match self {
             NcchInfoResponse::Ok(_) => HttpResponse::Ok(),
             NcchInfoResponse::NotFound => HttpResponse::NotFound(),
+            NcchInfoResponse::InvalidParam => HttpResponse::BadRequest(),
             NcchInfoResponse::InternalServerError => HttpResponse::InternalServerError(),
         }
         .json(self)
@@ -33,22 +34,110 @@ impl ToHttpResponse for PostNcchResponse {
     }
 }
 
+impl ToHttpResponse for diff::NcchDiffResponse {
+    fn http(&self) -> HttpResponse {
+        match self {
+            diff::NcchDiffResponse::Ok(_) => HttpResponse::Ok(),
+            diff::NcchDiffResponse::NotFound => HttpResponse::NotFound(),
+            diff::NcchDiffResponse::InternalServerError => HttpResponse::InternalServerError(),
+        }
+        .json(self)
+    }
+}
+
+impl ToHttpResponse for MakerResponse {
+    fn http(&self) -> HttpResponse {
+        match self {
+            MakerResponse::Ok(_) => HttpResponse::Ok(),
+            MakerResponse::InternalServerError => HttpResponse::InternalServerError(),
+        }
+        .json(self)
+    }
+}
+
+impl ToHttpResponse for NcchSimilarIconsResponse {
+    fn http(&self) -> HttpResponse {
+        match self {
+            NcchSimilarIconsResponse::Ok(_) => HttpResponse::Ok(),
+            NcchSimilarIconsResponse::NotFound => HttpResponse::NotFound(),
+            NcchSimilarIconsResponse::InternalServerError => HttpResponse::InternalServerError(),
+        }
+        .json(self)
+    }
+}
+
+impl ToHttpResponse for NcchLookupResponse {
+    fn http(&self) -> HttpResponse {
+        match self {
+            NcchLookupResponse::Ok(_) => HttpResponse::Ok(),
+            NcchLookupResponse::InvalidParam => HttpResponse::BadRequest(),
+            NcchLookupResponse::InternalServerError => HttpResponse::InternalServerError(),
+        }
+        .json(self)
+    }
+}
+
+impl ToHttpResponse for NcchLookupBatchResponse {
+    fn http(&self) -> HttpResponse {
+        match self {
+            NcchLookupBatchResponse::Ok(_) => HttpResponse::Ok(),
+            NcchLookupBatchResponse::InvalidParam => HttpResponse::BadRequest(),
+            NcchLookupBatchResponse::InternalServerError => HttpResponse::InternalServerError(),
+        }
+        .json(self)
+    }
+}
+
 impl ToHttpResponse for NcchQueryResponse {
     fn http(&self) -> HttpResponse {
         match self {
             NcchQueryResponse::Ok(_) => HttpResponse::Ok(),
+            NcchQueryResponse::InvalidQuery(_) => HttpResponse::BadRequest(),
             NcchQueryResponse::InternalServerError => HttpResponse::InternalServerError(),
         }
         .json(self)
     }
 }
 
+impl ToHttpResponse for NcchIconSheetResponse {
+    fn http(&self) -> HttpResponse {
+        match self {
+            NcchIconSheetResponse::Ok(_) => HttpResponse::Ok(),
+            NcchIconSheetResponse::InvalidQuery(_) => HttpResponse::BadRequest(),
+            NcchIconSheetResponse::InternalServerError => HttpResponse::InternalServerError(),
+        }
+        .json(self)
+    }
+}
+
 impl ToHttpResponse for NcchQueryCountResponse {
     fn http(&self) -> HttpResponse {
         match self {
             NcchQueryCountResponse::Ok(_) => HttpResponse::Ok(),
+            NcchQueryCountResponse::InvalidQuery(_) => HttpResponse::BadRequest(),
             NcchQueryCountResponse::InternalServerError => HttpResponse::InternalServerError(),
         }
         .json(self)
     }
 }
+
+impl ToHttpResponse for NcchStatsResponse {
+    fn http(&self) -> HttpResponse {
+        match self {
+            NcchStatsResponse::Ok(_) => HttpResponse::Ok(),
+            NcchStatsResponse::InvalidQuery(_) => HttpResponse::BadRequest(),
+            NcchStatsResponse::InternalServerError => HttpResponse::InternalServerError(),
+        }
+        .json(self)
+    }
+}
+
+impl ToHttpResponse for NcchExportResponse {
+    fn http(&self) -> HttpResponse {
+        match self {
+            NcchExportResponse::InvalidQuery(_) => HttpResponse::BadRequest(),
+            NcchExportResponse::InternalServerError => HttpResponse::InternalServerError(),
+        }
+        .json(self)
+    }
+}
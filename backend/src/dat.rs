@@ -0,0 +1,257 @@
+use crate::api::*;
+use crate::database::{Connection, DatabaseError, NcchHash, NcchStore};
+use crate::export::{BatchQuery, RecordBatches};
+use actix_web::web;
+use futures::{Async, Poll, Stream};
+
+const DAT_BATCH_SIZE: i64 = 500;
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn element(name: &str, attributes: &[(&str, String)]) -> String {
+    let attributes: String = attributes
+        .iter()
+        .map(|(key, value)| format!(" {}=\"{}\"", key, xml_escape(value)))
+        .collect();
+    format!("<{}{}/>", name, attributes)
+}
+
+// One rom per NCCH, named after its ID. NCCHs are not stored in full, so there is no CRC32, MD5 or
+// SHA-1 of the file, only its size. The stored SHA-256 of the header, Exheader and SMDH follow in a
+// comment, which DAT tools skip.
+fn rom(info: &NcchInfo, hashes: &[NcchHash]) -> String {
+    let extension = if info.content_is_executable {
+        "cxi"
+    } else {
+        "cfa"
+    };
+    let size = u64::from(info.content_size) << (9 + info.content_unit_size);
+    let rom = element(
+        "rom",
+        &[
+            ("name", format!("{}.{}", info.id, extension)),
+            ("size", size.to_string()),
+        ],
+    );
+    if hashes.is_empty() {
+        return rom;
+    }
+    let hashes: Vec<String> = hashes
+        .iter()
+        .map(|hash| {
+            let hex: String = hash.hash.iter().map(|b| format!("{:02x}", b)).collect();
+            format!("{} sha256=\"{}\"", hash.kind, hex)
+        })
+        .collect();
+    format!("{} <!-- {} -->", rom, hashes.join(" "))
+}
+
+// The name of a matched release, or else the English title, or else the title in any language
+fn title(info: &NcchInfo) -> Option<String> {
+    if let Some(release) = info.releases.first() {
+        return Some(release.name.clone());
+    }
+    let titles = info.long_title.as_ref()?;
+    titles
+        .get(1)
+        .filter(|t| !t.is_empty())
+        .or_else(|| titles.iter().find(|t| !t.is_empty()))
+        .map(|t| t.replace('\n', " "))
+}
+
+// One game per program ID, with the NCCHs of all partitions and versions
+struct Game {
+    title: Option<String>,
+    releases: Vec<Release>,
+    roms: Vec<String>,
+}
+
+fn game(program_id: &str, game: &Game) -> String {
+    let name = match &game.title {
+        Some(title) => format!("{} ({})", title, program_id),
+        None => program_id.to_owned(),
+    };
+    let mut text = format!("\t<game name=\"{}\">\n", xml_escape(&name));
+    text += &format!("\t\t<description>{}</description>\n", xml_escape(&name));
+    for release in &game.releases {
+        let mut attributes = vec![
+            ("name", release.name.clone()),
+            ("region", release.region.clone().unwrap_or_default()),
+        ];
+        if let Some(date) = &release.release_date {
+            attributes.push(("date", date.clone()));
+        }
+        text += &format!("\t\t{}\n", element("release", &attributes));
+    }
+    for rom in &game.roms {
+        text += &format!("\t\t{}\n", rom);
+    }
+    text + "\t</game>\n"
+}
+
+impl Game {
+    fn new() -> Game {
+        Game {
+            title: None,
+            releases: vec![],
+            roms: vec![],
+        }
+    }
+
+    fn add(&mut self, info: &NcchInfo, hashes: &[NcchHash]) {
+        if self.title.is_none() {
+            self.title = title(info);
+        }
+        for release in &info.releases {
+            if !self.releases.contains(release) {
+                self.releases.push(release.clone());
+            }
+        }
+        self.roms.push(rom(info, hashes));
+    }
+}
+
+fn header() -> String {
+    let version = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut text = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                    <!DOCTYPE datafile PUBLIC \"-//Logiqx//DTD ROM Management Datafile//EN\" \
+                    \"http://www.logiqx.com/Dats/datafile.dtd\">\n\
+                    <datafile>\n\
+                    \t<header>\n\
+                    \t\t<name>index3ds</name>\n\
+                    \t\t<description>index3ds NCCH export</description>\n"
+        .to_owned();
+    text += &format!("\t\t<version>{}</version>\n", version);
+    text += "\t\t<author>index3ds</author>\n";
+    text += "\t\t<comment>Roms have no hash of the whole file. The SHA-256 of the NCCH header, \
+             Exheader and SMDH are in a comment after each rom.</comment>\n";
+    text + "\t</header>\n"
+}
+
+// A Logiqx XML datafile of the NCCHs matching the filter, for clrmamepro and RomVault. The NCCHs
+// are fetched in program ID order, so that each game is complete when the next one starts.
+pub struct DatStream {
+    batches: RecordBatches,
+    game: Option<(String, Game)>,
+    header_sent: bool,
+    finished: bool,
+}
+
+impl DatStream {
+    pub fn new(
+        connection: Connection,
+        filter: NcchFilterParam,
+    ) -> Result<DatStream, DatabaseError> {
+        let query: BatchQuery = |connection, filter, last| {
+            let mut records = connection.query_ncch_program_batch(
+                filter,
+                last.map(|record| (record.ncch.program_id as u64, record.ncch.id.as_str())),
+                DAT_BATCH_SIZE,
+            )?;
+            connection.load_hashes(&mut records)?;
+            Ok(records)
+        };
+        Ok(DatStream {
+            batches: RecordBatches::new(connection, filter, query, DAT_BATCH_SIZE)?,
+            game: None,
+            header_sent: false,
+            finished: false,
+        })
+    }
+}
+
+impl Stream for DatStream {
+    type Item = web::Bytes;
+    type Error = actix_web::Error;
+
+    fn poll(&mut self) -> Poll<Option<web::Bytes>, actix_web::Error> {
+        let mut text = String::new();
+        if !self.header_sent {
+            self.header_sent = true;
+            text += &header();
+        }
+        // A batch can continue the same game without finishing any
+        while text.is_empty() && !self.finished {
+            match self.batches.poll_next()? {
+                Async::Ready(Some(records)) => {
+                    for record in &records {
+                        let info = record.to_ncch_info();
+                        match &mut self.game {
+                            Some((program_id, g)) if *program_id == info.program_id => {
+                                g.add(&info, &record.hashes)
+                            }
+                            current => {
+                                if let Some((program_id, g)) = current.take() {
+                                    text += &game(&program_id, &g);
+                                }
+                                let mut g = Game::new();
+                                g.add(&info, &record.hashes);
+                                *current = Some((info.program_id.clone(), g));
+                            }
+                        }
+                    }
+                }
+                Async::Ready(None) => {
+                    if let Some((program_id, g)) = self.game.take() {
+                        text += &game(&program_id, &g);
+                    }
+                    text += "</datafile>\n";
+                    self.finished = true;
+                }
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+        if text.is_empty() {
+            Ok(Async::Ready(None))
+        } else {
+            Ok(Async::Ready(Some(web::Bytes::from(text))))
+        }
+    }
+}
+
+#[test]
+fn dat_test() {
+    use crate::database::{Database, NcchRecord};
+    use index3ds_formats::{ByteStruct, NcchHeader};
+    let database = Database::connect_to("sqlite://:memory:");
+    let connection = database.get_connection().unwrap();
+    // The NCCH of the other program sorts between the two by ID
+    for &(partition_id, program_id, unit_size, signature) in &[
+        (0x0004000000123400u64, 0x0004000000123400, 0, 0),
+        (0x0004000000123401, 0x0004000000123400, 1, 0),
+        (0x0004000000123400, 0x0004000000123300, 0, 1),
+    ] {
+        let mut header = NcchHeader::read_bytes(&[0; NcchHeader::BYTE_LEN]);
+        header.partition_id = partition_id;
+        header.program_id = program_id;
+        header.content_size = 4;
+        header.content_unit_size = unit_size;
+        header.signature[0] = signature;
+        let record = NcchRecord::new(header, None, None, None);
+        connection.insert_ncch_record(&record).unwrap();
+    }
+    let stream = DatStream::new(connection, NcchFilterParam::default()).unwrap();
+    let dat: String = stream
+        .wait()
+        .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+        .collect();
+    assert_eq!(dat.matches("<game ").count(), 2);
+    assert_eq!(dat.matches("<rom ").count(), 3);
+    let first = dat.find("<game name=\"0004000000123300\">").unwrap();
+    let second = dat.find("<game name=\"0004000000123400\">").unwrap();
+    assert!(first < second);
+    assert!(dat[second..].contains("size=\"2048\""));
+    assert!(dat[second..].contains("size=\"4096\""));
+    assert_eq!(dat.matches("<!-- header sha256=\"").count(), 3);
+    assert!(dat.ends_with("</datafile>\n"));
+    assert_eq!(xml_escape("a<\"b\"&"), "a&lt;&quot;b&quot;&amp;");
+}
@@ -1,24 +1,45 @@
+use crate::api::query::{QueryError, QueryErrorKind, QueryExpr, QueryTerm};
 use crate::api::*;
-use crate::data_format::*;
 use crate::schema::*;
+use crate::sql_types::PortableVec;
+use diesel::connection::SimpleConnection;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use diesel::r2d2::*;
 use diesel::result::{DatabaseErrorKind, Error};
-use diesel::{Insertable, Queryable};
+use diesel::sqlite::SqliteConnection;
+use diesel::Connection as _;
+use diesel::{AsChangeset, BoxableExpression, Insertable, Queryable};
+use index3ds_formats::*;
 use log::{error, info, warn};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use std::env;
 
-#[derive(Queryable, Insertable, Debug)]
+mod blob;
+mod rebuild;
+mod sqlite_schema;
+#[cfg(test)]
+mod tests;
+pub use blob::{NcchBlob, NcchSections};
+pub use rebuild::Rebuilt;
+
+// SQLite's default limit of bind parameters in one statement. Longer lists passed to eq_any, and
+// rows inserted together, are split into chunks.
+const BIND_LIMIT: usize = 999;
+const RELEASE_COLUMNS: usize = 6;
+
+#[derive(Queryable, Insertable, AsChangeset, Debug, Clone)]
 #[table_name = "ncch"]
-pub struct NcchRecord {
+#[changeset_options(treat_none_as_null = "true")]
+pub struct NcchRow {
     pub id: String,
     pub ncch_signature: Vec<u8>,
     content_size: i32,
     partition_id: i64,
     maker_code: i16,
     ncch_verson: i16,
-    program_id: i64,
+    pub program_id: i64,
     product_code: Vec<u8>,
     secondary_key_slot: i16,
     platform: i16,
@@ -34,7 +55,7 @@ pub struct NcchRecord {
     exheader_name: Option<Vec<u8>>,
     sd_app: Option<bool>,
     remaster_version: Option<i16>,
-    dependencies: Option<Vec<i64>>,
+    dependencies: Option<PortableVec<i64>>,
     save_data_size: Option<i64>,
     jump_id: Option<i64>,
     exheader_program_id: Option<i64>,
@@ -46,34 +67,210 @@ pub struct NcchRecord {
     ideal_processor: Option<i16>,
     affinity_mask: Option<i16>,
     thread_priority: Option<i16>,
-    resource_limit_desc: Option<Vec<i16>>,
+    resource_limit_desc: Option<PortableVec<i16>>,
     extdata_id: Option<i64>,
     system_savedata_id0: Option<i32>,
     system_savedata_id1: Option<i32>,
     storage_access_id: Option<i64>,
     filesystem_flag: Option<i64>,
-    services: Option<Vec<Vec<u8>>>,
+    services: Option<PortableVec<Vec<u8>>>,
     resource_limit_category: Option<i16>,
-    kernel_desc: Option<Vec<i32>>,
+    kernel_desc: Option<PortableVec<i32>>,
     arm9_flag: Option<i32>,
     arm9_flag_version: Option<i16>,
 
-    short_title: Option<Vec<i16>>,
-    long_title: Option<Vec<i16>>,
-    publisher: Option<Vec<i16>>,
-    ratings: Option<Vec<i16>>,
+    ratings: Option<PortableVec<i16>>,
     region_lockout: Option<i32>,
     match_maker_id: Option<i32>,
     match_maker_bit_id: Option<i64>,
     smdh_flags: Option<i32>,
     eula_version: Option<i16>,
     cec_id: Option<i32>,
-    pub small_icon: Option<Vec<i16>>,
-    pub large_icon: Option<Vec<i16>>,
+    pub small_icon_hash: Option<String>,
+    pub large_icon_hash: Option<String>,
+    // SHA-256 of the first 0x400 bytes of the Exheader, and of the whole SMDH, as verified on
+    // upload. Used to check the sections served for download.
+    exheader_hash: Option<Vec<u8>>,
+    smdh_hash: Option<Vec<u8>>,
 
     keyword: String,
 }
 
+// One row per SMDH title language
+#[derive(Queryable, Insertable, Deserialize, Debug, Clone, PartialEq)]
+#[table_name = "ncch_title"]
+pub struct NcchTitle {
+    ncch_id: String,
+    language: i16,
+    short_title: String,
+    long_title: String,
+    publisher: String,
+}
+
+// An entry of the maker registry, mapping a maker code to the canonical company name
+#[derive(Queryable, Insertable, Debug, Clone, PartialEq)]
+#[table_name = "maker"]
+pub struct MakerRow {
+    code: i16,
+    pub name: String,
+}
+
+impl MakerRow {
+    pub fn new(code: &str, name: &str) -> Result<MakerRow, String> {
+        let code =
+            maker_code_value(code).ok_or_else(|| format!("invalid maker code \"{}\"", code))?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("empty maker name".to_owned());
+        }
+        Ok(MakerRow {
+            code,
+            name: name.to_owned(),
+        })
+    }
+
+    pub fn code(&self) -> String {
+        maker_code_string(self.code.into())
+    }
+}
+
+// An official release matched to an NCCH by the release list import
+#[derive(Queryable, Insertable, Deserialize, Debug, Clone, PartialEq)]
+#[table_name = "ncch_release"]
+pub struct ReleaseRow {
+    ncch_id: String,
+    serial: String,
+    name: String,
+    region: Option<String>,
+    publisher: Option<String>,
+    release_date: Option<String>,
+}
+
+impl ReleaseRow {
+    pub fn new(ncch_id: &str, release: Release) -> ReleaseRow {
+        ReleaseRow {
+            ncch_id: ncch_id.to_owned(),
+            serial: release.serial,
+            name: release.name,
+            region: release.region,
+            publisher: release.publisher,
+            release_date: release.release_date,
+        }
+    }
+
+    pub fn ncch_id(&self) -> &str {
+        &self.ncch_id
+    }
+
+    pub fn serial(&self) -> &str {
+        &self.serial
+    }
+
+    pub fn to_release(&self) -> Release {
+        Release {
+            serial: self.serial.clone(),
+            name: self.name.clone(),
+            region: self.region.clone(),
+            publisher: self.publisher.clone(),
+            release_date: self.release_date.clone(),
+        }
+    }
+}
+
+// SHA-256 of the whole header, Exheader or SMDH of an NCCH, for identifying dumped files. The
+// Exheader hash covers all 0x800 bytes, unlike the one in the header, which only covers the first
+// 0x400.
+#[derive(Queryable, Insertable, Debug, Clone, PartialEq)]
+#[table_name = "ncch_hash"]
+pub struct NcchHash {
+    ncch_id: String,
+    pub kind: String,
+    pub hash: Vec<u8>,
+}
+
+impl NcchHash {
+    fn new(ncch_id: &str, kind: &str, hash: Vec<u8>) -> NcchHash {
+        NcchHash {
+            ncch_id: ncch_id.to_owned(),
+            kind: kind.to_owned(),
+            hash,
+        }
+    }
+}
+
+// An SMDH icon, stored once for all NCCHs that share it
+#[derive(Queryable, Insertable, Debug, Clone)]
+#[table_name = "icon"]
+pub struct Icon {
+    // SHA-256 of data in hex
+    pub hash: String,
+    // Tiled RGB565 pixels as in the SMDH, little endian
+    pub data: Vec<u8>,
+    // Encoded on insert. Icons moved over by the migration are encoded on first request.
+    pub png: Option<Vec<u8>>,
+    // Perceptual hash from Image::dhash, for finding similar icons. Icons stored before the
+    // column was added are hashed by the reprocess command.
+    pub dhash: Option<i64>,
+}
+
+impl Icon {
+    pub fn new(pixels: &[u16]) -> Icon {
+        let mut data = Vec::with_capacity(pixels.len() * 2);
+        for pixel in pixels {
+            data.extend_from_slice(&pixel.to_le_bytes());
+        }
+        let hash = Sha256::digest(&data)
+            .iter()
+            .map(|c| format!("{:02x}", c))
+            .collect();
+        let (png, dhash) = match crate::icon::Image::decode(pixels) {
+            Ok(image) => {
+                let png = image.png().map_err(|e| error!("{}", e)).ok();
+                (png, Some(image.dhash() as i64))
+            }
+            Err(e) => {
+                error!("{}", e);
+                (None, None)
+            }
+        };
+        Icon {
+            hash,
+            data,
+            png,
+            dhash,
+        }
+    }
+
+    pub fn pixels(&self) -> Vec<u16> {
+        self.data
+            .chunks(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum IconSize {
+    Small,
+    Large,
+}
+
+// An ncch row with its titles, which are empty if the NCCH has no SMDH. Icons and blobs are only
+// loaded on their own, so they are only filled for new records that are about to be stored.
+#[derive(Debug, Clone)]
+pub struct NcchRecord {
+    pub ncch: NcchRow,
+    pub titles: Vec<NcchTitle>,
+    pub icons: Vec<Icon>,
+    pub blob: Option<NcchBlob>,
+    // From the maker registry when loaded
+    pub maker_name: Option<String>,
+    // Set by the release list import, or carried over from an export dump
+    pub releases: Vec<ReleaseRow>,
+    // Only set on upload and reprocess. Not loaded.
+    pub hashes: Vec<NcchHash>,
+}
+
 fn trim<'a, U, T: PartialEq<U>>(to_trim: &U, mut s: &'a [T]) -> &'a [T] {
     while s.last().map(|s| *s == *to_trim).unwrap_or(false) {
         s = &s[0..s.len() - 1]
@@ -100,44 +297,42 @@ fn convert_string(s: &[u8]) -> String {
     trim(&0, s).iter().map(|&x| x as char).collect()
 }
 
-fn convert_string16(s: &[u16]) -> String {
-    String::from_utf16_lossy(trim(&0, s))
-}
-
-fn generate_keyword(
-    header: &NcchHeader,
-    exheader: Option<&Exheader>,
-    smdh: Option<&Smdh>,
-) -> String {
+// Searchable text of a record, from its IDs, product code, Exheader name and titles
+fn generate_keyword(ncch: &NcchRow, titles: &[NcchTitle]) -> String {
     let mut soup = std::collections::HashSet::<String>::new();
-    soup.insert(normalize(&format!("{:016x}", header.partition_id)));
-    soup.insert(normalize(&format!("{:016x}", header.program_id)));
-    soup.insert(normalize(&convert_string(&header.product_code)));
+    soup.insert(normalize(&format!("{:016x}", ncch.partition_id as u64)));
+    soup.insert(normalize(&format!("{:016x}", ncch.program_id as u64)));
+    soup.insert(normalize(&convert_string(&ncch.product_code)));
 
-    if let Some(exheader) = exheader {
-        soup.insert(normalize(&format!(
-            "{:016x}",
-            exheader.access_control.program_id
-        )));
-        soup.insert(normalize(&convert_string(&exheader.name)));
+    if let Some(exheader_program_id) = ncch.exheader_program_id {
+        soup.insert(normalize(&format!("{:016x}", exheader_program_id as u64)));
+    }
+    if let Some(exheader_name) = &ncch.exheader_name {
+        soup.insert(normalize(&convert_string(exheader_name)));
     }
 
-    if let Some(smdh) = smdh {
-        for title in smdh.title.iter() {
-            soup.insert(normalize(&convert_string16(&title.short)));
-            soup.insert(normalize(&convert_string16(&title.long)));
-            soup.insert(normalize(&convert_string16(&title.publisher)));
-        }
+    for title in titles {
+        soup.insert(normalize(&title.short_title));
+        soup.insert(normalize(&title.long_title));
+        soup.insert(normalize(&title.publisher));
     }
 
+    join_keyword(soup)
+}
+
+fn join_keyword(soup: std::collections::HashSet<String>) -> String {
     soup.into_iter()
         .fold("".to_owned(), |b, x| format!("{}{}\n", b, x))
 }
 
 impl NcchRecord {
     #[allow(clippy::cast_lossless)]
-    pub fn new(header: NcchHeader, exheader: Option<Exheader>, smdh: Option<Smdh>) -> NcchRecord {
-        let keyword = generate_keyword(&header, exheader.as_ref(), smdh.as_ref());
+    pub fn new(
+        header: NcchHeader,
+        exheader: Option<Exheader>,
+        exefs_header: Option<ExefsHeader>,
+        smdh: Option<Smdh>,
+    ) -> NcchRecord {
         let id = format!(
             "{:016x}-{}",
             header.partition_id,
@@ -152,8 +347,11 @@ impl NcchRecord {
         );
         let exheader = exheader.as_ref();
         let smdh = smdh.as_ref();
-        NcchRecord {
-            id,
+        let blob = NcchBlob::new(&id, &header, exheader, exefs_header.as_ref(), smdh);
+        let small_icon = smdh.map(|s| Icon::new(&s.small_icon[..]));
+        let large_icon = smdh.map(|s| Icon::new(&s.large_icon[..]));
+        let mut ncch = NcchRow {
+            id: id.clone(),
             ncch_signature: header.signature[..].to_vec(),
             content_size: header.content_size as i32,
             partition_id: header.partition_id as i64,
@@ -217,27 +415,6 @@ impl NcchRecord {
             arm9_flag: exheader.map(|e| e.access_control.arm9_flag as i32),
             arm9_flag_version: exheader.map(|e| e.access_control.arm9_flag_version as i16),
 
-            short_title: smdh.map(|s| {
-                s.title
-                    .iter()
-                    .map(|t| t.short[..].iter().map(|&c| c as i16))
-                    .flatten()
-                    .collect()
-            }),
-            long_title: smdh.map(|s| {
-                s.title
-                    .iter()
-                    .map(|t| t.long[..].iter().map(|&c| c as i16))
-                    .flatten()
-                    .collect()
-            }),
-            publisher: smdh.map(|s| {
-                s.title
-                    .iter()
-                    .map(|t| t.publisher[..].iter().map(|&c| c as i16))
-                    .flatten()
-                    .collect()
-            }),
             ratings: smdh.map(|s| s.ratings.iter().map(|&r| r as i16).collect()),
             region_lockout: smdh.map(|s| s.region_lockout as i32),
             match_maker_id: smdh.map(|s| s.match_maker_id as i32),
@@ -245,23 +422,71 @@ impl NcchRecord {
             smdh_flags: smdh.map(|s| s.flags as i32),
             eula_version: smdh.map(|s| s.eula_version as i16),
             cec_id: smdh.map(|s| s.cec_id as i32),
-            small_icon: smdh.map(|s| s.small_icon[..].iter().map(|&p| p as i16).collect()),
-            large_icon: smdh.map(|s| s.large_icon[..].iter().map(|&p| p as i16).collect()),
+            small_icon_hash: small_icon.as_ref().map(|i| i.hash.clone()),
+            large_icon_hash: large_icon.as_ref().map(|i| i.hash.clone()),
+            exheader_hash: exheader.map(|_| header.exheader_hash.to_vec()),
+            smdh_hash: smdh.map(|s| Sha256::digest(&s.to_bytes()).to_vec()),
+
+            keyword: String::new(),
+        };
 
-            keyword,
+        let titles: Vec<NcchTitle> = smdh
+            .map(|s| {
+                s.title
+                    .iter()
+                    .enumerate()
+                    .map(|(language, t)| NcchTitle {
+                        ncch_id: id.clone(),
+                        language: language as i16,
+                        short_title: t.short_title(),
+                        long_title: t.long_title(),
+                        publisher: t.publisher_name(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        ncch.keyword = generate_keyword(&ncch, &titles);
+
+        let icons = small_icon.into_iter().chain(large_icon).collect();
+
+        let mut hashes = vec![NcchHash::new(
+            &id,
+            "header",
+            Sha256::digest(&header.to_bytes()).to_vec(),
+        )];
+        if let Some(exheader) = exheader {
+            hashes.push(NcchHash::new(
+                &id,
+                "exheader",
+                Sha256::digest(&exheader.to_bytes()).to_vec(),
+            ));
+        }
+        if let Some(hash) = &ncch.smdh_hash {
+            hashes.push(NcchHash::new(&id, "smdh", hash.clone()));
+        }
+
+        NcchRecord {
+            ncch,
+            titles,
+            icons,
+            blob: Some(blob),
+            maker_name: None,
+            releases: vec![],
+            hashes,
         }
     }
 
     pub fn to_ncch_info(&self) -> NcchInfo {
-        fn convert_title(title: &Option<Vec<i16>>) -> Option<Vec<String>> {
-            title.as_ref().map(|x| {
-                let x: Vec<u16> = x.iter().map(|&y| y as u16).collect();
-                let chunk = x.len() / 16;
-                x.chunks(chunk).map(convert_string16).collect()
-            })
-        }
+        let ncch = &self.ncch;
+        let titles = |field: fn(&NcchTitle) -> &String| {
+            if self.titles.is_empty() {
+                None
+            } else {
+                Some(self.titles.iter().map(|t| field(t).clone()).collect())
+            }
+        };
 
-        let maker_code = self.maker_code as u16;
+        let maker_code = ncch.maker_code as u16;
         let maker_code = [
             (maker_code & 0xFF) as u8 as char,
             (maker_code >> 8) as u8 as char,
@@ -271,96 +496,298 @@ impl NcchRecord {
 
         let service_zero_test = [0u8; 8];
 
+        let product_code = convert_string(&ncch.product_code);
+        let region_lockout = ncch.region_lockout.map(|x| x as u32);
+        let region = product_code::infer_region(&product_code, region_lockout).map(str::to_owned);
+
         NcchInfo {
-            id: self.id.clone(),
-            ncch_signature: self.ncch_signature.clone(),
-            content_size: self.content_size as u32,
-            partition_id: format!("{:016x}", self.partition_id as u64),
+            id: ncch.id.clone(),
+            ncch_signature: ncch.ncch_signature.clone(),
+            content_size: ncch.content_size as u32,
+            partition_id: format!("{:016x}", ncch.partition_id as u64),
             maker_code,
-            ncch_version: self.ncch_verson as u16,
-            program_id: format!("{:016x}", self.program_id as u64),
-            product_code: convert_string(&self.product_code),
-            secondary_key_slot: self.secondary_key_slot as u8,
-            platform: self.platform as u8,
-            content_is_data: self.content_is_data,
-            content_is_executable: self.content_is_executable,
-            content_category: self.content_category as u8,
-            content_unit_size: self.content_unit_size as u8,
-            fixed_key: self.fixed_key,
-            no_romfs: self.no_romfs,
-            no_crypto: self.no_crypto,
-            seed_crypto: self.seed_crypto,
-
-            exheader_name: self.exheader_name.as_ref().map(|s| convert_string(s)),
-            sd_app: self.sd_app,
-            remaster_version: self.remaster_version.map(|x| x as u16),
-            dependencies: self.dependencies.as_ref().map(|c| {
+            maker_name: self.maker_name.clone(),
+            ncch_version: ncch.ncch_verson as u16,
+            program_id: format!("{:016x}", ncch.program_id as u64),
+            product_code,
+            secondary_key_slot: ncch.secondary_key_slot as u8,
+            platform: ncch.platform as u8,
+            content_is_data: ncch.content_is_data,
+            content_is_executable: ncch.content_is_executable,
+            content_category: ncch.content_category as u8,
+            content_unit_size: ncch.content_unit_size as u8,
+            fixed_key: ncch.fixed_key,
+            no_romfs: ncch.no_romfs,
+            no_crypto: ncch.no_crypto,
+            seed_crypto: ncch.seed_crypto,
+
+            exheader_name: ncch.exheader_name.as_ref().map(|s| convert_string(s)),
+            sd_app: ncch.sd_app,
+            remaster_version: ncch.remaster_version.map(|x| x as u16),
+            dependencies: ncch.dependencies.as_ref().map(|c| {
                 trim(&0, c)
                     .iter()
                     .map(|&x| format!("{:016x}", x as u64))
                     .collect()
             }),
-            save_data_size: self.save_data_size.map(|x| x as u64),
-            jump_id: self.jump_id.map(|x| format!("{:016x}", x as u64)),
-            exheader_program_id: self
+            save_data_size: ncch.save_data_size.map(|x| x as u64),
+            jump_id: ncch.jump_id.map(|x| format!("{:016x}", x as u64)),
+            exheader_program_id: ncch
                 .exheader_program_id
                 .map(|x| format!("{:016x}", x as u64)),
-            core_version: self.core_version.map(|x| x as u32),
-            enable_l2_cache: self.enable_l2_cache,
-            high_cpu_speed: self.high_cpu_speed,
-            system_mode: self.system_mode.map(|x| x as u8),
-            n3ds_system_mode: self.n3ds_system_mode.map(|x| x as u8),
-            ideal_processor: self.ideal_processor.map(|x| x as u8),
-            affinity_mask: self.affinity_mask.map(|x| x as u8),
-            thread_priority: self.thread_priority.map(|x| x as u8),
-            resource_limit_desc: self
+            core_version: ncch.core_version.map(|x| x as u32),
+            enable_l2_cache: ncch.enable_l2_cache,
+            high_cpu_speed: ncch.high_cpu_speed,
+            system_mode: ncch.system_mode.map(|x| x as u8),
+            n3ds_system_mode: ncch.n3ds_system_mode.map(|x| x as u8),
+            ideal_processor: ncch.ideal_processor.map(|x| x as u8),
+            affinity_mask: ncch.affinity_mask.map(|x| x as u8),
+            thread_priority: ncch.thread_priority.map(|x| x as u8),
+            resource_limit_desc: ncch
                 .resource_limit_desc
                 .as_ref()
                 .map(|x| trim(&0, x).iter().map(|&y| y as u16).collect()),
-            extdata_id: self.extdata_id.map(|x| format!("{:016x}", x as u64)),
-            system_savedata_id0: self
+            extdata_id: ncch.extdata_id.map(|x| format!("{:016x}", x as u64)),
+            system_savedata_id0: ncch
                 .system_savedata_id0
                 .map(|x| format!("{:08x}", x as u32)),
-            system_savedata_id1: self
+            system_savedata_id1: ncch
                 .system_savedata_id1
                 .map(|x| format!("{:08x}", x as u32)),
-            storage_access_id: self.storage_access_id.map(|x| format!("{:016x}", x as u64)),
-            filesystem_flag: self.filesystem_flag.map(|x| x as u64),
-            services: self.services.as_ref().map(|x| {
+            storage_access_id: ncch.storage_access_id.map(|x| format!("{:016x}", x as u64)),
+            filesystem_flag: ncch.filesystem_flag.map(|x| x as u64),
+            services: ncch.services.as_ref().map(|x| {
                 trim(&service_zero_test, x)
                     .iter()
                     .map(|y| convert_string(&y))
                     .collect()
             }),
-            resource_limit_category: self.resource_limit_category.map(|x| x as u8),
-            kernel_desc: self
+            resource_limit_category: ncch.resource_limit_category.map(|x| x as u8),
+            kernel_desc: ncch
                 .kernel_desc
                 .as_ref()
                 .map(|x| trim(&-1, x).iter().map(|&y| y as u32).collect()),
-            arm9_flag: self.arm9_flag.map(|x| x as u32),
-            arm9_flag_version: self.arm9_flag_version.map(|x| x as u8),
+            arm9_flag: ncch.arm9_flag.map(|x| x as u32),
+            arm9_flag_version: ncch.arm9_flag_version.map(|x| x as u8),
 
-            short_title: convert_title(&self.short_title),
-            long_title: convert_title(&self.long_title),
-            publisher: convert_title(&self.publisher),
-            ratings: self
+            short_title: titles(|t| &t.short_title),
+            long_title: titles(|t| &t.long_title),
+            publisher: titles(|t| &t.publisher),
+            ratings: ncch
                 .ratings
                 .as_ref()
                 .map(|x| x.iter().map(|&y| y as u8).collect()),
-            region_lockout: self.region_lockout.map(|x| x as u32),
-            match_maker_id: self.match_maker_id.map(|x| format!("{:08x}", x as u32)),
-            match_maker_bit_id: self
+            region_lockout,
+            region,
+            match_maker_id: ncch.match_maker_id.map(|x| format!("{:08x}", x as u32)),
+            match_maker_bit_id: ncch
                 .match_maker_bit_id
                 .map(|x| format!("{:016x}", x as u64)),
-            smdh_flags: self.smdh_flags.map(|x| x as u32),
-            eula_version: self.eula_version.map(|x| x as u16),
-            cec_id: self.cec_id.map(|x| format!("{:08x}", x as u32)),
+            smdh_flags: ncch.smdh_flags.map(|x| x as u32),
+            eula_version: ncch.eula_version.map(|x| x as u16),
+            cec_id: ncch.cec_id.map(|x| format!("{:08x}", x as u32)),
+
+            releases: self.releases.iter().map(ReleaseRow::to_release).collect(),
         }
     }
+
+    // Reverses to_ncch_info for records coming from an export dump. Icons are not part of
+    // NcchInfo and are left empty.
+    #[allow(clippy::cast_lossless)]
+    pub fn from_ncch_info(info: &NcchInfo) -> Result<NcchRecord, String> {
+        fn hex64(field: &str, value: &str) -> Result<i64, String> {
+            u64::from_str_radix(value, 16)
+                .map(|x| x as i64)
+                .map_err(|_| format!("invalid {} \"{}\"", field, value))
+        }
+
+        fn hex32(field: &str, value: &str) -> Result<i32, String> {
+            u32::from_str_radix(value, 16)
+                .map(|x| x as i32)
+                .map_err(|_| format!("invalid {} \"{}\"", field, value))
+        }
+
+        fn opt_hex64(field: &str, value: &Option<String>) -> Result<Option<i64>, String> {
+            value.as_ref().map(|v| hex64(field, v)).transpose()
+        }
+
+        fn opt_hex32(field: &str, value: &Option<String>) -> Result<Option<i32>, String> {
+            value.as_ref().map(|v| hex32(field, v)).transpose()
+        }
+
+        // Pads or truncates to the length of the field in the original structure
+        fn fit<T: Clone>(mut v: Vec<T>, len: usize, fill: T) -> Vec<T> {
+            v.resize(len, fill);
+            v
+        }
+
+        fn bytes(s: &str, len: usize) -> Vec<u8> {
+            fit(s.chars().map(|c| c as u8).collect(), len, 0)
+        }
+
+        // Truncates to the number of UTF-16 units the SMDH has room for
+        fn title(s: &str, len: usize) -> String {
+            let mut units = 0;
+            s.chars()
+                .take_while(|c| {
+                    units += c.len_utf16();
+                    units <= len
+                })
+                .collect()
+        }
+
+        if info.ncch_signature.len() < 16 {
+            return Err("invalid ncch_signature".to_owned());
+        }
+        let partition_id = hex64("partition_id", &info.partition_id)?;
+        let id = format!(
+            "{:016x}-{}",
+            partition_id as u64,
+            info.ncch_signature[..16]
+                .iter()
+                .map(|c| format!("{:02x}", c))
+                .collect::<String>()
+        );
+        if id != info.id {
+            return Err(format!("ID {} does not match its content", info.id));
+        }
+
+        let maker_code: Vec<u8> = info.maker_code.chars().map(|c| c as u8).collect();
+        if maker_code.len() != 2 {
+            return Err(format!("invalid maker_code \"{}\"", info.maker_code));
+        }
+
+        let mut ncch = NcchRow {
+            id: id.clone(),
+            ncch_signature: info.ncch_signature.clone(),
+            content_size: info.content_size as i32,
+            partition_id,
+            maker_code: (maker_code[0] as u16 | (maker_code[1] as u16) << 8) as i16,
+            ncch_verson: info.ncch_version as i16,
+            program_id: hex64("program_id", &info.program_id)?,
+            product_code: bytes(&info.product_code, 16),
+            secondary_key_slot: info.secondary_key_slot as i16,
+            platform: info.platform as i16,
+            content_is_data: info.content_is_data,
+            content_is_executable: info.content_is_executable,
+            content_category: info.content_category as i16,
+            content_unit_size: info.content_unit_size as i16,
+            fixed_key: info.fixed_key,
+            no_romfs: info.no_romfs,
+            no_crypto: info.no_crypto,
+            seed_crypto: info.seed_crypto,
+
+            exheader_name: info.exheader_name.as_ref().map(|s| bytes(s, 8)),
+            sd_app: info.sd_app,
+            remaster_version: info.remaster_version.map(|x| x as i16),
+            dependencies: info
+                .dependencies
+                .as_ref()
+                .map(|d| {
+                    d.iter()
+                        .map(|x| hex64("dependencies", x))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map(|d| PortableVec(fit(d, 48, 0)))
+                })
+                .transpose()?,
+            save_data_size: info.save_data_size.map(|x| x as i64),
+            jump_id: opt_hex64("jump_id", &info.jump_id)?,
+            exheader_program_id: opt_hex64("exheader_program_id", &info.exheader_program_id)?,
+            core_version: info.core_version.map(|x| x as i32),
+            enable_l2_cache: info.enable_l2_cache,
+            high_cpu_speed: info.high_cpu_speed,
+            system_mode: info.system_mode.map(|x| x as i16),
+            n3ds_system_mode: info.n3ds_system_mode.map(|x| x as i16),
+            ideal_processor: info.ideal_processor.map(|x| x as i16),
+            affinity_mask: info.affinity_mask.map(|x| x as i16),
+            thread_priority: info.thread_priority.map(|x| x as i16),
+            resource_limit_desc: info
+                .resource_limit_desc
+                .as_ref()
+                .map(|x| PortableVec(fit(x.iter().map(|&y| y as i16).collect(), 16, 0))),
+            extdata_id: opt_hex64("extdata_id", &info.extdata_id)?,
+            system_savedata_id0: opt_hex32("system_savedata_id0", &info.system_savedata_id0)?,
+            system_savedata_id1: opt_hex32("system_savedata_id1", &info.system_savedata_id1)?,
+            storage_access_id: opt_hex64("storage_access_id", &info.storage_access_id)?,
+            filesystem_flag: info.filesystem_flag.map(|x| x as i64),
+            services: info
+                .services
+                .as_ref()
+                .map(|x| PortableVec(fit(x.iter().map(|y| bytes(y, 8)).collect(), 34, vec![0; 8]))),
+            resource_limit_category: info.resource_limit_category.map(|x| x as i16),
+            kernel_desc: info
+                .kernel_desc
+                .as_ref()
+                .map(|x| PortableVec(fit(x.iter().map(|&y| y as i32).collect(), 28, -1))),
+            arm9_flag: info.arm9_flag.map(|x| x as i32),
+            arm9_flag_version: info.arm9_flag_version.map(|x| x as i16),
+
+            ratings: info
+                .ratings
+                .as_ref()
+                .map(|x| x.iter().map(|&y| y as i16).collect()),
+            region_lockout: info.region_lockout.map(|x| x as i32),
+            match_maker_id: opt_hex32("match_maker_id", &info.match_maker_id)?,
+            match_maker_bit_id: opt_hex64("match_maker_bit_id", &info.match_maker_bit_id)?,
+            smdh_flags: info.smdh_flags.map(|x| x as i32),
+            eula_version: info.eula_version.map(|x| x as i16),
+            cec_id: opt_hex32("cec_id", &info.cec_id)?,
+            small_icon_hash: None,
+            large_icon_hash: None,
+            exheader_hash: None,
+            smdh_hash: None,
+
+            keyword: String::new(),
+        };
+
+        let mut titles = vec![];
+        if let (Some(short_title), Some(long_title), Some(publisher)) =
+            (&info.short_title, &info.long_title, &info.publisher)
+        {
+            let count = short_title.len().max(long_title.len()).max(publisher.len());
+            for language in 0..count.min(16) {
+                let get = |v: &Vec<String>, len| title(v.get(language).map_or("", |s| s), len);
+                titles.push(NcchTitle {
+                    ncch_id: id.clone(),
+                    language: language as i16,
+                    short_title: get(short_title, 0x40),
+                    long_title: get(long_title, 0x80),
+                    publisher: get(publisher, 0x40),
+                });
+            }
+        }
+        ncch.keyword = generate_keyword(&ncch, &titles);
+
+        let releases = info
+            .releases
+            .iter()
+            .map(|release| ReleaseRow::new(&id, release.clone()))
+            .collect();
+
+        Ok(NcchRecord {
+            ncch,
+            titles,
+            icons: vec![],
+            blob: None,
+            maker_name: None,
+            releases,
+            hashes: vec![],
+        })
+    }
+}
+
+enum DatabasePool {
+    Postgres(Pool<ConnectionManager<PgConnection>>),
+    Sqlite(Pool<ConnectionManager<SqliteConnection>>),
+}
+
+enum PooledBackend {
+    Postgres(PooledConnection<ConnectionManager<PgConnection>>),
+    Sqlite(PooledConnection<ConnectionManager<SqliteConnection>>),
 }
 
 pub struct Database {
-    pool: Pool<ConnectionManager<PgConnection>>,
+    pool: DatabasePool,
 }
 
 impl std::fmt::Debug for Database {
@@ -369,29 +796,184 @@ impl std::fmt::Debug for Database {
     }
 }
 
+// Registers the functions SQLite lacks on every new connection
+#[derive(Debug)]
+struct SqliteFunctions;
+
+impl CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for SqliteFunctions {
+    fn on_acquire(&self, connection: &mut SqliteConnection) -> Result<(), diesel::r2d2::Error> {
+        hamming_distance::register_impl(connection, |a: i64, b: i64| (a ^ b).count_ones() as i32)
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
 impl Database {
     pub fn connect() -> Database {
-        let manager =
-            ConnectionManager::new(env::var("DATABASE_URL").expect("DATABASE_URL must be set"));
-        let pool = Pool::builder()
-            .max_size(10)
-            .build(manager)
-            .expect("Failed to build the connection pool");
-        Database { pool }
+        Database::connect_to(&env::var("DATABASE_URL").expect("DATABASE_URL must be set"))
+    }
+
+    // postgres://... connects to a Postgres server. sqlite://path, or sqlite://:memory: for a
+    // throwaway database, opens an SQLite file and creates the tables if needed.
+    pub fn connect_to(url: &str) -> Database {
+        if url.starts_with("sqlite://") {
+            let path = &url["sqlite://".len()..];
+            let mut builder = Pool::builder().max_size(10);
+            if path == ":memory:" {
+                // Every connection to :memory: is a separate database, so keep exactly one alive.
+                builder = builder.max_size(1).idle_timeout(None).max_lifetime(None);
+            }
+            let pool = builder
+                .connection_customizer(Box::new(SqliteFunctions))
+                .build(ConnectionManager::<SqliteConnection>::new(path))
+                .expect("Failed to build the connection pool");
+            let connection = pool
+                .get()
+                .expect("Failed to connect to the SQLite database");
+            sqlite_schema::migrate(&connection).expect("Failed to create the SQLite schema");
+            Database {
+                pool: DatabasePool::Sqlite(pool),
+            }
+        } else {
+            let pool = Pool::builder()
+                .max_size(10)
+                .build(ConnectionManager::<PgConnection>::new(url))
+                .expect("Failed to build the connection pool");
+            Database {
+                pool: DatabasePool::Postgres(pool),
+            }
+        }
     }
 
     pub fn get_connection(&self) -> Result<Connection, Box<dyn std::error::Error>> {
-        Ok(self
-            .pool
-            .get()
-            .map(|connection| Connection { connection })?)
+        let connection = match &self.pool {
+            DatabasePool::Postgres(pool) => PooledBackend::Postgres(pool.get()?),
+            DatabasePool::Sqlite(pool) => PooledBackend::Sqlite(pool.get()?),
+        };
+        Ok(Connection { connection })
+    }
+
+    fn connection(&self) -> Result<Connection, DatabaseError> {
+        self.get_connection().map_err(|e| {
+            error!("failed to get database connection: {}", e);
+            DatabaseError::Other
+        })
+    }
+}
+
+// Takes a connection from the pool for every operation.
+impl NcchStore for Database {
+    fn insert_ncch_record(&self, record: &NcchRecord) -> Result<(), DatabaseError> {
+        self.connection()?.insert_ncch_record(record)
+    }
+
+    fn update_ncch_record(&self, record: &NcchRecord) -> Result<(), DatabaseError> {
+        self.connection()?.update_ncch_record(record)
+    }
+
+    fn get_ncch_record(&self, id: &str) -> Result<NcchRecord, DatabaseError> {
+        self.connection()?.get_ncch_record(id)
+    }
+
+    fn get_ncch_icon(&self, id: &str, size: IconSize) -> Result<Icon, DatabaseError> {
+        self.connection()?.get_ncch_icon(id, size)
+    }
+
+    fn get_icons(&self, hashes: &[String]) -> Result<Vec<Icon>, DatabaseError> {
+        self.connection()?.get_icons(hashes)
+    }
+
+    fn get_ncch_blob(&self, id: &str) -> Result<NcchBlob, DatabaseError> {
+        self.connection()?.get_ncch_blob(id)
+    }
+
+    fn query_ncch_blobs(
+        &self,
+        after: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<NcchBlob>, DatabaseError> {
+        self.connection()?.query_ncch_blobs(after, limit)
+    }
+
+    fn query_similar_icons(
+        &self,
+        id: &str,
+        max_distance: u32,
+        limit: i64,
+    ) -> Result<Vec<(NcchRecord, u32)>, DatabaseError> {
+        self.connection()?
+            .query_similar_icons(id, max_distance, limit)
+    }
+
+    fn lookup_hash(&self, hash: &[u8]) -> Result<Vec<(NcchRecord, String)>, DatabaseError> {
+        self.connection()?.lookup_hash(hash)
+    }
+
+    fn load_hashes(&self, records: &mut [NcchRecord]) -> Result<(), DatabaseError> {
+        self.connection()?.load_hashes(records)
+    }
+
+    fn query_ncch_by_ids(
+        &self,
+        program_ids: &[u64],
+        ids: &[String],
+    ) -> Result<Vec<NcchRecord>, DatabaseError> {
+        self.connection()?.query_ncch_by_ids(program_ids, ids)
+    }
+
+    fn query_ncch(&self, param: &NcchQueryParam) -> Result<Vec<NcchRecord>, DatabaseError> {
+        self.connection()?.query_ncch(param)
+    }
+
+    fn query_ncch_batch(
+        &self,
+        filter: &NcchFilterParam,
+        after: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<NcchRecord>, DatabaseError> {
+        self.connection()?.query_ncch_batch(filter, after, limit)
+    }
+
+    fn query_ncch_program_batch(
+        &self,
+        filter: &NcchFilterParam,
+        after: Option<(u64, &str)>,
+        limit: i64,
+    ) -> Result<Vec<NcchRecord>, DatabaseError> {
+        self.connection()?
+            .query_ncch_program_batch(filter, after, limit)
+    }
+
+    fn query_ncch_facets(&self, param: &NcchFilterParam) -> Result<NcchFacets, DatabaseError> {
+        self.connection()?.query_ncch_facets(param)
+    }
+
+    fn query_ncch_stats(&self, param: &NcchFilterParam) -> Result<NcchStats, DatabaseError> {
+        self.connection()?.query_ncch_stats(param)
+    }
+
+    fn query_ncch_count(&self, param: &NcchFilterParam) -> Result<i64, DatabaseError> {
+        self.connection()?.query_ncch_count(param)
+    }
+
+    fn replace_makers(&self, makers: &[MakerRow]) -> Result<(), DatabaseError> {
+        self.connection()?.replace_makers(makers)
+    }
+
+    fn query_makers(&self) -> Result<Vec<Maker>, DatabaseError> {
+        self.connection()?.query_makers()
+    }
+
+    fn replace_releases(&self, releases: &[ReleaseRow]) -> Result<(), DatabaseError> {
+        self.connection()?.replace_releases(releases)
     }
 }
 
+#[derive(Debug)]
 pub enum DatabaseError {
     Conflict,
     NotFound,
     InvalidParam,
+    InvalidQuery(QueryError),
     Other,
 }
 
@@ -435,384 +1017,1407 @@ impl DatabaseTypeAdaptor for bool {
     }
 }
 
-fn filter_comparator<'a, T, U>(
-    statement: ncch::BoxedQuery<'a, diesel::pg::Pg>,
-    comparator: &Option<Comparator>,
-    rhs: &Option<StringWrapper<T>>,
-    field: U,
-) -> Result<ncch::BoxedQuery<'a, diesel::pg::Pg>, DatabaseError>
-where
-    T: DatabaseTypeAdaptor + std::string::ToString + std::str::FromStr,
-    U: 'a
-        + diesel::expression_methods::ExpressionMethods
-        + diesel::AppearsOnTable<ncch::table>
-        + diesel::expression::NonAggregate
-        + diesel::query_builder::QueryFragment<diesel::pg::Pg>,
-    T::DatabaseType: diesel::expression::AsExpression<U::SqlType>,
-    <T::DatabaseType as diesel::expression::AsExpression<U::SqlType>>::Expression:
-        'a
-            + diesel::AppearsOnTable<ncch::table>
-            + diesel::expression::NonAggregate
-            + diesel::query_builder::QueryFragment<diesel::pg::Pg>,
-{
-    if let Some(cmp) = comparator {
-        let rhs = rhs
-            .as_ref()
-            .and_then(|v| v.value())
-            .ok_or(DatabaseError::InvalidParam)?
-            .cast();
-        Ok(match cmp {
-            Comparator::Eq => statement.filter(field.eq(rhs)),
-            Comparator::Ne => statement.filter(field.ne(rhs)),
-            Comparator::Lt => statement.filter(field.lt(rhs)),
-            Comparator::Le => statement.filter(field.le(rhs)),
-            Comparator::Gt => statement.filter(field.gt(rhs)),
-            Comparator::Ge => statement.filter(field.ge(rhs)),
-        })
-    } else {
-        Ok(statement)
-    }
-}
-
-fn filter_eq<'a, T, U>(
-    statement: ncch::BoxedQuery<'a, diesel::pg::Pg>,
-    rhs: &Option<StringWrapper<T>>,
-    field: U,
-) -> Result<ncch::BoxedQuery<'a, diesel::pg::Pg>, DatabaseError>
-where
-    T: DatabaseTypeAdaptor + std::string::ToString + std::str::FromStr,
-    U: 'a
-        + diesel::expression_methods::ExpressionMethods
-        + diesel::AppearsOnTable<ncch::table>
-        + diesel::expression::NonAggregate
-        + diesel::query_builder::QueryFragment<diesel::pg::Pg>,
-    T::DatabaseType: diesel::expression::AsExpression<U::SqlType>,
-    <T::DatabaseType as diesel::expression::AsExpression<U::SqlType>>::Expression:
-        'a
-            + diesel::AppearsOnTable<ncch::table>
-            + diesel::expression::NonAggregate
-            + diesel::query_builder::QueryFragment<diesel::pg::Pg>,
-{
-    if let Some(rhs) = rhs {
-        let rhs = rhs.value().ok_or(DatabaseError::InvalidParam)?.cast();
-        Ok(statement.filter(field.eq(rhs)))
-    } else {
-        Ok(statement)
-    }
-}
-
-fn filter_id<'a, U: diesel::query_source::Column>(
-    statement: ncch::BoxedQuery<'a, diesel::pg::Pg>,
-    id: &Option<String>,
-    mask: &Option<String>,
-    _: U,
-) -> Result<ncch::BoxedQuery<'a, diesel::pg::Pg>, DatabaseError> {
-    if let Some(id) = id {
-        let id = u64::from_str_radix(&id, 16).map_err(|_| DatabaseError::InvalidParam)? as i64;
-        let mask = mask.as_ref().ok_or(DatabaseError::InvalidParam)?;
-        let mask = u64::from_str_radix(&mask, 16).map_err(|_| DatabaseError::InvalidParam)? as i64;
-        Ok(statement.filter(diesel::dsl::sql(&format!(
-            "{} IS NOT NULL AND {} & {} = {}",
-            U::NAME,
-            U::NAME,
-            mask,
-            id
-        ))))
-    } else {
-        Ok(statement)
-    }
-}
-
-fn filter_flag<'a, T, U>(
-    statement: ncch::BoxedQuery<'a, diesel::pg::Pg>,
-    flag: &Option<StringWrapper<T>>,
-    mask: &Option<StringWrapper<T>>,
-    _: U,
-) -> Result<ncch::BoxedQuery<'a, diesel::pg::Pg>, DatabaseError>
-where
-    U: diesel::query_source::Column,
-    T: DatabaseTypeAdaptor + std::string::ToString + std::str::FromStr,
-    T::DatabaseType: std::fmt::Display,
-{
-    if let Some(flag) = flag {
-        //let id = u64::from_str_radix(&id, 16).map_err(|_| DatabaseError::InvalidParam)? as i64;
-        let flag = flag.value().ok_or(DatabaseError::InvalidParam)?.cast();
-        let mask = mask.as_ref().ok_or(DatabaseError::InvalidParam)?;
-        let mask = mask.value().ok_or(DatabaseError::InvalidParam)?.cast();
-        Ok(statement.filter(diesel::dsl::sql(&format!(
-            "{} IS NOT NULL AND {} & {} = {}",
-            U::NAME,
-            U::NAME,
-            mask,
-            flag
-        ))))
-    } else {
-        Ok(statement)
-    }
-}
-
-fn filter_ncch(
-    param: &NcchFilterParam,
-) -> Result<ncch::BoxedQuery<'_, diesel::pg::Pg>, DatabaseError> {
-    let mut statement = Box::new(ncch::table).into_boxed();
-    if let Some(keyword) = &param.keyword {
-        let keyword_matcher = format!(
-            "%{}%",
-            normalize(keyword)
-                .replace('\\', "\\\\")
-                .replace('%', "\\%")
-                .replace('_', "\\_")
-        );
-        statement = statement.filter(ncch::keyword.like(keyword_matcher).escape('\\'));
-    }
+mod functions {
+    use diesel::sql_types::{BigInt, Integer, Text};
+    sql_function!(fn lower(x: Text) -> Text);
+    // Number of differing bits. Registered on every SQLite connection, see SqliteFunctions.
+    sql_function!(fn hamming_distance(a: BigInt, b: BigInt) -> Integer);
+}
+use functions::{hamming_distance, lower};
+
+fn like_pattern(text: &str) -> String {
+    format!(
+        "%{}%",
+        text.replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    )
+}
 
-    statement = filter_comparator(
-        statement,
-        &param.content_size_cmp,
-        &param.content_size_rhs,
-        ncch::content_size,
-    )?;
+// Product code patterns use * and ? as wildcards. Codes are upper case, and so is the pattern, to
+// match the same way on both backends.
+fn wildcard_pattern(pattern: &str) -> String {
+    pattern
+        .to_uppercase()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+        .replace('*', "%")
+        .replace('?', "_")
+}
 
-    statement = filter_id(
-        statement,
-        &param.partition_id,
-        &param.partition_id_mask,
-        ncch::partition_id,
-    )?;
+fn keyword_matcher(keyword: &str) -> String {
+    like_pattern(&normalize(keyword))
+}
 
-    if let Some(maker_code) = &param.maker_code {
-        if maker_code.len() != 2 {
-            return Err(DatabaseError::InvalidParam);
+// Query builders shared by all backends. Each backend module defines `Backend` and
+// `service_condition`, the only filter that needs backend specific SQL, before expanding this.
+macro_rules! ncch_query_builder {
+    () => {
+        fn filter_comparator<'a, T, U>(
+            statement: ncch::BoxedQuery<'a, Backend>,
+            comparator: &Option<Comparator>,
+            rhs: &Option<StringWrapper<T>>,
+            field: U,
+        ) -> Result<ncch::BoxedQuery<'a, Backend>, DatabaseError>
+        where
+            T: DatabaseTypeAdaptor + std::string::ToString + std::str::FromStr,
+            U: 'a
+                + diesel::expression_methods::ExpressionMethods
+                + diesel::AppearsOnTable<ncch::table>
+                + diesel::expression::NonAggregate
+                + diesel::query_builder::QueryFragment<Backend>,
+            T::DatabaseType: diesel::expression::AsExpression<U::SqlType>,
+            <T::DatabaseType as diesel::expression::AsExpression<U::SqlType>>::Expression:
+                'a
+                    + diesel::AppearsOnTable<ncch::table>
+                    + diesel::expression::NonAggregate
+                    + diesel::query_builder::QueryFragment<Backend>,
+        {
+            if let Some(cmp) = comparator {
+                let rhs = rhs
+                    .as_ref()
+                    .and_then(|v| v.value())
+                    .ok_or(DatabaseError::InvalidParam)?
+                    .cast();
+                Ok(match cmp {
+                    Comparator::Eq => statement.filter(field.eq(rhs)),
+                    Comparator::Ne => statement.filter(field.ne(rhs)),
+                    Comparator::Lt => statement.filter(field.lt(rhs)),
+                    Comparator::Le => statement.filter(field.le(rhs)),
+                    Comparator::Gt => statement.filter(field.gt(rhs)),
+                    Comparator::Ge => statement.filter(field.ge(rhs)),
+                })
+            } else {
+                Ok(statement)
+            }
         }
-        let maker_code =
-            (maker_code.as_bytes()[0] as u16 + ((maker_code.as_bytes()[1] as u16) << 8)) as i16;
-        statement = statement.filter(ncch::maker_code.eq(maker_code));
-    }
 
-    statement = filter_comparator(
-        statement,
-        &param.ncch_version_cmp,
-        &param.ncch_version_rhs,
-        ncch::ncch_verson,
-    )?;
+        fn filter_eq<'a, T, U>(
+            statement: ncch::BoxedQuery<'a, Backend>,
+            rhs: &Option<StringWrapper<T>>,
+            field: U,
+        ) -> Result<ncch::BoxedQuery<'a, Backend>, DatabaseError>
+        where
+            T: DatabaseTypeAdaptor + std::string::ToString + std::str::FromStr,
+            U: 'a
+                + diesel::expression_methods::ExpressionMethods
+                + diesel::AppearsOnTable<ncch::table>
+                + diesel::expression::NonAggregate
+                + diesel::query_builder::QueryFragment<Backend>,
+            T::DatabaseType: diesel::expression::AsExpression<U::SqlType>,
+            <T::DatabaseType as diesel::expression::AsExpression<U::SqlType>>::Expression:
+                'a
+                    + diesel::AppearsOnTable<ncch::table>
+                    + diesel::expression::NonAggregate
+                    + diesel::query_builder::QueryFragment<Backend>,
+        {
+            if let Some(rhs) = rhs {
+                let rhs = rhs.value().ok_or(DatabaseError::InvalidParam)?.cast();
+                Ok(statement.filter(field.eq(rhs)))
+            } else {
+                Ok(statement)
+            }
+        }
 
-    statement = filter_id(
-        statement,
-        &param.program_id,
-        &param.program_id_mask,
-        ncch::program_id,
-    )?;
+        fn filter_id<'a, U: diesel::query_source::Column>(
+            statement: ncch::BoxedQuery<'a, Backend>,
+            id: &Option<String>,
+            mask: &Option<String>,
+            _: U,
+        ) -> Result<ncch::BoxedQuery<'a, Backend>, DatabaseError> {
+            if let Some(id) = id {
+                let id =
+                    u64::from_str_radix(&id, 16).map_err(|_| DatabaseError::InvalidParam)? as i64;
+                let mask = mask.as_ref().ok_or(DatabaseError::InvalidParam)?;
+                let mask =
+                    u64::from_str_radix(&mask, 16).map_err(|_| DatabaseError::InvalidParam)? as i64;
+                Ok(statement.filter(diesel::dsl::sql(&format!(
+                    "{} IS NOT NULL AND {} & {} = {}",
+                    U::NAME,
+                    U::NAME,
+                    mask,
+                    id
+                ))))
+            } else {
+                Ok(statement)
+            }
+        }
 
-    if let Some(product_code) = &param.product_code {
-        if product_code.len() > 16 {
-            return Err(DatabaseError::InvalidParam);
+        fn filter_flag<'a, T, U>(
+            statement: ncch::BoxedQuery<'a, Backend>,
+            flag: &Option<StringWrapper<T>>,
+            mask: &Option<StringWrapper<T>>,
+            _: U,
+        ) -> Result<ncch::BoxedQuery<'a, Backend>, DatabaseError>
+        where
+            U: diesel::query_source::Column,
+            T: DatabaseTypeAdaptor + std::string::ToString + std::str::FromStr,
+            T::DatabaseType: std::fmt::Display,
+        {
+            if let Some(flag) = flag {
+                //let id = u64::from_str_radix(&id, 16).map_err(|_| DatabaseError::InvalidParam)? as i64;
+                let flag = flag.value().ok_or(DatabaseError::InvalidParam)?.cast();
+                let mask = mask.as_ref().ok_or(DatabaseError::InvalidParam)?;
+                let mask = mask.value().ok_or(DatabaseError::InvalidParam)?.cast();
+                Ok(statement.filter(diesel::dsl::sql(&format!(
+                    "{} IS NOT NULL AND {} & {} = {}",
+                    U::NAME,
+                    U::NAME,
+                    mask,
+                    flag
+                ))))
+            } else {
+                Ok(statement)
+            }
         }
-        let mut product_code = product_code.as_bytes().to_vec();
-        while product_code.len() < 16 {
-            product_code.push(0);
+
+        type BoxedCondition<'a> = Box<
+            dyn BoxableExpression<ncch::table, Backend, SqlType = diesel::sql_types::Bool> + 'a,
+        >;
+
+        fn compare<'a, U, V>(field: U, comparator: &Comparator, rhs: V) -> BoxedCondition<'a>
+        where
+            U: 'a
+                + diesel::expression_methods::ExpressionMethods
+                + diesel::SelectableExpression<ncch::table>
+                + diesel::expression::NonAggregate
+                + diesel::query_builder::QueryFragment<Backend>,
+            V: diesel::expression::AsExpression<U::SqlType>,
+            V::Expression: 'a
+                + diesel::SelectableExpression<ncch::table>
+                + diesel::expression::NonAggregate
+                + diesel::query_builder::QueryFragment<Backend>,
+        {
+            match comparator {
+                Comparator::Eq => Box::new(field.eq(rhs)),
+                Comparator::Ne => Box::new(field.ne(rhs)),
+                Comparator::Lt => Box::new(field.lt(rhs)),
+                Comparator::Le => Box::new(field.le(rhs)),
+                Comparator::Gt => Box::new(field.gt(rhs)),
+                Comparator::Ge => Box::new(field.ge(rhs)),
+            }
+        }
+
+        // Release names are not part of the keyword, which is generated from the NCCH alone. They
+        // are searched with the release key, as no index helps the LIKE on them.
+        fn keyword_condition<'a>(keyword: &str) -> BoxedCondition<'a> {
+            Box::new(
+                ncch::keyword
+                    .like(keyword_matcher(keyword))
+                    .escape('\\'),
+            )
         }
-        statement = statement.filter(ncch::product_code.eq(product_code));
+
+        // Makers whose name contains the text in any case
+        fn maker_name_condition<'a>(name: &str) -> BoxedCondition<'a> {
+            Box::new(
+                ncch::maker_code.eq_any(
+                    maker::table.select(maker::code).filter(
+                        lower(maker::name)
+                            .like(like_pattern(&name.to_lowercase()))
+                            .escape('\\'),
+                    ),
+                ),
+            )
+        }
+
+        fn release_condition<'a>(column: &str, pattern: String) -> BoxedCondition<'a> {
+            Box::new(
+                diesel::dsl::sql::<diesel::sql_types::Bool>(&format!(
+                    "ncch.id IN (SELECT ncch_id FROM ncch_release WHERE {} LIKE ",
+                    column
+                ))
+                .bind::<diesel::sql_types::Text, _>(pattern)
+                .sql(" ESCAPE '\\')"),
+            )
+        }
+
+        fn query_condition<'a>(expr: &QueryExpr) -> Result<BoxedCondition<'a>, QueryError> {
+            Ok(match expr {
+                QueryExpr::And(a, b) => Box::new(query_condition(a)?.and(query_condition(b)?)),
+                QueryExpr::Or(a, b) => Box::new(query_condition(a)?.or(query_condition(b)?)),
+                QueryExpr::Not(a) => Box::new(diesel::dsl::not(query_condition(a)?)),
+                QueryExpr::Term(term) => query_term(term)?,
+            })
+        }
+
+        fn query_term<'a>(term: &QueryTerm) -> Result<BoxedCondition<'a>, QueryError> {
+            let field = if let Some(field) = &term.field {
+                field.as_str()
+            } else {
+                return Ok(keyword_condition(&term.value));
+            };
+
+            let invalid = || {
+                QueryError::new(
+                    term.position,
+                    QueryErrorKind::InvalidValue {
+                        field: field.to_owned(),
+                        value: term.value.clone(),
+                    },
+                )
+            };
+
+            // Fields that only make sense to be tested for (in)equality.
+            // Returns whether the condition should be negated.
+            let eq_only = || match term.comparator {
+                Comparator::Eq => Ok(false),
+                Comparator::Ne => Ok(true),
+                _ => Err(QueryError::new(
+                    term.position,
+                    QueryErrorKind::UnsupportedComparator {
+                        field: field.to_owned(),
+                    },
+                )),
+            };
+
+            fn number<T: DatabaseTypeAdaptor + std::str::FromStr>(
+                value: &str,
+            ) -> Option<T::DatabaseType> {
+                value.parse::<T>().ok().map(T::cast)
+            }
+
+            fn id(value: &str) -> Option<i64> {
+                u64::from_str_radix(value, 16).ok().map(|x| x as i64)
+            }
+
+            fn padded(value: &str, len: usize) -> Option<Vec<u8>> {
+                if value.len() > len {
+                    return None;
+                }
+                let mut value = value.as_bytes().to_vec();
+                value.resize(len, 0);
+                Some(value)
+            }
+
+            let value = term.value.as_str();
+            let cmp = &term.comparator;
+
+            // short_title, long_title and publisher match any language, or one language with a
+            // suffix such as long_title.ko
+            let (column, language) = match field.find('.') {
+                Some(i) => (&field[..i], Some(&field[i + 1..])),
+                None => (field, None),
+            };
+            if let "short_title" | "long_title" | "publisher" = column {
+                let language = match language {
+                    Some(language) => Some(title_language(language).ok_or_else(|| {
+                        QueryError::new(
+                            term.position,
+                            QueryErrorKind::UnknownField {
+                                field: field.to_owned(),
+                            },
+                        )
+                    })?),
+                    None => None,
+                };
+                let negate = eq_only()?;
+                let language = language
+                    .map(|language| format!(" AND ncch_title.language = {}", language))
+                    .unwrap_or_default();
+                let condition: BoxedCondition<'a> = Box::new(
+                    diesel::dsl::sql::<diesel::sql_types::Bool>(&format!(
+                        "EXISTS (SELECT 1 FROM ncch_title WHERE ncch_title.ncch_id = ncch.id{} \
+                         AND LOWER(ncch_title.{}) LIKE ",
+                        language, column
+                    ))
+                    .bind::<diesel::sql_types::Text, _>(like_pattern(&value.to_lowercase()))
+                    .sql(" ESCAPE '\\')"),
+                );
+                return Ok(if negate {
+                    Box::new(diesel::dsl::not(condition))
+                } else {
+                    condition
+                });
+            }
+
+            let condition: BoxedCondition<'a> = match field {
+                "title" | "keyword" => {
+                    let negate = eq_only()?;
+                    let condition: BoxedCondition<'a> =
+                        Box::new(ncch::keyword.like(keyword_matcher(value)).escape('\\'));
+                    return Ok(if negate {
+                        Box::new(diesel::dsl::not(condition))
+                    } else {
+                        condition
+                    });
+                }
+                // Matches the release name, or the serial with the same wildcards as product_code
+                "release" => {
+                    let negate = eq_only()?;
+                    let condition: BoxedCondition<'a> = Box::new(
+                        release_condition("LOWER(name)", keyword_matcher(value))
+                            .or(release_condition("UPPER(serial)", wildcard_pattern(value))),
+                    );
+                    return Ok(if negate {
+                        Box::new(diesel::dsl::not(condition))
+                    } else {
+                        condition
+                    });
+                }
+                "maker" => {
+                    eq_only()?;
+                    if value.len() != 2 {
+                        return Err(invalid());
+                    }
+                    let maker_code =
+                        (value.as_bytes()[0] as u16 + ((value.as_bytes()[1] as u16) << 8)) as i16;
+                    compare(ncch::maker_code, cmp, maker_code)
+                }
+                "maker_name" => {
+                    let negate = eq_only()?;
+                    let condition = maker_name_condition(value);
+                    return Ok(if negate {
+                        Box::new(diesel::dsl::not(condition))
+                    } else {
+                        condition
+                    });
+                }
+                "service" => {
+                    let negate = eq_only()?;
+                    let service = padded(value, 8).ok_or_else(invalid)?;
+                    let condition = service_condition(service);
+                    return Ok(if negate {
+                        Box::new(diesel::dsl::not(condition))
+                    } else {
+                        condition
+                    });
+                }
+                "product_code" if product_code::is_wildcard(value) => {
+                    let negate = eq_only()?;
+                    let condition = product_code_condition(wildcard_pattern(value));
+                    return Ok(if negate {
+                        Box::new(diesel::dsl::not(condition))
+                    } else {
+                        condition
+                    });
+                }
+                "product_code" => {
+                    eq_only()?;
+                    let product_code = padded(value, 16).ok_or_else(invalid)?;
+                    compare(ncch::product_code, cmp, product_code)
+                }
+                "name" => {
+                    eq_only()?;
+                    let name = padded(value, 8).ok_or_else(invalid)?;
+                    compare(ncch::exheader_name, cmp, name)
+                }
+                "partition_id" => compare(ncch::partition_id, cmp, id(value).ok_or_else(invalid)?),
+                "program_id" => compare(ncch::program_id, cmp, id(value).ok_or_else(invalid)?),
+                "title_id" => compare(
+                    ncch::exheader_program_id,
+                    cmp,
+                    id(value).ok_or_else(invalid)?,
+                ),
+                "jump_id" => compare(ncch::jump_id, cmp, id(value).ok_or_else(invalid)?),
+                "content_size" => compare(
+                    ncch::content_size,
+                    cmp,
+                    number::<u32>(value).ok_or_else(invalid)?,
+                ),
+                "ncch_version" => compare(
+                    ncch::ncch_verson,
+                    cmp,
+                    number::<u16>(value).ok_or_else(invalid)?,
+                ),
+                "platform" => compare(
+                    ncch::platform,
+                    cmp,
+                    number::<u8>(value).ok_or_else(invalid)?,
+                ),
+                "category" => compare(
+                    ncch::content_category,
+                    cmp,
+                    number::<u8>(value).ok_or_else(invalid)?,
+                ),
+                "data" => compare(
+                    ncch::content_is_data,
+                    cmp,
+                    number::<bool>(value).ok_or_else(invalid)?,
+                ),
+                "executable" => compare(
+                    ncch::content_is_executable,
+                    cmp,
+                    number::<bool>(value).ok_or_else(invalid)?,
+                ),
+                "fixed_key" => compare(
+                    ncch::fixed_key,
+                    cmp,
+                    number::<bool>(value).ok_or_else(invalid)?,
+                ),
+                "no_romfs" => compare(
+                    ncch::no_romfs,
+                    cmp,
+                    number::<bool>(value).ok_or_else(invalid)?,
+                ),
+                "no_crypto" => compare(
+                    ncch::no_crypto,
+                    cmp,
+                    number::<bool>(value).ok_or_else(invalid)?,
+                ),
+                "seed_crypto" => compare(
+                    ncch::seed_crypto,
+                    cmp,
+                    number::<bool>(value).ok_or_else(invalid)?,
+                ),
+                "sd_app" => compare(
+                    ncch::sd_app,
+                    cmp,
+                    number::<bool>(value).ok_or_else(invalid)?,
+                ),
+                "remaster_version" => compare(
+                    ncch::remaster_version,
+                    cmp,
+                    number::<u16>(value).ok_or_else(invalid)?,
+                ),
+                "save_data_size" => compare(
+                    ncch::save_data_size,
+                    cmp,
+                    number::<u64>(value).ok_or_else(invalid)?,
+                ),
+                "core_version" => compare(
+                    ncch::core_version,
+                    cmp,
+                    number::<u32>(value).ok_or_else(invalid)?,
+                ),
+                "l2_cache" => compare(
+                    ncch::enable_l2_cache,
+                    cmp,
+                    number::<bool>(value).ok_or_else(invalid)?,
+                ),
+                "high_cpu_speed" => compare(
+                    ncch::high_cpu_speed,
+                    cmp,
+                    number::<bool>(value).ok_or_else(invalid)?,
+                ),
+                "system_mode" => compare(
+                    ncch::system_mode,
+                    cmp,
+                    number::<u8>(value).ok_or_else(invalid)?,
+                ),
+                "n3ds_mode" => compare(
+                    ncch::n3ds_system_mode,
+                    cmp,
+                    number::<u8>(value).ok_or_else(invalid)?,
+                ),
+                "ideal_processor" => compare(
+                    ncch::ideal_processor,
+                    cmp,
+                    number::<u8>(value).ok_or_else(invalid)?,
+                ),
+                "thread_priority" => compare(
+                    ncch::thread_priority,
+                    cmp,
+                    number::<u8>(value).ok_or_else(invalid)?,
+                ),
+                "eula_version" => compare(
+                    ncch::eula_version,
+                    cmp,
+                    number::<u16>(value).ok_or_else(invalid)?,
+                ),
+                _ => {
+                    return Err(QueryError::new(
+                        term.position,
+                        QueryErrorKind::UnknownField {
+                            field: field.to_owned(),
+                        },
+                    ))
+                }
+            };
+            Ok(condition)
+        }
+
+        pub fn filter_ncch(
+            param: &NcchFilterParam,
+        ) -> Result<ncch::BoxedQuery<'_, Backend>, DatabaseError> {
+            let mut statement = Box::new(ncch::table).into_boxed();
+            if let Some(keyword) = &param.keyword {
+                statement = statement.filter(keyword_condition(keyword));
+            }
+
+            if let Some(query) = &param.query {
+                let expr = query::parse(query).map_err(DatabaseError::InvalidQuery)?;
+                statement =
+                    statement.filter(query_condition(&expr).map_err(DatabaseError::InvalidQuery)?);
+            }
+
+            statement = filter_comparator(
+                statement,
+                &param.content_size_cmp,
+                &param.content_size_rhs,
+                ncch::content_size,
+            )?;
+
+            statement = filter_id(
+                statement,
+                &param.partition_id,
+                &param.partition_id_mask,
+                ncch::partition_id,
+            )?;
+
+            if let Some(maker_code) = &param.maker_code {
+                if maker_code.len() != 2 {
+                    return Err(DatabaseError::InvalidParam);
+                }
+                let maker_code = (maker_code.as_bytes()[0] as u16
+                    + ((maker_code.as_bytes()[1] as u16) << 8)) as i16;
+                statement = statement.filter(ncch::maker_code.eq(maker_code));
+            }
+
+            if let Some(maker_name) = &param.maker_name {
+                statement = statement.filter(maker_name_condition(maker_name));
+            }
+
+            statement = filter_comparator(
+                statement,
+                &param.ncch_version_cmp,
+                &param.ncch_version_rhs,
+                ncch::ncch_verson,
+            )?;
+
+            statement = filter_id(
+                statement,
+                &param.program_id,
+                &param.program_id_mask,
+                ncch::program_id,
+            )?;
+
+            if let Some(product_code) = &param.product_code {
+                if product_code::is_wildcard(product_code) {
+                    statement =
+                        statement.filter(product_code_condition(wildcard_pattern(product_code)));
+                } else {
+                    if product_code.len() > 16 {
+                        return Err(DatabaseError::InvalidParam);
+                    }
+                    let mut product_code = product_code.as_bytes().to_vec();
+                    while product_code.len() < 16 {
+                        product_code.push(0);
+                    }
+                    statement = statement.filter(ncch::product_code.eq(product_code));
+                }
+            }
+
+            statement = filter_comparator(
+                statement,
+                &param.secondary_key_slot_cmp,
+                &param.secondary_key_slot_rhs,
+                ncch::secondary_key_slot,
+            )?;
+
+            statement = filter_eq(statement, &param.platform, ncch::platform)?;
+
+            statement = filter_eq(statement, &param.content_is_data, ncch::content_is_data)?;
+
+            statement = filter_eq(
+                statement,
+                &param.content_is_executable,
+                ncch::content_is_executable,
+            )?;
+
+            statement = filter_comparator(
+                statement,
+                &param.content_category_cmp,
+                &param.content_category_rhs,
+                ncch::content_category,
+            )?;
+
+            statement = filter_comparator(
+                statement,
+                &param.content_unit_size_cmp,
+                &param.content_unit_size_rhs,
+                ncch::content_unit_size,
+            )?;
+
+            statement = filter_eq(statement, &param.fixed_key, ncch::fixed_key)?;
+
+            statement = filter_eq(statement, &param.no_romfs, ncch::no_romfs)?;
+
+            statement = filter_eq(statement, &param.no_crypto, ncch::no_crypto)?;
+
+            statement = filter_eq(statement, &param.seed_crypto, ncch::seed_crypto)?;
+
+            if let Some(exheader_name) = &param.exheader_name {
+                if exheader_name.len() > 8 {
+                    return Err(DatabaseError::InvalidParam);
+                }
+                let mut exheader_name = exheader_name.as_bytes().to_vec();
+                while exheader_name.len() < 8 {
+                    exheader_name.push(0);
+                }
+                statement = statement.filter(ncch::exheader_name.eq(exheader_name));
+            }
+
+            statement = filter_eq(statement, &param.sd_app, ncch::sd_app)?;
+
+            statement = filter_comparator(
+                statement,
+                &param.save_data_size_cmp,
+                &param.save_data_size_rhs,
+                ncch::save_data_size,
+            )?;
+
+            statement = filter_id(
+                statement,
+                &param.jump_id,
+                &param.jump_id_mask,
+                ncch::jump_id,
+            )?;
+
+            statement = filter_id(
+                statement,
+                &param.exheader_program_id,
+                &param.exheader_program_id_mask,
+                ncch::exheader_program_id,
+            )?;
+
+            statement = filter_comparator(
+                statement,
+                &param.core_version_cmp,
+                &param.core_version_rhs,
+                ncch::core_version,
+            )?;
+
+            statement = filter_eq(statement, &param.enable_l2_cache, ncch::enable_l2_cache)?;
+
+            statement = filter_eq(statement, &param.high_cpu_speed, ncch::high_cpu_speed)?;
+
+            statement = filter_comparator(
+                statement,
+                &param.system_mode_cmp,
+                &param.system_mode_rhs,
+                ncch::system_mode,
+            )?;
+
+            statement = filter_comparator(
+                statement,
+                &param.n3ds_system_mode_cmp,
+                &param.n3ds_system_mode_rhs,
+                ncch::n3ds_system_mode,
+            )?;
+
+            statement = filter_comparator(
+                statement,
+                &param.ideal_processor_cmp,
+                &param.ideal_processor_rhs,
+                ncch::ideal_processor,
+            )?;
+
+            statement = filter_comparator(
+                statement,
+                &param.affinity_mask_cmp,
+                &param.affinity_mask_rhs,
+                ncch::affinity_mask,
+            )?;
+
+            statement = filter_comparator(
+                statement,
+                &param.thread_priority_cmp,
+                &param.thread_priority_rhs,
+                ncch::thread_priority,
+            )?;
+
+            statement = filter_flag(
+                statement,
+                &param.filesystem_flag,
+                &param.filesystem_flag_mask,
+                ncch::filesystem_flag,
+            )?;
+
+            statement = filter_comparator(
+                statement,
+                &param.resource_limit_category_cmp,
+                &param.resource_limit_category_rhs,
+                ncch::resource_limit_category,
+            )?;
+
+            statement = filter_flag(
+                statement,
+                &param.arm9_flag,
+                &param.arm9_flag_mask,
+                ncch::arm9_flag,
+            )?;
+
+            statement = filter_comparator(
+                statement,
+                &param.arm9_flag_version_cmp,
+                &param.arm9_flag_version_rhs,
+                ncch::arm9_flag_version,
+            )?;
+
+            statement = filter_flag(
+                statement,
+                &param.region_lockout,
+                &param.region_lockout_mask,
+                ncch::region_lockout,
+            )?;
+
+            if let Some(match_maker_id) = &param.match_maker_id {
+                let match_maker_id = u32::from_str_radix(&match_maker_id, 16)
+                    .map_err(|_| DatabaseError::InvalidParam)? as i32;
+                statement = statement.filter(ncch::match_maker_id.eq(match_maker_id));
+            }
+
+            if let Some(match_maker_bit_id) = &param.match_maker_bit_id {
+                let match_maker_bit_id = u64::from_str_radix(&match_maker_bit_id, 16)
+                    .map_err(|_| DatabaseError::InvalidParam)?
+                    as i64;
+                statement = statement.filter(ncch::match_maker_bit_id.eq(match_maker_bit_id));
+            }
+
+            statement = filter_flag(
+                statement,
+                &param.smdh_flag,
+                &param.smdh_flag_mask,
+                ncch::smdh_flags,
+            )?;
+
+            statement = filter_comparator(
+                statement,
+                &param.eula_version_cmp,
+                &param.eula_version_rhs,
+                ncch::eula_version,
+            )?;
+
+            if let Some(cec_id) = &param.cec_id {
+                let cec_id = u32::from_str_radix(&cec_id, 16)
+                    .map_err(|_| DatabaseError::InvalidParam)? as i32;
+                statement = statement.filter(ncch::cec_id.eq(cec_id));
+            }
+
+            Ok(statement)
+        }
+    };
+}
+
+mod postgres {
+    use super::*;
+
+    type Backend = diesel::pg::Pg;
+
+    fn service_condition<'a>(service: Vec<u8>) -> BoxedCondition<'a> {
+        Box::new(
+            diesel::dsl::sql::<diesel::sql_types::Bool>(
+                "services IS NOT NULL AND services @> ARRAY[",
+            )
+            .bind::<diesel::sql_types::Binary, _>(service)
+            .sql("]"),
+        )
     }
 
-    statement = filter_comparator(
-        statement,
-        &param.secondary_key_slot_cmp,
-        &param.secondary_key_slot_rhs,
-        ncch::secondary_key_slot,
-    )?;
+    // Product codes are zero padded bytes
+    fn product_code_condition<'a>(pattern: String) -> BoxedCondition<'a> {
+        Box::new(
+            diesel::dsl::sql::<diesel::sql_types::Bool>(
+                "encode(btrim(product_code, '\\x00'::bytea), 'escape') LIKE ",
+            )
+            .bind::<diesel::sql_types::Text, _>(pattern)
+            .sql(" ESCAPE '\\'"),
+        )
+    }
 
-    statement = filter_eq(statement, &param.platform, ncch::platform)?;
+    // Rounds a positive integer expression down to a power of two. Anything below 1 becomes 0.
+    pub fn power_of_two_floor(expr: &str) -> String {
+        format!(
+            "CASE WHEN {} < 1 THEN 0 ELSE POWER(2, FLOOR(LOG(2, CAST({} AS NUMERIC)))) END",
+            expr, expr
+        )
+    }
 
-    statement = filter_eq(statement, &param.content_is_data, ncch::content_is_data)?;
+    pub fn hamming_distance(a: &str, b: &str) -> String {
+        format!(
+            "LENGTH(REPLACE(CAST(CAST(({} # {}) AS BIT(64)) AS TEXT), '0', ''))",
+            a, b
+        )
+    }
 
-    statement = filter_eq(
-        statement,
-        &param.content_is_executable,
-        ncch::content_is_executable,
-    )?;
+    // Icons that are already stored are skipped
+    pub fn insert_icons(connection: &PgConnection, icons: &[Icon]) -> QueryResult<usize> {
+        diesel::insert_into(icon::table)
+            .values(icons)
+            .on_conflict_do_nothing()
+            .execute(connection)
+    }
 
-    statement = filter_comparator(
-        statement,
-        &param.content_category_cmp,
-        &param.content_category_rhs,
-        ncch::content_category,
-    )?;
+    fn from_json<T: serde::de::DeserializeOwned>(json: Option<String>) -> QueryResult<Vec<T>> {
+        match json {
+            Some(json) => {
+                serde_json::from_str(&json).map_err(|e| Error::DeserializationError(Box::new(e)))
+            }
+            None => Ok(vec![]),
+        }
+    }
 
-    statement = filter_comparator(
-        statement,
-        &param.content_unit_size_cmp,
-        &param.content_unit_size_rhs,
-        ncch::content_unit_size,
-    )?;
+    // eq_any binds each list as one array, so the NCCHs come in one statement whatever the
+    // number of IDs, with their titles, maker name and releases aggregated in subqueries
+    pub fn query_ncch_by_ids(
+        connection: &PgConnection,
+        program_ids: &[i64],
+        ids: &[String],
+    ) -> QueryResult<Vec<NcchRecord>> {
+        use diesel::dsl::sql;
+        use diesel::sql_types::{Nullable, Text};
+        let rows = ncch::table
+            .select((
+                ncch::all_columns,
+                sql::<Nullable<Text>>(
+                    "(SELECT CAST(json_agg(t ORDER BY t.language) AS TEXT) \
+                     FROM ncch_title t WHERE t.ncch_id = ncch.id)",
+                ),
+                sql::<Nullable<Text>>("(SELECT name FROM maker WHERE code = ncch.maker_code)"),
+                sql::<Nullable<Text>>(
+                    "(SELECT CAST(json_agg(r ORDER BY r.serial) AS TEXT) \
+                     FROM ncch_release r WHERE r.ncch_id = ncch.id)",
+                ),
+            ))
+            .filter(
+                ncch::program_id
+                    .eq_any(program_ids)
+                    .or(ncch::id.eq_any(ids)),
+            )
+            .order_by(ncch::id.asc())
+            .load::<(NcchRow, Option<String>, Option<String>, Option<String>)>(connection)?;
+        rows.into_iter()
+            .map(|(ncch, titles, maker_name, releases)| {
+                Ok(NcchRecord {
+                    ncch,
+                    titles: from_json(titles)?,
+                    icons: vec![],
+                    blob: None,
+                    maker_name,
+                    releases: from_json(releases)?,
+                    hashes: vec![],
+                })
+            })
+            .collect()
+    }
 
-    statement = filter_eq(statement, &param.fixed_key, ncch::fixed_key)?;
+    ncch_query_builder!();
+}
 
-    statement = filter_eq(statement, &param.no_romfs, ncch::no_romfs)?;
+mod sqlite {
+    use super::*;
+
+    type Backend = diesel::sqlite::Sqlite;
+
+    // Arrays are stored as JSON text on SQLite, see PortableArray
+    fn service_condition<'a>(service: Vec<u8>) -> BoxedCondition<'a> {
+        Box::new(
+            diesel::dsl::sql::<diesel::sql_types::Bool>(
+                "services IS NOT NULL AND EXISTS (SELECT 1 FROM json_each(services) \
+                 WHERE json_each.value = ",
+            )
+            .bind::<diesel::sql_types::Text, _>(
+                serde_json::to_string(&service).expect("failed to serialize service name"),
+            )
+            .sql(")"),
+        )
+    }
 
-    statement = filter_eq(statement, &param.no_crypto, ncch::no_crypto)?;
+    // Product codes are zero padded bytes
+    fn product_code_condition<'a>(pattern: String) -> BoxedCondition<'a> {
+        Box::new(
+            diesel::dsl::sql::<diesel::sql_types::Bool>(
+                "RTRIM(CAST(product_code AS TEXT), char(0)) LIKE ",
+            )
+            .bind::<diesel::sql_types::Text, _>(pattern)
+            .sql(" ESCAPE '\\'"),
+        )
+    }
 
-    statement = filter_eq(statement, &param.seed_crypto, ncch::seed_crypto)?;
+    // SQLite has no logarithm function in older versions, so spell out every bucket.
+    pub fn power_of_two_floor(expr: &str) -> String {
+        let mut sql = "CASE".to_owned();
+        for bit in (0..63).rev() {
+            sql += &format!(" WHEN {} >= {} THEN {}", expr, 1u64 << bit, 1u64 << bit);
+        }
+        sql + " ELSE 0 END"
+    }
 
-    if let Some(exheader_name) = &param.exheader_name {
-        if exheader_name.len() > 8 {
-            return Err(DatabaseError::InvalidParam);
+    // No XOR operator or popcount either, so the function is implemented in Rust
+    pub fn hamming_distance(a: &str, b: &str) -> String {
+        format!("hamming_distance({}, {})", a, b)
+    }
+
+    // Icons that are already stored are skipped
+    pub fn insert_icons(connection: &SqliteConnection, icons: &[Icon]) -> QueryResult<usize> {
+        diesel::insert_or_ignore_into(icon::table)
+            .values(icons)
+            .execute(connection)
+    }
+
+    ncch_query_builder!();
+}
+
+// Runs the body with `$connection` bound to the concrete diesel connection (not the pooled
+// wrapper, so that backend specific impls such as SQLite batch insert apply), and optionally
+// `$backend` to the matching query builder module.
+macro_rules! with_backend {
+    ($self:ident, |$connection:ident| $body:expr) => {
+        match &$self.connection {
+            PooledBackend::Postgres($connection) => {
+                let $connection: &PgConnection = $connection;
+                $body
+            }
+            PooledBackend::Sqlite($connection) => {
+                let $connection: &SqliteConnection = $connection;
+                $body
+            }
+        }
+    };
+    ($self:ident, |$connection:ident, $backend:ident| $body:expr) => {
+        match &$self.connection {
+            PooledBackend::Postgres($connection) => {
+                let $connection: &PgConnection = $connection;
+                use self::postgres as $backend;
+                $body
+            }
+            PooledBackend::Sqlite($connection) => {
+                let $connection: &SqliteConnection = $connection;
+                use self::sqlite as $backend;
+                $body
+            }
         }
-        let mut exheader_name = exheader_name.as_bytes().to_vec();
-        while exheader_name.len() < 8 {
-            exheader_name.push(0);
-        }
-        statement = statement.filter(ncch::exheader_name.eq(exheader_name));
-    }
-
-    statement = filter_eq(statement, &param.sd_app, ncch::sd_app)?;
-
-    statement = filter_comparator(
-        statement,
-        &param.save_data_size_cmp,
-        &param.save_data_size_rhs,
-        ncch::save_data_size,
-    )?;
-
-    statement = filter_id(
-        statement,
-        &param.jump_id,
-        &param.jump_id_mask,
-        ncch::jump_id,
-    )?;
-
-    statement = filter_id(
-        statement,
-        &param.exheader_program_id,
-        &param.exheader_program_id_mask,
-        ncch::exheader_program_id,
-    )?;
-
-    statement = filter_comparator(
-        statement,
-        &param.core_version_cmp,
-        &param.core_version_rhs,
-        ncch::core_version,
-    )?;
-
-    statement = filter_eq(statement, &param.enable_l2_cache, ncch::enable_l2_cache)?;
-
-    statement = filter_eq(statement, &param.high_cpu_speed, ncch::high_cpu_speed)?;
-
-    statement = filter_comparator(
-        statement,
-        &param.system_mode_cmp,
-        &param.system_mode_rhs,
-        ncch::system_mode,
-    )?;
-
-    statement = filter_comparator(
-        statement,
-        &param.n3ds_system_mode_cmp,
-        &param.n3ds_system_mode_rhs,
-        ncch::n3ds_system_mode,
-    )?;
-
-    statement = filter_comparator(
-        statement,
-        &param.ideal_processor_cmp,
-        &param.ideal_processor_rhs,
-        ncch::ideal_processor,
-    )?;
-
-    statement = filter_comparator(
-        statement,
-        &param.affinity_mask_cmp,
-        &param.affinity_mask_rhs,
-        ncch::affinity_mask,
-    )?;
-
-    statement = filter_comparator(
-        statement,
-        &param.thread_priority_cmp,
-        &param.thread_priority_rhs,
-        ncch::thread_priority,
-    )?;
-
-    statement = filter_flag(
-        statement,
-        &param.filesystem_flag,
-        &param.filesystem_flag_mask,
-        ncch::filesystem_flag,
-    )?;
-
-    statement = filter_comparator(
-        statement,
-        &param.resource_limit_category_cmp,
-        &param.resource_limit_category_rhs,
-        ncch::resource_limit_category,
-    )?;
-
-    statement = filter_flag(
-        statement,
-        &param.arm9_flag,
-        &param.arm9_flag_mask,
-        ncch::arm9_flag,
-    )?;
-
-    statement = filter_comparator(
-        statement,
-        &param.arm9_flag_version_cmp,
-        &param.arm9_flag_version_rhs,
-        ncch::arm9_flag_version,
-    )?;
-
-    statement = filter_flag(
-        statement,
-        &param.region_lockout,
-        &param.region_lockout_mask,
-        ncch::region_lockout,
-    )?;
-
-    if let Some(match_maker_id) = &param.match_maker_id {
-        let match_maker_id = u32::from_str_radix(&match_maker_id, 16)
-            .map_err(|_| DatabaseError::InvalidParam)? as i32;
-        statement = statement.filter(ncch::match_maker_id.eq(match_maker_id));
-    }
-
-    if let Some(match_maker_bit_id) = &param.match_maker_bit_id {
-        let match_maker_bit_id = u64::from_str_radix(&match_maker_bit_id, 16)
-            .map_err(|_| DatabaseError::InvalidParam)? as i64;
-        statement = statement.filter(ncch::match_maker_bit_id.eq(match_maker_bit_id));
-    }
-
-    statement = filter_flag(
-        statement,
-        &param.smdh_flag,
-        &param.smdh_flag_mask,
-        ncch::smdh_flags,
-    )?;
-
-    statement = filter_comparator(
-        statement,
-        &param.eula_version_cmp,
-        &param.eula_version_rhs,
-        ncch::eula_version,
-    )?;
-
-    if let Some(cec_id) = &param.cec_id {
-        let cec_id =
-            u32::from_str_radix(&cec_id, 16).map_err(|_| DatabaseError::InvalidParam)? as i32;
-        statement = statement.filter(ncch::cec_id.eq(cec_id));
-    }
-
-    Ok(statement)
+    };
+}
+
+// Two ASCII characters, stored little endian
+fn maker_code_value(maker_code: &str) -> Option<i16> {
+    match maker_code.as_bytes() {
+        &[a, b] if maker_code.is_ascii() => Some((a as u16 | (b as u16) << 8) as i16),
+        _ => None,
+    }
+}
+
+fn maker_code_string(maker_code: i64) -> String {
+    [
+        (maker_code & 0xFF) as u8 as char,
+        ((maker_code >> 8) & 0xFF) as u8 as char,
+    ]
+    .iter()
+    .collect()
+}
+
+// The false and true counts of a flag
+type FlagCount = (i64, i64);
+// The number of records, and the counts of each boolean facet in the order NcchFacets lists them
+type FlagCounts = (
+    i64,
+    FlagCount,
+    FlagCount,
+    FlagCount,
+    FlagCount,
+    FlagCount,
+    FlagCount,
+    FlagCount,
+    FlagCount,
+    FlagCount,
+);
+
+// Buckets for false, true and unknown, leaving out the empty ones
+fn flag_buckets(total: i64, (unset, set): FlagCount) -> Vec<StatsBucket> {
+    vec![
+        (Some("0"), unset),
+        (Some("1"), set),
+        (None, total - unset - set),
+    ]
+    .into_iter()
+    .filter(|&(_, count)| count > 0)
+    .map(|(key, count)| StatsBucket {
+        key: key.map(str::to_owned),
+        count,
+    })
+    .collect()
+}
+
+fn to_buckets(counts: Vec<(Option<i64>, i64)>) -> Vec<StatsBucket> {
+    counts
+        .into_iter()
+        .map(|(key, count)| StatsBucket {
+            key: key.map(|k| format!("{}", k)),
+            count,
+        })
+        .collect()
+}
+
+// Looks up every input of a batch together, rather than one query per input, and groups the
+// matches by input
+pub fn lookup_batch(
+    store: &dyn NcchStore,
+    inputs: &[String],
+) -> Result<std::collections::BTreeMap<String, Vec<NcchInfo>>, DatabaseError> {
+    if inputs.len() > LOOKUP_BATCH_LIMIT {
+        return Err(DatabaseError::InvalidParam);
+    }
+    let program_id = |input: &str| {
+        if input.len() == 16 {
+            u64::from_str_radix(input, 16).ok()
+        } else {
+            None
+        }
+    };
+    let mut program_ids = vec![];
+    let mut ids = vec![];
+    for input in inputs {
+        match program_id(input) {
+            Some(program_id) => program_ids.push(program_id),
+            None => ids.push(input.clone()),
+        }
+    }
+    let mut by_program_id = std::collections::HashMap::<u64, Vec<NcchInfo>>::new();
+    let mut by_id = std::collections::HashMap::<String, NcchInfo>::new();
+    for record in store.query_ncch_by_ids(&program_ids, &ids)? {
+        let info = record.to_ncch_info();
+        by_program_id
+            .entry(record.ncch.program_id as u64)
+            .or_default()
+            .push(info.clone());
+        by_id.insert(info.id.clone(), info);
+    }
+    Ok(inputs
+        .iter()
+        .map(|input| {
+            let matches = match program_id(input) {
+                Some(program_id) => by_program_id.get(&program_id).cloned().unwrap_or_default(),
+                None => by_id.get(input).cloned().into_iter().collect(),
+            };
+            (input.clone(), matches)
+        })
+        .collect())
+}
+
+// Operations the rest of the server needs from the NCCH storage, implemented by Connection and by
+// Database. Tests run them on an in-memory SQLite database.
+pub trait NcchStore: std::fmt::Debug {
+    fn insert_ncch_record(&self, record: &NcchRecord) -> Result<(), DatabaseError>;
+    fn update_ncch_record(&self, record: &NcchRecord) -> Result<(), DatabaseError>;
+    fn get_ncch_record(&self, id: &str) -> Result<NcchRecord, DatabaseError>;
+    fn get_ncch_icon(&self, id: &str, size: IconSize) -> Result<Icon, DatabaseError>;
+    fn get_icons(&self, hashes: &[String]) -> Result<Vec<Icon>, DatabaseError>;
+    fn get_ncch_blob(&self, id: &str) -> Result<NcchBlob, DatabaseError>;
+    fn query_ncch_blobs(
+        &self,
+        after: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<NcchBlob>, DatabaseError>;
+    fn query_similar_icons(
+        &self,
+        id: &str,
+        max_distance: u32,
+        limit: i64,
+    ) -> Result<Vec<(NcchRecord, u32)>, DatabaseError>;
+    // Records with a section of the given SHA-256, or with the given 0x100-byte signature, and
+    // the kind of what matched
+    fn lookup_hash(&self, hash: &[u8]) -> Result<Vec<(NcchRecord, String)>, DatabaseError>;
+    // Fills in the stored hashes of the records, which the queries leave out
+    fn load_hashes(&self, records: &mut [NcchRecord]) -> Result<(), DatabaseError>;
+    // Records with any of the program IDs or NCCH IDs, ordered by ID. On Postgres this is a
+    // single round trip. SQLite splits the IDs into chunks under its bind parameter limit and
+    // loads the details separately.
+    fn query_ncch_by_ids(
+        &self,
+        program_ids: &[u64],
+        ids: &[String],
+    ) -> Result<Vec<NcchRecord>, DatabaseError>;
+    fn query_ncch(&self, param: &NcchQueryParam) -> Result<Vec<NcchRecord>, DatabaseError>;
+    fn query_ncch_batch(
+        &self,
+        filter: &NcchFilterParam,
+        after: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<NcchRecord>, DatabaseError>;
+    // Same as query_ncch_batch, but ordered by program ID and then by ID, after the given pair
+    fn query_ncch_program_batch(
+        &self,
+        filter: &NcchFilterParam,
+        after: Option<(u64, &str)>,
+        limit: i64,
+    ) -> Result<Vec<NcchRecord>, DatabaseError>;
+    fn query_ncch_facets(&self, param: &NcchFilterParam) -> Result<NcchFacets, DatabaseError>;
+    fn query_ncch_stats(&self, param: &NcchFilterParam) -> Result<NcchStats, DatabaseError>;
+    fn query_ncch_count(&self, param: &NcchFilterParam) -> Result<i64, DatabaseError>;
+    // Replaces the whole maker registry
+    fn replace_makers(&self, makers: &[MakerRow]) -> Result<(), DatabaseError>;
+    // Registered makers by name, with the number of NCCHs of each code
+    fn query_makers(&self) -> Result<Vec<Maker>, DatabaseError>;
+    // Replaces the releases of every NCCH with the matches of a new release list
+    fn replace_releases(&self, releases: &[ReleaseRow]) -> Result<(), DatabaseError>;
 }
 
 pub struct Connection {
-    connection: PooledConnection<ConnectionManager<PgConnection>>,
+    connection: PooledBackend,
+}
+
+impl std::fmt::Debug for Connection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[database connection]")
+    }
 }
 
 impl Connection {
-    pub fn insert_ncch_record(&self, record: &NcchRecord) -> Result<(), DatabaseError> {
-        let record: QueryResult<NcchRecord> = diesel::insert_into(ncch::table)
-            .values(record)
-            .get_result(&self.connection);
-        match record {
+    // Loads the titles, maker names and releases of the rows and attaches them, keeping the row
+    // order. The IDs are passed in chunks that stay under the bind parameter limit.
+    fn with_details(&self, rows: Vec<NcchRow>) -> QueryResult<Vec<NcchRecord>> {
+        let ids: Vec<&str> = rows.iter().map(|row| row.id.as_str()).collect();
+        let mut titles: Vec<NcchTitle> = vec![];
+        let mut releases: Vec<ReleaseRow> = vec![];
+        for chunk in ids.chunks(BIND_LIMIT) {
+            titles.extend(with_backend!(self, |connection| {
+                ncch_title::table
+                    .filter(ncch_title::ncch_id.eq_any(chunk))
+                    .order_by(ncch_title::language.asc())
+                    .load::<NcchTitle>(connection)
+            })?);
+            releases.extend(with_backend!(self, |connection| {
+                ncch_release::table
+                    .filter(ncch_release::ncch_id.eq_any(chunk))
+                    .order_by(ncch_release::serial.asc())
+                    .load::<ReleaseRow>(connection)
+            })?);
+        }
+        let mut codes: Vec<i16> = rows.iter().map(|row| row.maker_code).collect();
+        codes.sort();
+        codes.dedup();
+        let mut makers: Vec<MakerRow> = vec![];
+        for chunk in codes.chunks(BIND_LIMIT) {
+            makers.extend(with_backend!(self, |connection| {
+                maker::table
+                    .filter(maker::code.eq_any(chunk))
+                    .load::<MakerRow>(connection)
+            })?);
+        }
+
+        let mut titles_by_id = std::collections::HashMap::<String, Vec<NcchTitle>>::new();
+        for title in titles {
+            titles_by_id
+                .entry(title.ncch_id.clone())
+                .or_default()
+                .push(title);
+        }
+        let names: std::collections::HashMap<i16, String> = makers
+            .into_iter()
+            .map(|maker| (maker.code, maker.name))
+            .collect();
+        let mut releases_by_id = std::collections::HashMap::<String, Vec<ReleaseRow>>::new();
+        for release in releases {
+            releases_by_id
+                .entry(release.ncch_id.clone())
+                .or_default()
+                .push(release);
+        }
+
+        Ok(rows
+            .into_iter()
+            .map(|ncch| {
+                let titles = titles_by_id.remove(&ncch.id).unwrap_or_default();
+                let maker_name = names.get(&ncch.maker_code).cloned();
+                let releases = releases_by_id.remove(&ncch.id).unwrap_or_default();
+                NcchRecord {
+                    ncch,
+                    titles,
+                    icons: vec![],
+                    blob: None,
+                    maker_name,
+                    releases,
+                    hashes: vec![],
+                }
+            })
+            .collect())
+    }
+
+    fn count_by(
+        &self,
+        param: &NcchFilterParam,
+        key: &str,
+    ) -> Result<Vec<(Option<i64>, i64)>, DatabaseError> {
+        use diesel::sql_types::{BigInt, Nullable};
+        match with_backend!(self, |connection, backend| {
+            backend::filter_ncch(param)?
+                .select((
+                    diesel::dsl::sql::<Nullable<BigInt>>(&format!("CAST({} AS BIGINT)", key)),
+                    diesel::dsl::sql::<BigInt>("COUNT(*)"),
+                ))
+                .group_by(diesel::dsl::sql::<BigInt>("1"))
+                .order_by(diesel::dsl::sql::<BigInt>("1"))
+                .load(connection)
+        }) {
+            Err(e) => {
+                error!("Database error: {}", e);
+                Err(DatabaseError::Other)
+            }
+            Ok(buckets) => Ok(buckets),
+        }
+    }
+
+    // Counts the records with each bit set, from one group per distinct value
+    fn count_by_bit(
+        &self,
+        param: &NcchFilterParam,
+        field: &str,
+        bits: u32,
+    ) -> Result<Vec<StatsBucket>, DatabaseError> {
+        let mut counts = vec![0; bits as usize];
+        for (value, count) in self.count_by(param, field)? {
+            if let Some(value) = value {
+                for (bit, total) in counts.iter_mut().enumerate() {
+                    if value & (1 << bit) != 0 {
+                        *total += count;
+                    }
+                }
+            }
+        }
+        Ok(counts
+            .into_iter()
+            .enumerate()
+            .map(|(bit, count)| StatsBucket {
+                key: Some(format!("{}", bit)),
+                count,
+            })
+            .collect())
+    }
+
+    fn count_by_maker_code(
+        &self,
+        param: &NcchFilterParam,
+    ) -> Result<Vec<StatsBucket>, DatabaseError> {
+        Ok(self
+            .count_by(param, "maker_code")?
+            .into_iter()
+            .map(|(key, count)| StatsBucket {
+                key: key.map(maker_code_string),
+                count,
+            })
+            .collect())
+    }
+
+    // Counts every boolean facet in one aggregate query
+    fn count_by_flags(&self, param: &NcchFilterParam) -> Result<FlagCounts, DatabaseError> {
+        use diesel::dsl::sql;
+        use diesel::sql_types::BigInt;
+        let flag = |field: &str| {
+            (
+                sql::<BigInt>(&format!(
+                    "COALESCE(SUM(CASE WHEN NOT {} THEN 1 ELSE 0 END), 0)",
+                    field
+                )),
+                sql::<BigInt>(&format!(
+                    "COALESCE(SUM(CASE WHEN {} THEN 1 ELSE 0 END), 0)",
+                    field
+                )),
+            )
+        };
+        match with_backend!(self, |connection, backend| {
+            backend::filter_ncch(param)?
+                .select((
+                    sql::<BigInt>("COUNT(*)"),
+                    flag("content_is_data"),
+                    flag("content_is_executable"),
+                    flag("fixed_key"),
+                    flag("no_romfs"),
+                    flag("no_crypto"),
+                    flag("seed_crypto"),
+                    flag("sd_app"),
+                    flag("enable_l2_cache"),
+                    flag("high_cpu_speed"),
+                ))
+                .get_result::<FlagCounts>(connection)
+        }) {
+            Err(e) => {
+                error!("Database error: {}", e);
+                Err(DatabaseError::Other)
+            }
+            Ok(counts) => Ok(counts),
+        }
+    }
+
+    // Hashes a batch of the icons that don't have a dhash yet, in hash order after `after`.
+    // Returns how many were updated, and the last hash of the batch to continue from.
+    pub fn fill_icon_dhash(
+        &self,
+        after: Option<&str>,
+        limit: i64,
+    ) -> QueryResult<(usize, Option<String>)> {
+        let icons: Vec<Icon> = with_backend!(self, |connection| {
+            icon::table
+                .filter(icon::dhash.is_null())
+                .filter(icon::hash.gt(after.unwrap_or("")))
+                .order_by(icon::hash.asc())
+                .limit(limit)
+                .load(connection)
+        })?;
+        let last = icons.last().map(|icon| icon.hash.clone());
+        let mut count = 0;
+        for icon in icons {
+            let dhash = match crate::icon::Image::decode(&icon.pixels()) {
+                Ok(image) => image.dhash() as i64,
+                Err(e) => {
+                    warn!("icon {}: {}", icon.hash, e);
+                    continue;
+                }
+            };
+            count += with_backend!(self, |connection| {
+                diesel::update(icon::table.find(&icon.hash))
+                    .set(icon::dhash.eq(dhash))
+                    .execute(connection)
+            })?;
+        }
+        Ok((count, last))
+    }
+
+    // NCCHs with a stored blob but no header hash, as stored before hashes were kept. Reprocessing
+    // fills them in.
+    pub fn count_unhashed(&self) -> QueryResult<i64> {
+        with_backend!(self, |connection| {
+            ncch_blob::table
+                .filter(diesel::dsl::not(
+                    ncch_blob::ncch_id.eq_any(
+                        ncch_hash::table
+                            .select(ncch_hash::ncch_id)
+                            .filter(ncch_hash::kind.eq("header")),
+                    ),
+                ))
+                .count()
+                .get_result(connection)
+        })
+    }
+}
+
+impl NcchStore for Connection {
+    fn insert_ncch_record(&self, record: &NcchRecord) -> Result<(), DatabaseError> {
+        // SQLite has no RETURNING, so only count the inserted rows
+        let result: QueryResult<usize> = with_backend!(self, |connection, backend| {
+            connection.transaction(|| {
+                if !record.icons.is_empty() {
+                    backend::insert_icons(connection, &record.icons)?;
+                }
+                let count = diesel::insert_into(ncch::table)
+                    .values(&record.ncch)
+                    .execute(connection)?;
+                if !record.titles.is_empty() {
+                    diesel::insert_into(ncch_title::table)
+                        .values(&record.titles)
+                        .execute(connection)?;
+                }
+                if !record.releases.is_empty() {
+                    diesel::insert_into(ncch_release::table)
+                        .values(&record.releases)
+                        .execute(connection)?;
+                }
+                if let Some(blob) = &record.blob {
+                    diesel::insert_into(ncch_blob::table)
+                        .values(blob)
+                        .execute(connection)?;
+                }
+                if !record.hashes.is_empty() {
+                    diesel::insert_into(ncch_hash::table)
+                        .values(&record.hashes)
+                        .execute(connection)?;
+                }
+                Ok(count)
+            })
+        });
+        match result {
             Ok(_) => {
                 info!("NCCH record inserted");
                 Ok(())
@@ -828,30 +2433,413 @@ impl Connection {
         }
     }
 
-    pub fn get_ncch_record(&self, id: &str) -> Result<NcchRecord, DatabaseError> {
+    // Overwrites an existing record, clearing the fields that are None. The icon and section
+    // hashes, which an imported record doesn't carry, are kept if the record has none, and so are
+    // the titles and hashes, the blob and the releases.
+    fn update_ncch_record(&self, record: &NcchRecord) -> Result<(), DatabaseError> {
+        match with_backend!(self, |connection, backend| {
+            connection.transaction::<_, Error, _>(|| {
+                if !record.icons.is_empty() {
+                    backend::insert_icons(connection, &record.icons)?;
+                }
+                let stored = ncch::table
+                    .find(&record.ncch.id)
+                    .select((
+                        ncch::small_icon_hash,
+                        ncch::large_icon_hash,
+                        ncch::exheader_hash,
+                        ncch::smdh_hash,
+                    ))
+                    .first(connection)
+                    .optional()?;
+                let mut row = record.ncch.clone();
+                if let Some((small_icon_hash, large_icon_hash, exheader_hash, smdh_hash)) = stored {
+                    row.small_icon_hash = row.small_icon_hash.or(small_icon_hash);
+                    row.large_icon_hash = row.large_icon_hash.or(large_icon_hash);
+                    row.exheader_hash = row.exheader_hash.or(exheader_hash);
+                    row.smdh_hash = row.smdh_hash.or(smdh_hash);
+                }
+                let count = diesel::update(ncch::table.find(&record.ncch.id))
+                    .set(&row)
+                    .execute(connection)?;
+                if count != 0 && !record.titles.is_empty() {
+                    diesel::delete(
+                        ncch_title::table.filter(ncch_title::ncch_id.eq(&record.ncch.id)),
+                    )
+                    .execute(connection)?;
+                    diesel::insert_into(ncch_title::table)
+                        .values(&record.titles)
+                        .execute(connection)?;
+                }
+                if let Some(blob) = record.blob.as_ref().filter(|_| count != 0) {
+                    diesel::delete(ncch_blob::table.find(&record.ncch.id)).execute(connection)?;
+                    diesel::insert_into(ncch_blob::table)
+                        .values(blob)
+                        .execute(connection)?;
+                }
+                if count != 0 && !record.hashes.is_empty() {
+                    diesel::delete(ncch_hash::table.filter(ncch_hash::ncch_id.eq(&record.ncch.id)))
+                        .execute(connection)?;
+                    diesel::insert_into(ncch_hash::table)
+                        .values(&record.hashes)
+                        .execute(connection)?;
+                }
+                Ok(count)
+            })
+        }) {
+            Ok(0) => Err(DatabaseError::NotFound),
+            Ok(_) => {
+                info!("NCCH record updated");
+                Ok(())
+            }
+            Err(e) => {
+                error!("Database error: {}", e);
+                Err(DatabaseError::Other)
+            }
+        }
+    }
+
+    fn get_ncch_record(&self, id: &str) -> Result<NcchRecord, DatabaseError> {
         info!("getting NCCH with id = {}", id);
-        match ncch::table.filter(ncch::id.eq(id)).first(&self.connection) {
+        match with_backend!(self, |connection| {
+            ncch::table.filter(ncch::id.eq(id)).first(connection)
+        }) {
             Err(e) => {
                 warn!("Database error: {}", e);
                 Err(DatabaseError::NotFound)
             }
             Ok(ncch) => {
                 info!("NCCH found");
-                Ok(ncch)
+                match self.with_details(vec![ncch]) {
+                    Err(e) => {
+                        error!("Database error: {}", e);
+                        Err(DatabaseError::Other)
+                    }
+                    Ok(mut records) => Ok(records.remove(0)),
+                }
+            }
+        }
+    }
+
+    fn get_ncch_icon(&self, id: &str, size: IconSize) -> Result<Icon, DatabaseError> {
+        info!("getting {:?} icon of NCCH with id = {}", size, id);
+        let mut icon: Icon = match with_backend!(self, |connection| {
+            ncch::table
+                .find(id)
+                .select((ncch::small_icon_hash, ncch::large_icon_hash))
+                .first::<(Option<String>, Option<String>)>(connection)
+                .and_then(|(small, large)| {
+                    let hash = match size {
+                        IconSize::Small => small,
+                        IconSize::Large => large,
+                    };
+                    icon::table
+                        .find(hash.ok_or(Error::NotFound)?)
+                        .first(connection)
+                })
+        }) {
+            Err(Error::NotFound) => return Err(DatabaseError::NotFound),
+            Err(e) => {
+                error!("Database error: {}", e);
+                return Err(DatabaseError::Other);
             }
+            Ok(icon) => icon,
+        };
+
+        if icon.png.is_none() {
+            let png = crate::icon::encode_png(&icon.pixels()).map_err(|e| {
+                error!("{}", e);
+                DatabaseError::Other
+            })?;
+            if let Err(e) = with_backend!(self, |connection| {
+                diesel::update(icon::table.find(&icon.hash))
+                    .set(icon::png.eq(&png))
+                    .execute(connection)
+            }) {
+                warn!("failed to store the encoded icon: {}", e);
+            }
+            icon.png = Some(png);
+        }
+        Ok(icon)
+    }
+
+    // Icons that are not found are left out
+    fn get_icons(&self, hashes: &[String]) -> Result<Vec<Icon>, DatabaseError> {
+        match with_backend!(self, |connection| {
+            icon::table
+                .filter(icon::hash.eq_any(hashes))
+                .load(connection)
+        }) {
+            Err(e) => {
+                error!("Database error: {}", e);
+                Err(DatabaseError::Other)
+            }
+            Ok(icons) => Ok(icons),
+        }
+    }
+
+    fn get_ncch_blob(&self, id: &str) -> Result<NcchBlob, DatabaseError> {
+        info!("getting blob of NCCH with id = {}", id);
+        match with_backend!(self, |connection| {
+            ncch_blob::table.find(id).first(connection)
+        }) {
+            Err(Error::NotFound) => Err(DatabaseError::NotFound),
+            Err(e) => {
+                error!("Database error: {}", e);
+                Err(DatabaseError::Other)
+            }
+            Ok(blob) => Ok(blob),
+        }
+    }
+
+    // Keyset pagination by NCCH ID, like query_ncch_batch
+    fn query_ncch_blobs(
+        &self,
+        after: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<NcchBlob>, DatabaseError> {
+        match with_backend!(self, |connection| {
+            let mut statement = ncch_blob::table.into_boxed();
+            if let Some(after) = after {
+                statement = statement.filter(ncch_blob::ncch_id.gt(after.to_owned()));
+            }
+            statement
+                .order_by(ncch_blob::ncch_id.asc())
+                .limit(limit)
+                .load(connection)
+        }) {
+            Err(e) => {
+                error!("Database error: {}", e);
+                Err(DatabaseError::Other)
+            }
+            Ok(blobs) => Ok(blobs),
+        }
+    }
+
+    // Compares the large icons, closest first. NCCHs without an icon have nothing similar. Every
+    // icon is compared, since no index helps with finding close Hamming distances.
+    fn query_similar_icons(
+        &self,
+        id: &str,
+        max_distance: u32,
+        limit: i64,
+    ) -> Result<Vec<(NcchRecord, u32)>, DatabaseError> {
+        info!("querying icons similar to NCCH with id = {}", id);
+        let dhash = match with_backend!(self, |connection| {
+            ncch::table
+                .find(id)
+                .left_join(icon::table.on(ncch::large_icon_hash.eq(icon::hash.nullable())))
+                .select(icon::dhash.nullable())
+                .first::<Option<i64>>(connection)
+        }) {
+            Err(Error::NotFound) => return Err(DatabaseError::NotFound),
+            Err(e) => {
+                error!("Database error: {}", e);
+                return Err(DatabaseError::Other);
+            }
+            Ok(Some(dhash)) => dhash,
+            Ok(None) => return Ok(vec![]),
+        };
+
+        let rows: Vec<(NcchRow, i32)> = match with_backend!(self, |connection, backend| {
+            use diesel::sql_types::{Bool, Integer};
+            let distance =
+                backend::hamming_distance("icon.dhash", &format!("CAST({} AS BIGINT)", dhash));
+            ncch::table
+                .inner_join(icon::table.on(ncch::large_icon_hash.eq(icon::hash.nullable())))
+                .filter(ncch::id.ne(id))
+                .filter(diesel::dsl::sql::<Bool>(&format!(
+                    "{} <= {}",
+                    distance, max_distance
+                )))
+                .select((ncch::all_columns, diesel::dsl::sql::<Integer>(&distance)))
+                .order_by(diesel::dsl::sql::<Integer>(&distance).asc())
+                .then_order_by(ncch::id.asc())
+                .limit(limit)
+                .load(connection)
+        }) {
+            Err(e) => {
+                error!("Database error: {}", e);
+                return Err(DatabaseError::Other);
+            }
+            Ok(rows) => rows,
+        };
+
+        let (ncchs, distances): (Vec<NcchRow>, Vec<i32>) = rows.into_iter().unzip();
+        match self.with_details(ncchs) {
+            Err(e) => {
+                error!("Database error: {}", e);
+                Err(DatabaseError::Other)
+            }
+            Ok(records) => Ok(records
+                .into_iter()
+                .zip(distances.into_iter().map(|d| d as u32))
+                .collect()),
         }
     }
 
-    pub fn query_ncch(&self, param: &NcchQueryParam) -> Result<Vec<NcchRecord>, DatabaseError> {
+    fn lookup_hash(&self, hash: &[u8]) -> Result<Vec<(NcchRecord, String)>, DatabaseError> {
+        info!("looking up NCCH by hash");
+        let rows: QueryResult<Vec<(NcchRow, String)>> = if hash.len() == 0x100 {
+            with_backend!(self, |connection| {
+                ncch::table
+                    .filter(ncch::ncch_signature.eq(hash))
+                    .order_by(ncch::id.asc())
+                    .load::<NcchRow>(connection)
+            })
+            .map(|rows| {
+                rows.into_iter()
+                    .map(|row| (row, "signature".to_owned()))
+                    .collect()
+            })
+        } else {
+            with_backend!(self, |connection| {
+                ncch::table
+                    .inner_join(ncch_hash::table)
+                    .filter(ncch_hash::hash.eq(hash))
+                    .select((ncch::all_columns, ncch_hash::kind))
+                    .order_by(ncch::id.asc())
+                    .then_order_by(ncch_hash::kind.asc())
+                    .load(connection)
+            })
+        };
+        let (ncchs, kinds): (Vec<NcchRow>, Vec<String>) = match rows {
+            Err(e) => {
+                error!("Database error: {}", e);
+                return Err(DatabaseError::Other);
+            }
+            Ok(rows) => rows.into_iter().unzip(),
+        };
+        match self.with_details(ncchs) {
+            Err(e) => {
+                error!("Database error: {}", e);
+                Err(DatabaseError::Other)
+            }
+            Ok(records) => Ok(records.into_iter().zip(kinds).collect()),
+        }
+    }
+
+    fn load_hashes(&self, records: &mut [NcchRecord]) -> Result<(), DatabaseError> {
+        let ids: Vec<&str> = records
+            .iter()
+            .map(|record| record.ncch.id.as_str())
+            .collect();
+        let mut hashes: Vec<NcchHash> = vec![];
+        for chunk in ids.chunks(BIND_LIMIT) {
+            match with_backend!(self, |connection| {
+                ncch_hash::table
+                    .filter(ncch_hash::ncch_id.eq_any(chunk))
+                    .order_by(ncch_hash::kind.asc())
+                    .load::<NcchHash>(connection)
+            }) {
+                Err(e) => {
+                    error!("Database error: {}", e);
+                    return Err(DatabaseError::Other);
+                }
+                Ok(chunk) => hashes.extend(chunk),
+            }
+        }
+        let mut hashes_by_id = std::collections::HashMap::<String, Vec<NcchHash>>::new();
+        for hash in hashes {
+            hashes_by_id
+                .entry(hash.ncch_id.clone())
+                .or_default()
+                .push(hash);
+        }
+        for record in records {
+            record.hashes = hashes_by_id.remove(&record.ncch.id).unwrap_or_default();
+        }
+        Ok(())
+    }
+
+    fn query_ncch_by_ids(
+        &self,
+        program_ids: &[u64],
+        ids: &[String],
+    ) -> Result<Vec<NcchRecord>, DatabaseError> {
+        info!(
+            "querying NCCH by {} program IDs and {} IDs",
+            program_ids.len(),
+            ids.len()
+        );
+        let program_ids: Vec<i64> = program_ids.iter().map(|&id| id as i64).collect();
+        let connection: &SqliteConnection = match &self.connection {
+            PooledBackend::Postgres(connection) => {
+                return postgres::query_ncch_by_ids(connection, &program_ids, ids).map_err(|e| {
+                    error!("Database error: {}", e);
+                    DatabaseError::Other
+                })
+            }
+            PooledBackend::Sqlite(connection) => connection,
+        };
+        let mut rows: Vec<NcchRow> = vec![];
+        let loaded = program_ids
+            .chunks(BIND_LIMIT)
+            .map(|chunk| {
+                ncch::table
+                    .filter(ncch::program_id.eq_any(chunk))
+                    .load::<NcchRow>(connection)
+            })
+            .chain(ids.chunks(BIND_LIMIT).map(|chunk| {
+                ncch::table
+                    .filter(ncch::id.eq_any(chunk))
+                    .load::<NcchRow>(connection)
+            }));
+        for chunk in loaded {
+            match chunk {
+                Err(e) => {
+                    error!("Database error: {}", e);
+                    return Err(DatabaseError::Other);
+                }
+                Ok(chunk) => rows.extend(chunk),
+            }
+        }
+        // A row can match both by program ID and by ID
+        rows.sort_by(|a, b| a.id.cmp(&b.id));
+        rows.dedup_by(|a, b| a.id == b.id);
+        self.with_details(rows).map_err(|e| {
+            error!("Database error: {}", e);
+            DatabaseError::Other
+        })
+    }
+
+    fn query_ncch(&self, param: &NcchQueryParam) -> Result<Vec<NcchRecord>, DatabaseError> {
         if param.limit < 1 || param.limit > 100 || param.offset < 0 {
             return Err(DatabaseError::InvalidParam);
         }
-        match filter_ncch(&param.filter)?
-            .order_by(ncch::program_id.asc())
-            .then_order_by(ncch::id.asc())
-            .limit(param.limit)
-            .offset(param.offset)
-            .load(&self.connection)
+        let title_order = param
+            .order_by_title
+            .as_ref()
+            .map(|language| title_language(language).ok_or(DatabaseError::InvalidParam))
+            .transpose()?
+            .map(|language| {
+                format!(
+                    "NULLIF((SELECT short_title FROM ncch_title WHERE ncch_title.ncch_id = ncch.id \
+                     AND ncch_title.language = {}), '')",
+                    language
+                )
+            });
+        match with_backend!(self, |connection, backend| {
+            let mut statement = backend::filter_ncch(&param.filter)?;
+            if let Some(title) = &title_order {
+                // Untitled NCCHs go last on both backends
+                statement = statement
+                    .order_by(diesel::dsl::sql::<diesel::sql_types::Bool>(&format!(
+                        "{} IS NULL",
+                        title
+                    )))
+                    .then_order_by(diesel::dsl::sql::<diesel::sql_types::Text>(title))
+                    .then_order_by(ncch::program_id.asc());
+            } else {
+                statement = statement.order_by(ncch::program_id.asc());
+            }
+            statement
+                .then_order_by(ncch::id.asc())
+                .limit(param.limit)
+                .offset(param.offset)
+                .load(connection)
+        })
+        .and_then(|ncchs| self.with_details(ncchs))
         {
             Err(e) => {
                 error!("Database error: {}", e);
@@ -861,11 +2849,124 @@ impl Connection {
         }
     }
 
-    pub fn query_ncch_count(&self, param: &NcchFilterParam) -> Result<i64, DatabaseError> {
-        match filter_ncch(param)?
-            .select(diesel::dsl::count(ncch::id))
-            .first(&self.connection)
+    // Keyset pagination by ID, used to walk the whole result set batch by batch.
+    fn query_ncch_batch(
+        &self,
+        filter: &NcchFilterParam,
+        after: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<NcchRecord>, DatabaseError> {
+        match with_backend!(self, |connection, backend| {
+            let mut statement = backend::filter_ncch(filter)?;
+            if let Some(after) = after {
+                statement = statement.filter(ncch::id.gt(after.to_owned()));
+            }
+            statement
+                .order_by(ncch::id.asc())
+                .limit(limit)
+                .load(connection)
+        })
+        .and_then(|ncchs| self.with_details(ncchs))
         {
+            Err(e) => {
+                error!("Database error: {}", e);
+                Err(DatabaseError::Other)
+            }
+            Ok(ncchs) => Ok(ncchs),
+        }
+    }
+
+    fn query_ncch_program_batch(
+        &self,
+        filter: &NcchFilterParam,
+        after: Option<(u64, &str)>,
+        limit: i64,
+    ) -> Result<Vec<NcchRecord>, DatabaseError> {
+        match with_backend!(self, |connection, backend| {
+            let mut statement = backend::filter_ncch(filter)?;
+            if let Some((program_id, id)) = after {
+                let program_id = program_id as i64;
+                statement = statement.filter(
+                    ncch::program_id.gt(program_id).or(ncch::program_id
+                        .eq(program_id)
+                        .and(ncch::id.gt(id.to_owned()))),
+                );
+            }
+            statement
+                .order_by((ncch::program_id.asc(), ncch::id.asc()))
+                .limit(limit)
+                .load(connection)
+        })
+        .and_then(|ncchs| self.with_details(ncchs))
+        {
+            Err(e) => {
+                error!("Database error: {}", e);
+                Err(DatabaseError::Other)
+            }
+            Ok(ncchs) => Ok(ncchs),
+        }
+    }
+
+    fn query_ncch_facets(&self, param: &NcchFilterParam) -> Result<NcchFacets, DatabaseError> {
+        let (
+            total,
+            content_is_data,
+            content_is_executable,
+            fixed_key,
+            no_romfs,
+            no_crypto,
+            seed_crypto,
+            sd_app,
+            enable_l2_cache,
+            high_cpu_speed,
+        ) = self.count_by_flags(param)?;
+        let flag = |count| flag_buckets(total, count);
+        Ok(NcchFacets {
+            content_is_data: flag(content_is_data),
+            content_is_executable: flag(content_is_executable),
+            fixed_key: flag(fixed_key),
+            no_romfs: flag(no_romfs),
+            no_crypto: flag(no_crypto),
+            seed_crypto: flag(seed_crypto),
+            sd_app: flag(sd_app),
+            enable_l2_cache: flag(enable_l2_cache),
+            high_cpu_speed: flag(high_cpu_speed),
+            platform: to_buckets(self.count_by(param, "platform")?),
+            content_category: to_buckets(self.count_by(param, "content_category")?),
+            region_lockout: self.count_by_bit(param, "region_lockout", 7)?,
+            maker_code: self.count_by_maker_code(param)?,
+        })
+    }
+
+    fn query_ncch_stats(&self, param: &NcchFilterParam) -> Result<NcchStats, DatabaseError> {
+        let (content_size, save_data_size) = with_backend!(self, |_connection, backend| {
+            (
+                backend::power_of_two_floor(
+                    "CAST(content_size AS BIGINT) * (512 << content_unit_size)",
+                ),
+                backend::power_of_two_floor("save_data_size"),
+            )
+        });
+
+        Ok(NcchStats {
+            count: self.query_ncch_count(param)?,
+            platform: to_buckets(self.count_by(param, "platform")?),
+            content_category: to_buckets(self.count_by(param, "content_category")?),
+            maker_code: self.count_by_maker_code(param)?,
+            region_lockout: self.count_by_bit(param, "region_lockout", 7)?,
+            system_mode: to_buckets(self.count_by(param, "system_mode")?),
+            core_version: to_buckets(self.count_by(param, "core_version")?),
+            content_size: to_buckets(self.count_by(param, &content_size)?),
+            save_data_size: to_buckets(self.count_by(param, &save_data_size)?),
+        })
+    }
+
+    fn query_ncch_count(&self, param: &NcchFilterParam) -> Result<i64, DatabaseError> {
+        match with_backend!(self, |connection, backend| {
+            backend::filter_ncch(param)?
+                .select(diesel::dsl::count(ncch::id))
+                .first(connection)
+        }) {
             Err(e) => {
                 error!("Database error: {}", e);
                 Err(DatabaseError::Other)
@@ -873,4 +2974,75 @@ impl Connection {
             Ok(count) => Ok(count),
         }
     }
+
+    fn replace_makers(&self, makers: &[MakerRow]) -> Result<(), DatabaseError> {
+        match with_backend!(self, |connection| {
+            connection.transaction(|| {
+                diesel::delete(maker::table).execute(connection)?;
+                diesel::insert_into(maker::table)
+                    .values(makers)
+                    .execute(connection)
+            })
+        }) {
+            Err(e) => {
+                error!("Database error: {}", e);
+                Err(DatabaseError::Other)
+            }
+            Ok(count) => {
+                info!("{} makers stored", count);
+                Ok(())
+            }
+        }
+    }
+
+    fn query_makers(&self) -> Result<Vec<Maker>, DatabaseError> {
+        let makers: Vec<MakerRow> = match with_backend!(self, |connection| {
+            maker::table
+                .order_by((maker::name.asc(), maker::code.asc()))
+                .load(connection)
+        }) {
+            Err(e) => {
+                error!("Database error: {}", e);
+                return Err(DatabaseError::Other);
+            }
+            Ok(makers) => makers,
+        };
+        let counts: std::collections::HashMap<Option<i64>, i64> = self
+            .count_by(&NcchFilterParam::default(), "maker_code")?
+            .into_iter()
+            .collect();
+        Ok(makers
+            .into_iter()
+            .map(|maker| Maker {
+                code: maker.code(),
+                count: counts.get(&Some(maker.code.into())).cloned().unwrap_or(0),
+                name: maker.name,
+            })
+            .collect())
+    }
+
+    fn replace_releases(&self, releases: &[ReleaseRow]) -> Result<(), DatabaseError> {
+        match with_backend!(self, |connection| {
+            connection.transaction::<_, Error, _>(|| {
+                diesel::delete(ncch_release::table).execute(connection)?;
+                let mut count = 0;
+                // Each row binds one parameter per column
+                for chunk in releases.chunks(BIND_LIMIT / RELEASE_COLUMNS) {
+                    count += diesel::insert_into(ncch_release::table)
+                        .values(chunk)
+                        .execute(connection)?;
+                }
+                Ok(count)
+            })
+        }) {
+            Err(e) => {
+                error!("Database error: {}", e);
+                Err(DatabaseError::Other)
+            }
+            Ok(count) => {
+                info!("{} releases stored", count);
+                Ok(())
+            }
+        }
+    }
 }
@@ -0,0 +1,83 @@
+use super::*;
+
+// The verified raw sections of an NCCH, each compressed with zlib. Exheader, ExeFS header and
+// SMDH are decrypted. Kept so that records can be derived again after the parser changes.
+#[derive(Queryable, Insertable, Debug, Clone)]
+#[table_name = "ncch_blob"]
+pub struct NcchBlob {
+    pub ncch_id: String,
+    header: Vec<u8>,
+    exheader: Option<Vec<u8>>,
+    exefs_header: Option<Vec<u8>>,
+    smdh: Option<Vec<u8>>,
+}
+
+pub struct NcchSections {
+    pub header: NcchHeader,
+    pub exheader: Option<Exheader>,
+    pub exefs_header: Option<ExefsHeader>,
+    pub smdh: Option<Smdh>,
+}
+
+fn compress<T: ByteStruct>(section: &T) -> Vec<u8> {
+    deflate::deflate_bytes_zlib(&section.to_bytes())
+}
+
+fn decompress(data: &[u8], len: usize) -> Result<Vec<u8>, String> {
+    let raw = inflate::inflate_bytes_zlib(data)?;
+    if raw.len() != len {
+        return Err(format!("expected {} bytes, got {}", len, raw.len()));
+    }
+    Ok(raw)
+}
+
+fn decompress_optional<T: ByteStruct>(data: &Option<Vec<u8>>) -> Result<Option<T>, String> {
+    data.as_ref()
+        .map(|data| Ok(T::read_bytes(&decompress(data, T::BYTE_LEN)?)))
+        .transpose()
+}
+
+impl NcchBlob {
+    pub fn new(
+        id: &str,
+        header: &NcchHeader,
+        exheader: Option<&Exheader>,
+        exefs_header: Option<&ExefsHeader>,
+        smdh: Option<&Smdh>,
+    ) -> NcchBlob {
+        NcchBlob {
+            ncch_id: id.to_owned(),
+            header: compress(header),
+            exheader: exheader.map(compress),
+            exefs_header: exefs_header.map(compress),
+            smdh: smdh.map(compress),
+        }
+    }
+
+    pub fn sections(&self) -> Result<NcchSections, String> {
+        Ok(NcchSections {
+            header: NcchHeader::read_bytes(&decompress(&self.header, NcchHeader::BYTE_LEN)?),
+            exheader: decompress_optional(&self.exheader)?,
+            exefs_header: decompress_optional(&self.exefs_header)?,
+            smdh: decompress_optional(&self.smdh)?,
+        })
+    }
+
+    pub fn raw_header(&self) -> Result<Vec<u8>, String> {
+        decompress(&self.header, NcchHeader::BYTE_LEN)
+    }
+
+    pub fn raw_exheader(&self) -> Result<Option<Vec<u8>>, String> {
+        self.exheader
+            .as_ref()
+            .map(|data| decompress(data, Exheader::BYTE_LEN))
+            .transpose()
+    }
+
+    pub fn raw_smdh(&self) -> Result<Option<Vec<u8>>, String> {
+        self.smdh
+            .as_ref()
+            .map(|data| decompress(data, Smdh::BYTE_LEN))
+            .transpose()
+    }
+}
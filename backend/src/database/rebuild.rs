@@ -0,0 +1,147 @@
+use super::*;
+
+// An SMDH or Exheader to download, either the raw section stored on upload or one rebuilt from
+// the stored fields for records without a blob
+pub struct Rebuilt {
+    pub data: Vec<u8>,
+    // Whether it is the section verified on upload. None if the hash is not known, as for records
+    // imported from a dump.
+    pub exact: Option<bool>,
+}
+
+fn copy_to<'a, T: 'a + Copy>(dst: impl IntoIterator<Item = &'a mut T>, src: &[T]) {
+    for (d, s) in dst.into_iter().zip(src) {
+        *d = *s;
+    }
+}
+
+fn utf16(s: &str) -> Vec<u16> {
+    s.encode_utf16().collect()
+}
+
+fn check(data: &[u8], hash: &Option<Vec<u8>>) -> Option<bool> {
+    hash.as_ref()
+        .map(|hash| Sha256::digest(data)[..] == hash[..])
+}
+
+// The hash in the NCCH header only covers the first 0x400 bytes, and the rest is covered by the
+// access control signature
+fn check_exheader(data: &[u8], hash: &Option<Vec<u8>>) -> Option<bool> {
+    check(&data[..0x400], hash).map(|exact| {
+        exact
+            && crate::rsa2048::verify_signature(
+                &data[0x500..],
+                &data[0x400..0x500],
+                &*crate::key::EXHEADER_PUBLIC_KEY,
+            )
+    })
+}
+
+impl NcchRecord {
+    // Checks a raw Exheader from the blob against the record
+    pub fn stored_exheader(&self, data: Vec<u8>) -> Rebuilt {
+        let exact = check_exheader(&data, &self.ncch.exheader_hash);
+        Rebuilt { data, exact }
+    }
+
+    // Checks a raw SMDH from the blob against the record
+    pub fn stored_smdh(&self, data: Vec<u8>) -> Rebuilt {
+        let exact = check(&data, &self.ncch.smdh_hash);
+        Rebuilt { data, exact }
+    }
+
+    // The version and the banner animation frame are not stored, and are left as zero
+    pub fn rebuild_smdh(
+        &self,
+        small_icon: Option<&Icon>,
+        large_icon: Option<&Icon>,
+    ) -> Option<Rebuilt> {
+        let ncch = &self.ncch;
+        ncch.smdh_flags?;
+        let mut smdh = Smdh::read_bytes(&[0; Smdh::BYTE_LEN]);
+        smdh.magic = *b"SMDH";
+        for title in &self.titles {
+            if let Some(dst) = smdh.title.get_mut(title.language as usize) {
+                copy_to(dst.short.iter_mut(), &utf16(&title.short_title));
+                copy_to(dst.long.iter_mut(), &utf16(&title.long_title));
+                copy_to(dst.publisher.iter_mut(), &utf16(&title.publisher));
+            }
+        }
+        if let Some(ratings) = &ncch.ratings {
+            let ratings: Vec<u8> = ratings.iter().map(|&r| r as u8).collect();
+            copy_to(smdh.ratings.iter_mut(), &ratings);
+        }
+        smdh.region_lockout = ncch.region_lockout.unwrap_or(0) as u32;
+        smdh.match_maker_id = ncch.match_maker_id.unwrap_or(0) as u32;
+        smdh.match_maker_bit_id = ncch.match_maker_bit_id.unwrap_or(0) as u64;
+        smdh.flags = ncch.smdh_flags.unwrap_or(0) as u32;
+        smdh.eula_version = ncch.eula_version.unwrap_or(0) as u16;
+        smdh.cec_id = ncch.cec_id.unwrap_or(0) as u32;
+        if let Some(icon) = small_icon {
+            copy_to(smdh.small_icon.iter_mut(), &icon.pixels());
+        }
+        if let Some(icon) = large_icon {
+            copy_to(smdh.large_icon.iter_mut(), &icon.pixels());
+        }
+
+        let data = smdh.to_bytes();
+        let exact = check(&data, &ncch.smdh_hash);
+        Some(Rebuilt { data, exact })
+    }
+
+    // The code set info, stack and BSS sizes, compression flag, signature, public key and access
+    // control limit are not stored and are left as zero, so a rebuilt Exheader can't be exact
+    // unless those happen to be zero in the original.
+    pub fn rebuild_exheader(&self) -> Option<Rebuilt> {
+        let ncch = &self.ncch;
+        let mut exheader = Exheader::read_bytes(&[0; Exheader::BYTE_LEN]);
+        copy_to(exheader.name.iter_mut(), ncch.exheader_name.as_ref()?);
+        exheader.system_control_flag.sd_app = ncch.sd_app.unwrap_or(false) as _;
+        exheader.remaster_version = ncch.remaster_version.unwrap_or(0) as u16;
+        if let Some(dependencies) = &ncch.dependencies {
+            let dependencies: Vec<u64> = dependencies.iter().map(|&d| d as u64).collect();
+            copy_to(exheader.dependencies.iter_mut(), &dependencies);
+        }
+        exheader.save_data_size = ncch.save_data_size.unwrap_or(0) as u64;
+        exheader.jump_id = ncch.jump_id.unwrap_or(0) as u64;
+
+        let access_control = &mut exheader.access_control;
+        access_control.program_id = ncch.exheader_program_id.unwrap_or(0) as u64;
+        access_control.core_version = ncch.core_version.unwrap_or(0) as u32;
+        let core_flag = &mut access_control.core_flag;
+        core_flag.enable_l2_cache = ncch.enable_l2_cache.unwrap_or(false) as _;
+        core_flag.high_cpu_speed = ncch.high_cpu_speed.unwrap_or(false) as _;
+        core_flag.n3ds_system_mode = ncch.n3ds_system_mode.unwrap_or(0) as _;
+        core_flag.ideal_processor = ncch.ideal_processor.unwrap_or(0) as _;
+        core_flag.affinity_mask = ncch.affinity_mask.unwrap_or(0) as _;
+        core_flag.system_mode = ncch.system_mode.unwrap_or(0) as _;
+        core_flag.priority = ncch.thread_priority.unwrap_or(0) as _;
+        if let Some(desc) = &ncch.resource_limit_desc {
+            let desc: Vec<u16> = desc.iter().map(|&d| d as u16).collect();
+            copy_to(access_control.resource_limit_desc.iter_mut(), &desc);
+        }
+        access_control.extdata_id = ncch.extdata_id.unwrap_or(0) as u64;
+        access_control.system_savedata_id = [
+            ncch.system_savedata_id0.unwrap_or(0) as u32,
+            ncch.system_savedata_id1.unwrap_or(0) as u32,
+        ];
+        access_control.storage_access_id = ncch.storage_access_id.unwrap_or(0) as u64;
+        access_control.filesystem_flag = ncch.filesystem_flag.unwrap_or(0) as u64;
+        if let Some(services) = &ncch.services {
+            for (dst, service) in access_control.services.iter_mut().zip(services.iter()) {
+                copy_to(dst.iter_mut(), service);
+            }
+        }
+        access_control.resource_limit_category = ncch.resource_limit_category.unwrap_or(0) as u8;
+        if let Some(desc) = &ncch.kernel_desc {
+            let desc: Vec<u32> = desc.iter().map(|&d| d as u32).collect();
+            copy_to(access_control.kernel_desc.iter_mut(), &desc);
+        }
+        access_control.arm9_flag = ncch.arm9_flag.unwrap_or(0) as u32;
+        access_control.arm9_flag_version = ncch.arm9_flag_version.unwrap_or(0) as u8;
+
+        let data = exheader.to_bytes();
+        let exact = check_exheader(&data, &ncch.exheader_hash);
+        Some(Rebuilt { data, exact })
+    }
+}
@@ -0,0 +1,199 @@
+use super::*;
+use diesel::sql_types::{Integer, Nullable, Text};
+
+// Applied in order on connect. PRAGMA user_version counts the scripts a database has already
+// been through, so that scripts altering tables run once. Scripts are never edited after they
+// ship; changes to the layout go into a new script.
+const SQLITE_SCHEMA: &[&str] = &[
+    include_str!("../../migrations_sqlite/2019-08-26-152815_init_post_ncch/up.sql"),
+    include_str!("../../migrations_sqlite/2019-10-01-000000_ncch_title/up.sql"),
+    include_str!("../../migrations_sqlite/2019-10-15-000000_icon/up.sql"),
+    include_str!("../../migrations_sqlite/2019-10-20-000000_icon_dhash/up.sql"),
+    include_str!("../../migrations_sqlite/2019-10-25-000000_section_hash/up.sql"),
+    include_str!("../../migrations_sqlite/2019-10-28-000000_ncch_blob/up.sql"),
+    include_str!("../../migrations_sqlite/2019-11-01-000000_maker/up.sql"),
+    include_str!("../../migrations_sqlite/2019-11-08-000000_ncch_release/up.sql"),
+    include_str!("../../migrations_sqlite/2019-11-15-000000_ncch_hash/up.sql"),
+    include_str!("../../migrations_sqlite/2019-11-29-000000_ncch_program_id_index/up.sql"),
+];
+
+// Databases before the ncch_title script kept the titles in the ncch table. The first SQLite
+// layout didn't set user_version, so those are at 0 as well as new databases.
+const NCCH_TITLE_VERSION: usize = 2;
+// Same for the icons, which were kept as pixel arrays before the icon script
+const ICON_VERSION: usize = 3;
+
+#[derive(QueryableByName)]
+struct UserVersion {
+    #[sql_type = "Integer"]
+    user_version: i32,
+}
+
+// Titles as stored by the first SQLite layout, as JSON arrays of the UTF-16 units of all 16
+// languages
+#[derive(QueryableByName)]
+struct StoredTitles {
+    #[sql_type = "Text"]
+    id: String,
+    #[sql_type = "Nullable<Text>"]
+    short_title: Option<String>,
+    #[sql_type = "Nullable<Text>"]
+    long_title: Option<String>,
+    #[sql_type = "Nullable<Text>"]
+    publisher: Option<String>,
+}
+
+// Decodes `len` units starting at `first`, up to the first NUL
+fn utf16_text(units: &[u16], first: usize, len: usize) -> String {
+    let units = units.get(first..first + len).unwrap_or(&[]);
+    let end = units.iter().position(|&c| c == 0).unwrap_or(units.len());
+    String::from_utf16_lossy(&units[..end])
+}
+
+// Icons as stored by the first SQLite layout, as JSON arrays of RGB565 pixels
+#[derive(QueryableByName)]
+struct StoredIcons {
+    #[sql_type = "Text"]
+    id: String,
+    #[sql_type = "Nullable<Text>"]
+    small_icon: Option<String>,
+    #[sql_type = "Nullable<Text>"]
+    large_icon: Option<String>,
+}
+
+fn units(json: &Option<String>) -> Vec<u16> {
+    json.as_ref()
+        .and_then(|json| serde_json::from_str::<Vec<i16>>(json).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|unit| unit as u16)
+        .collect()
+}
+
+fn convert_titles(connection: &SqliteConnection) -> QueryResult<()> {
+    let stored: Vec<StoredTitles> = diesel::sql_query(
+        "SELECT id, short_title, long_title, publisher FROM ncch WHERE short_title IS NOT NULL",
+    )
+    .load(connection)?;
+    let mut titles = vec![];
+    for row in stored {
+        let (short_title, long_title, publisher) = (
+            units(&row.short_title),
+            units(&row.long_title),
+            units(&row.publisher),
+        );
+        for language in 0..16 {
+            titles.push(NcchTitle {
+                ncch_id: row.id.clone(),
+                language: language as i16,
+                short_title: utf16_text(&short_title, language * 64, 64),
+                long_title: utf16_text(&long_title, language * 128, 128),
+                publisher: utf16_text(&publisher, language * 64, 64),
+            });
+        }
+    }
+    for chunk in titles.chunks(100) {
+        diesel::insert_or_ignore_into(ncch_title::table)
+            .values(chunk)
+            .execute(connection)?;
+    }
+    info!("converted the titles of {} NCCHs", titles.len() / 16);
+    Ok(())
+}
+
+fn convert_icons(connection: &SqliteConnection) -> QueryResult<()> {
+    let stored: Vec<StoredIcons> = diesel::sql_query(
+        "SELECT id, small_icon, large_icon FROM ncch \
+         WHERE small_icon IS NOT NULL OR large_icon IS NOT NULL",
+    )
+    .load(connection)?;
+    for row in &stored {
+        let icon = |json: &Option<String>| json.as_ref().map(|_| Icon::new(&units(json)));
+        let (small_icon, large_icon) = (icon(&row.small_icon), icon(&row.large_icon));
+        let icons: Vec<Icon> = small_icon.iter().chain(&large_icon).cloned().collect();
+        sqlite::insert_icons(connection, &icons)?;
+        diesel::update(ncch::table.find(&row.id))
+            .set((
+                ncch::small_icon_hash.eq(small_icon.map(|icon| icon.hash)),
+                ncch::large_icon_hash.eq(large_icon.map(|icon| icon.hash)),
+            ))
+            .execute(connection)?;
+    }
+    info!("converted the icons of {} NCCHs", stored.len());
+    Ok(())
+}
+
+// Brings the schema up to date, converting what the scripts can't do in SQL alone
+pub fn migrate(connection: &SqliteConnection) -> QueryResult<()> {
+    let version = diesel::sql_query("PRAGMA user_version")
+        .get_result::<UserVersion>(connection)?
+        .user_version as usize;
+    if version >= SQLITE_SCHEMA.len() {
+        return Ok(());
+    }
+    connection.transaction(|| {
+        for script in &SQLITE_SCHEMA[version..] {
+            connection.batch_execute(script)?;
+        }
+        if version < NCCH_TITLE_VERSION {
+            convert_titles(connection)?;
+        }
+        if version < ICON_VERSION {
+            convert_icons(connection)?;
+        }
+        connection.batch_execute(&format!("PRAGMA user_version = {}", SQLITE_SCHEMA.len()))
+    })
+}
+
+#[test]
+fn migrate_test() {
+    let connection = SqliteConnection::establish(":memory:").unwrap();
+    // A database of the first layout
+    connection.batch_execute(SQLITE_SCHEMA[0]).unwrap();
+    let mut short_title = vec![0i16; 64 * 16];
+    short_title[64] = 'A' as i16;
+    short_title[65] = 0xD83Cu16 as i16; // U+1F3AE as a surrogate pair
+    short_title[66] = 0xDFAEu16 as i16;
+    let large_icon = vec![0xF800u16 as i16; 48 * 48];
+    diesel::sql_query(
+        "INSERT INTO ncch (id, ncch_signature, content_size, partition_id, maker_code, \
+         ncch_verson, program_id, product_code, secondary_key_slot, platform, content_is_data, \
+         content_is_executable, content_category, content_unit_size, fixed_key, no_romfs, \
+         no_crypto, seed_crypto, short_title, long_title, publisher, large_icon, keyword) \
+         VALUES ('a', x'00', 0, 0, 0, 0, 0, x'00', 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, ?, '[]', '[]', ?, \
+         '')",
+    )
+    .bind::<Text, _>(serde_json::to_string(&short_title).unwrap())
+    .bind::<Text, _>(serde_json::to_string(&large_icon).unwrap())
+    .execute(&connection)
+    .unwrap();
+
+    migrate(&connection).unwrap();
+    let titles: Vec<NcchTitle> = ncch_title::table
+        .order_by(ncch_title::language.asc())
+        .load(&connection)
+        .unwrap();
+    assert_eq!(titles.len(), 16);
+    assert_eq!(titles[0].short_title, "");
+    assert_eq!(titles[1].short_title, "A\u{1F3AE}");
+    assert_eq!(titles[1].long_title, "");
+
+    let (small_icon_hash, large_icon_hash): (Option<String>, Option<String>) = ncch::table
+        .select((ncch::small_icon_hash, ncch::large_icon_hash))
+        .first(&connection)
+        .unwrap();
+    assert_eq!(small_icon_hash, None);
+    let icon: Icon = icon::table
+        .find(large_icon_hash.unwrap())
+        .first(&connection)
+        .unwrap();
+    assert_eq!(icon.pixels(), vec![0xF800; 48 * 48]);
+    assert!(icon.png.is_some());
+
+    // Nothing is applied twice
+    migrate(&connection).unwrap();
+    let version = diesel::sql_query("PRAGMA user_version")
+        .get_result::<UserVersion>(&connection)
+        .unwrap();
+    assert_eq!(version.user_version as usize, SQLITE_SCHEMA.len());
+}
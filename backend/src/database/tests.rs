@@ -0,0 +1,228 @@
+use super::*;
+use index3ds_formats::ByteStruct;
+
+// Every store is a separate in-memory database
+fn store() -> Database {
+    Database::connect_to("sqlite://:memory:")
+}
+
+// A record without Exheader or SMDH, whose partition ID is its program ID
+fn record(program_id: u64, edit: impl FnOnce(&mut NcchHeader)) -> NcchRecord {
+    let mut header = NcchHeader::read_bytes(&[0; NcchHeader::BYTE_LEN]);
+    header.partition_id = program_id;
+    header.program_id = program_id;
+    header.platform = 1;
+    header.maker_code = 0x3130;
+    edit(&mut header);
+    NcchRecord::new(header, None, None, None)
+}
+
+fn product_code(code: &str) -> impl FnOnce(&mut NcchHeader) + '_ {
+    move |header| header.product_code[..code.len()].copy_from_slice(code.as_bytes())
+}
+
+fn query(query: &str) -> NcchFilterParam {
+    NcchFilterParam {
+        query: Some(query.to_owned()),
+        ..NcchFilterParam::default()
+    }
+}
+
+// Two records, CTR-P-AXCE on the 3DS and KTR-N-AXCJ on the New 3DS
+fn two_records(store: &Database) -> (NcchRecord, NcchRecord) {
+    let a = record(0x0004000000030000, product_code("CTR-P-AXCE"));
+    let b = record(0x0004000000030100, |header| {
+        header.platform = 2;
+        product_code("KTR-N-AXCJ")(header);
+    });
+    store.insert_ncch_record(&a).unwrap();
+    store.insert_ncch_record(&b).unwrap();
+    (a, b)
+}
+
+#[test]
+fn insert_test() {
+    let store = store();
+    let (a, b) = two_records(&store);
+    assert!(matches!(
+        store.insert_ncch_record(&a),
+        Err(DatabaseError::Conflict)
+    ));
+    let found = store.get_ncch_record(&b.ncch.id).unwrap();
+    assert_eq!(found.ncch.platform, 2);
+    assert!(matches!(
+        store.get_ncch_record("0"),
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+#[test]
+fn update_test() {
+    let store = store();
+    let (a, _) = two_records(&store);
+    let mut info = a.to_ncch_info();
+    info.region_lockout = Some(1);
+    let imported = NcchRecord::from_ncch_info(&info).unwrap();
+    store.update_ncch_record(&imported).unwrap();
+    let found = store.get_ncch_record(&a.ncch.id).unwrap();
+    assert_eq!(found.to_ncch_info().region_lockout, Some(1));
+    // Fields the record doesn't have are cleared
+    store.update_ncch_record(&a).unwrap();
+    let found = store.get_ncch_record(&a.ncch.id).unwrap();
+    assert_eq!(found.to_ncch_info().region_lockout, None);
+    assert!(matches!(
+        store.update_ncch_record(&record(1, |_| ())),
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+#[test]
+fn query_test() {
+    let store = store();
+    two_records(&store);
+    let count = |q: &str| store.query_ncch_count(&query(q)).ok();
+    assert_eq!(count("platform:2"), Some(1));
+    assert_eq!(count("platform>0"), Some(2));
+    assert_eq!(count("NOT platform:2"), Some(1));
+    assert_eq!(count("30100"), Some(1));
+    // NULL never matches, even negated
+    assert_eq!(count("NOT system_mode:0"), Some(0));
+    assert_eq!(count("product_code:CTR-P-AXCE"), Some(1));
+    assert_eq!(count("product_code:*-axc?"), Some(2));
+    assert_eq!(count("NOT product_code:CTR-*"), Some(1));
+    assert!(matches!(
+        store.query_ncch_count(&query("foo:1")),
+        Err(DatabaseError::InvalidQuery(_))
+    ));
+}
+
+#[test]
+fn region_test() {
+    let store = store();
+    let (_, b) = two_records(&store);
+    let found = store.get_ncch_record(&b.ncch.id).unwrap();
+    assert_eq!(
+        found.to_ncch_info().region.as_ref().map(|s| s.as_str()),
+        Some("JPN")
+    );
+}
+
+#[test]
+fn facet_test() {
+    let store = store();
+    let (a, b) = two_records(&store);
+    for &(record, region) in &[(&a, 0b101), (&b, 0b100)] {
+        let mut info = record.to_ncch_info();
+        info.region_lockout = Some(region);
+        let imported = NcchRecord::from_ncch_info(&info).unwrap();
+        store.update_ncch_record(&imported).unwrap();
+    }
+    let facets = store
+        .query_ncch_facets(&NcchFilterParam::default())
+        .unwrap();
+    let counts: Vec<i64> = facets.region_lockout.iter().map(|b| b.count).collect();
+    assert_eq!(counts, vec![1, 0, 2, 0, 0, 0, 0]);
+    for flag in &[&facets.content_is_executable, &facets.sd_app] {
+        assert_eq!(flag.iter().map(|b| b.count).sum::<i64>(), 2);
+        assert!(flag.iter().all(|b| b.count > 0));
+    }
+}
+
+#[test]
+fn batch_test() {
+    let store = store();
+    let (a, b) = two_records(&store);
+    let batch = store
+        .query_ncch_batch(&NcchFilterParam::default(), Some(&a.ncch.id), 10)
+        .unwrap();
+    assert_eq!(batch.len(), 1);
+    assert_eq!(batch[0].ncch.id, b.ncch.id);
+
+    // A lower program ID with a higher NCCH ID comes first by program ID
+    let c = record(0x0004000000020000, |header| {
+        header.partition_id = u64::max_value()
+    });
+    store.insert_ncch_record(&c).unwrap();
+    let ids = |after: Option<(u64, &str)>| -> Vec<String> {
+        store
+            .query_ncch_program_batch(&NcchFilterParam::default(), after, 2)
+            .unwrap()
+            .into_iter()
+            .map(|record| record.ncch.id)
+            .collect()
+    };
+    assert_eq!(ids(None), vec![c.ncch.id.clone(), a.ncch.id.clone()]);
+    assert_eq!(ids(Some((0x0004000000030000, &a.ncch.id))), vec![b.ncch.id]);
+}
+
+#[test]
+fn maker_test() {
+    let store = store();
+    let (_, b) = two_records(&store);
+    store
+        .replace_makers(&[MakerRow::new("01", "Nintendo").unwrap()])
+        .unwrap();
+    let found = store.get_ncch_record(&b.ncch.id).unwrap();
+    assert_eq!(
+        found.maker_name.as_ref().map(|s| s.as_str()),
+        Some("Nintendo")
+    );
+    assert_eq!(
+        store.query_ncch_count(&query("maker_name:nin")).ok(),
+        Some(2)
+    );
+    assert_eq!(store.query_makers().unwrap()[0].count, 2);
+}
+
+#[test]
+fn release_test() {
+    let store = store();
+    let (a, _) = two_records(&store);
+    let release = Release {
+        serial: "CTR-P-AXCE".to_owned(),
+        name: "Great Game".to_owned(),
+        region: None,
+        publisher: None,
+        release_date: None,
+    };
+    store
+        .replace_releases(&[ReleaseRow::new(&a.ncch.id, release)])
+        .unwrap();
+    let count = |q: &str| store.query_ncch_count(&query(q)).ok();
+    assert_eq!(count("great"), Some(0));
+    assert_eq!(count("release:great"), Some(1));
+    assert_eq!(count("release:CTR-P-AXC?"), Some(1));
+    let found = store.get_ncch_record(&a.ncch.id).unwrap();
+    assert_eq!(found.releases.len(), 1);
+}
+
+#[test]
+fn lookup_hash_test() {
+    let store = store();
+    let (a, _) = two_records(&store);
+    let found = store.lookup_hash(&a.hashes[0].hash).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].0.ncch.id, a.ncch.id);
+    assert_eq!(found[0].1, "header");
+    // Both test records have an all-zero signature
+    assert_eq!(store.lookup_hash(&a.ncch.ncch_signature).unwrap().len(), 2);
+    assert!(store.lookup_hash(&[0; 32]).unwrap().is_empty());
+}
+
+#[test]
+fn lookup_batch_test() {
+    let store = store();
+    let (a, b) = two_records(&store);
+    let inputs: Vec<String> = vec![
+        "0004000000030100".to_owned(),
+        a.ncch.id.clone(),
+        "00040000000301FF".to_owned(),
+        "x".to_owned(),
+    ];
+    let batch = lookup_batch(&store, &inputs).unwrap();
+    assert_eq!(batch.len(), 4);
+    assert_eq!(batch["0004000000030100"][0].id, b.ncch.id);
+    assert_eq!(batch[&a.ncch.id][0].id, a.ncch.id);
+    assert!(batch["00040000000301FF"].is_empty());
+    assert!(batch["x"].is_empty());
+}
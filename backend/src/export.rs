@@ -0,0 +1,376 @@
+use crate::api::*;
+use crate::database::{Connection, DatabaseError, NcchRecord, NcchStore};
+use actix_web::error::BlockingError;
+use actix_web::web;
+use futures::{Async, Future, Poll, Stream};
+use log::error;
+use std::fmt::Display;
+
+const EXPORT_BATCH_SIZE: i64 = 500;
+
+const CSV_COLUMNS: &[&str] = &[
+    "id",
+    "ncch_signature",
+    "content_size",
+    "partition_id",
+    "maker_code",
+    "maker_name",
+    "ncch_version",
+    "program_id",
+    "product_code",
+    "secondary_key_slot",
+    "platform",
+    "content_is_data",
+    "content_is_executable",
+    "content_category",
+    "content_unit_size",
+    "fixed_key",
+    "no_romfs",
+    "no_crypto",
+    "seed_crypto",
+    "exheader_name",
+    "sd_app",
+    "remaster_version",
+    "dependencies",
+    "save_data_size",
+    "jump_id",
+    "exheader_program_id",
+    "core_version",
+    "enable_l2_cache",
+    "high_cpu_speed",
+    "system_mode",
+    "n3ds_system_mode",
+    "ideal_processor",
+    "affinity_mask",
+    "thread_priority",
+    "resource_limit_desc",
+    "extdata_id",
+    "system_savedata_id0",
+    "system_savedata_id1",
+    "storage_access_id",
+    "filesystem_flag",
+    "services",
+    "resource_limit_category",
+    "kernel_desc",
+    "arm9_flag",
+    "arm9_flag_version",
+    "ratings",
+    "region_lockout",
+    "region",
+    "match_maker_id",
+    "match_maker_bit_id",
+    "smdh_flags",
+    "eula_version",
+    "cec_id",
+    "release_serials",
+];
+
+const CSV_TITLE_COLUMNS: &[&str] = &["short_title", "long_title", "publisher"];
+const CSV_TITLE_COUNT: usize = 16;
+
+fn csv_escape(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+fn csv_line(fields: &[String]) -> String {
+    let mut line = fields
+        .iter()
+        .map(|f| csv_escape(f))
+        .collect::<Vec<_>>()
+        .join(",");
+    line.push_str("\r\n");
+    line
+}
+
+fn csv_header() -> String {
+    let mut fields: Vec<String> = CSV_COLUMNS.iter().map(|&c| c.to_owned()).collect();
+    for column in CSV_TITLE_COLUMNS {
+        for i in 0..CSV_TITLE_COUNT {
+            fields.push(format!("{}_{}", column, i));
+        }
+    }
+    csv_line(&fields)
+}
+
+fn csv_row(ncch: &NcchInfo) -> String {
+    fn opt<T: Display>(value: &Option<T>) -> String {
+        value.as_ref().map(|v| v.to_string()).unwrap_or_default()
+    }
+
+    fn list<T: Display>(value: &Option<Vec<T>>) -> String {
+        value
+            .as_ref()
+            .map(|v| {
+                v.iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .unwrap_or_default()
+    }
+
+    let mut fields = vec![
+        ncch.id.clone(),
+        ncch.ncch_signature
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect(),
+        ncch.content_size.to_string(),
+        ncch.partition_id.clone(),
+        ncch.maker_code.clone(),
+        opt(&ncch.maker_name),
+        ncch.ncch_version.to_string(),
+        ncch.program_id.clone(),
+        ncch.product_code.clone(),
+        ncch.secondary_key_slot.to_string(),
+        ncch.platform.to_string(),
+        ncch.content_is_data.to_string(),
+        ncch.content_is_executable.to_string(),
+        ncch.content_category.to_string(),
+        ncch.content_unit_size.to_string(),
+        ncch.fixed_key.to_string(),
+        ncch.no_romfs.to_string(),
+        ncch.no_crypto.to_string(),
+        ncch.seed_crypto.to_string(),
+        opt(&ncch.exheader_name),
+        opt(&ncch.sd_app),
+        opt(&ncch.remaster_version),
+        list(&ncch.dependencies),
+        opt(&ncch.save_data_size),
+        opt(&ncch.jump_id),
+        opt(&ncch.exheader_program_id),
+        opt(&ncch.core_version),
+        opt(&ncch.enable_l2_cache),
+        opt(&ncch.high_cpu_speed),
+        opt(&ncch.system_mode),
+        opt(&ncch.n3ds_system_mode),
+        opt(&ncch.ideal_processor),
+        opt(&ncch.affinity_mask),
+        opt(&ncch.thread_priority),
+        list(&ncch.resource_limit_desc),
+        opt(&ncch.extdata_id),
+        opt(&ncch.system_savedata_id0),
+        opt(&ncch.system_savedata_id1),
+        opt(&ncch.storage_access_id),
+        opt(&ncch.filesystem_flag),
+        list(&ncch.services),
+        opt(&ncch.resource_limit_category),
+        ncch.kernel_desc
+            .as_ref()
+            .map(|v| {
+                v.iter()
+                    .map(|x| format!("{:08x}", x))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .unwrap_or_default(),
+        opt(&ncch.arm9_flag),
+        opt(&ncch.arm9_flag_version),
+        list(&ncch.ratings),
+        opt(&ncch.region_lockout),
+        opt(&ncch.region),
+        opt(&ncch.match_maker_id),
+        opt(&ncch.match_maker_bit_id),
+        opt(&ncch.smdh_flags),
+        opt(&ncch.eula_version),
+        opt(&ncch.cec_id),
+        ncch.releases
+            .iter()
+            .map(|release| release.serial.as_str())
+            .collect::<Vec<_>>()
+            .join(" "),
+    ];
+
+    for titles in &[&ncch.short_title, &ncch.long_title, &ncch.publisher] {
+        for i in 0..CSV_TITLE_COUNT {
+            fields.push(
+                titles
+                    .as_ref()
+                    .and_then(|t| t.get(i).cloned())
+                    .unwrap_or_default(),
+            );
+        }
+    }
+
+    csv_line(&fields)
+}
+
+type Batch =
+    Box<dyn Future<Item = (Connection, Vec<NcchRecord>), Error = BlockingError<DatabaseError>>>;
+
+// Loads the batch after the given record, or the first one
+pub type BatchQuery = fn(
+    &Connection,
+    &NcchFilterParam,
+    Option<&NcchRecord>,
+) -> Result<Vec<NcchRecord>, DatabaseError>;
+
+// Batches of the records matching a filter, so that only one batch is held in memory at any time.
+// Batches after the first are fetched on the blocking thread pool, with the connection moved there
+// and back.
+pub struct RecordBatches {
+    connection: Option<Connection>,
+    filter: NcchFilterParam,
+    query: BatchQuery,
+    batch_size: i64,
+    pending: Option<Vec<NcchRecord>>,
+    fetching: Option<Batch>,
+    last: Option<NcchRecord>,
+    finished: bool,
+}
+
+impl RecordBatches {
+    // Fetches the first batch eagerly so that an invalid filter is reported before the response
+    // starts.
+    pub fn new(
+        connection: Connection,
+        filter: NcchFilterParam,
+        query: BatchQuery,
+        batch_size: i64,
+    ) -> Result<RecordBatches, DatabaseError> {
+        let first = query(&connection, &filter, None)?;
+        Ok(RecordBatches {
+            connection: Some(connection),
+            filter,
+            query,
+            batch_size,
+            pending: Some(first),
+            fetching: None,
+            last: None,
+            finished: false,
+        })
+    }
+
+    fn fetch(&mut self) -> Batch {
+        let connection = self.connection.take().expect("connection is busy");
+        let filter = self.filter.clone();
+        let query = self.query;
+        let last = self.last.take();
+        Box::new(web::block(move || {
+            query(&connection, &filter, last.as_ref()).map(|records| (connection, records))
+        }))
+    }
+
+    // The next batch, NotReady while it is being fetched, or None after the last one, which is
+    // shorter than the batch size
+    pub fn poll_next(&mut self) -> Poll<Option<Vec<NcchRecord>>, actix_web::Error> {
+        if self.finished {
+            return Ok(Async::Ready(None));
+        }
+        let records = match self.pending.take() {
+            Some(records) => records,
+            None => {
+                let mut fetching = match self.fetching.take() {
+                    Some(fetching) => fetching,
+                    None => self.fetch(),
+                };
+                match fetching.poll() {
+                    Ok(Async::Ready((connection, records))) => {
+                        self.connection = Some(connection);
+                        records
+                    }
+                    Ok(Async::NotReady) => {
+                        self.fetching = Some(fetching);
+                        return Ok(Async::NotReady);
+                    }
+                    Err(_) => {
+                        error!("failed to fetch a batch");
+                        self.finished = true;
+                        return Err(actix_web::error::ErrorInternalServerError(
+                            "export interrupted",
+                        ));
+                    }
+                }
+            }
+        };
+        if (records.len() as i64) < self.batch_size {
+            self.finished = true;
+        }
+        self.last = records.last().cloned();
+        Ok(Async::Ready(Some(records)))
+    }
+}
+
+// Streams all records matching the filter in batches ordered by ID
+pub struct ExportStream {
+    batches: RecordBatches,
+    format: ExportFormat,
+    header_sent: bool,
+}
+
+impl ExportStream {
+    pub fn new(
+        connection: Connection,
+        param: NcchExportParam,
+    ) -> Result<ExportStream, DatabaseError> {
+        let query: BatchQuery = |connection, filter, last| {
+            connection.query_ncch_batch(
+                filter,
+                last.map(|record| record.ncch.id.as_str()),
+                EXPORT_BATCH_SIZE,
+            )
+        };
+        Ok(ExportStream {
+            batches: RecordBatches::new(connection, param.filter, query, EXPORT_BATCH_SIZE)?,
+            format: param.format,
+            header_sent: false,
+        })
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self.format {
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+        }
+    }
+}
+
+impl Stream for ExportStream {
+    type Item = web::Bytes;
+    type Error = actix_web::Error;
+
+    fn poll(&mut self) -> Poll<Option<web::Bytes>, actix_web::Error> {
+        let records = match self.batches.poll_next()? {
+            Async::Ready(Some(records)) => records,
+            Async::Ready(None) => return Ok(Async::Ready(None)),
+            Async::NotReady => return Ok(Async::NotReady),
+        };
+
+        let mut buffer = String::new();
+        if !self.header_sent {
+            self.header_sent = true;
+            if self.format == ExportFormat::Csv {
+                buffer.push_str(&csv_header());
+            }
+        }
+
+        for record in &records {
+            let ncch_info = record.to_ncch_info();
+            match self.format {
+                ExportFormat::Ndjson => {
+                    match serde_json::to_string(&ncch_info) {
+                        Ok(line) => buffer.push_str(&line),
+                        Err(e) => {
+                            error!("failed to serialize NCCH info: {}", e);
+                            return Err(actix_web::error::ErrorInternalServerError(
+                                "export interrupted",
+                            ));
+                        }
+                    }
+                    buffer.push('\n');
+                }
+                ExportFormat::Csv => buffer.push_str(&csv_row(&ncch_info)),
+            }
+        }
+
+        if buffer.is_empty() {
+            Ok(Async::Ready(None))
+        } else {
+            Ok(Async::Ready(Some(web::Bytes::from(buffer))))
+        }
+    }
+}
@@ -0,0 +1,84 @@
+// Test data for the backend and, through the "fixture" feature, for the crates testing against it
+
+use index3ds_formats::*;
+use num_bigint_dig::{BigUint, ModInverse};
+use rsa::{hash, PaddingScheme, PublicKey, RSAPrivateKey};
+use sha2::*;
+
+// A throwaway RSA-2048 key standing in for the CFA signing key
+const P: &str = "d06a0517837763b24c2532555c20705b1f6c0e99b7d1c52a4d8445696531fe701ff3e59a505af975\
+                 ad6ace30e100e47d6b14a9c9be2b303e0bf66e377d6609c94e0230cc4cf8f3ab0290c7efa87441ef\
+                 0910484c208114e90bd72b4c5977c6e6d38975a1781fa210e13272323574451143e704c7702f87e9\
+                 9532509088e0c17b";
+const Q: &str = "e133b60d2359ab2a167559c439a63d60d83f6ce12e8e2bc6e1578a877b906f545ca5a7069ccbfb62\
+                 5b4c10c4d8281178a1ffb5f02db2939faf1863fb8ace10b0bf745c3364913c32e459637119ee4a76\
+                 61b0ebc80ed981b18fafa80fe3fc9649300ed4bda8dea2875b7767658abf5a52a25de9ec056d5bc9\
+                 6474946e0431529f";
+
+fn test_key() -> RSAPrivateKey {
+    let p = BigUint::parse_bytes(P.as_bytes(), 16).unwrap();
+    let q = BigUint::parse_bytes(Q.as_bytes(), 16).unwrap();
+    let e = BigUint::from(0x10001u32);
+    let phi = (&p - 1u32) * (&q - 1u32);
+    let d = e.clone().mod_inverse(&phi).unwrap().to_biguint().unwrap();
+    let key = RSAPrivateKey::from_components(&p * &q, e, d, vec![p, q]);
+    std::env::set_var(
+        "CFA_PUBLIC_KEY",
+        key.n()
+            .to_bytes_be()
+            .iter()
+            .map(u8::to_string)
+            .collect::<Vec<_>>()
+            .join(","),
+    );
+    key
+}
+
+// An unencrypted CFA with an ExeFS holding only the icon, signed by test_key. Build it before the
+// first verification, since the verifier loads CFA_PUBLIC_KEY once.
+pub fn fixture_ncch() -> Vec<u8> {
+    let mut smdh = Smdh::read_bytes(&[0; Smdh::BYTE_LEN][..]);
+    smdh.magic = *b"SMDH";
+    for (c, s) in "Fixture".encode_utf16().zip(smdh.title[1].short.iter_mut()) {
+        *s = c;
+    }
+    let smdh = smdh.to_bytes();
+
+    let mut exefs = ExefsHeader::read_bytes(&[0; ExefsHeader::BYTE_LEN][..]);
+    exefs.files[0].name[0..4].copy_from_slice(b"icon");
+    exefs.files[0].size = Smdh::BYTE_LEN as u32;
+    exefs.hashes[9].copy_from_slice(&Sha256::digest(&smdh));
+    let exefs = exefs.to_bytes();
+
+    let exefs_units = (ExefsHeader::BYTE_LEN + Smdh::BYTE_LEN + 0x1FF) / 0x200;
+    let mut header = NcchHeader::read_bytes(&[0; NcchHeader::BYTE_LEN][..]);
+    header.magic = *b"NCCH";
+    header.content_size = 1 + exefs_units as u32;
+    header.partition_id = 0x0004_0000_0fff_0000;
+    header.program_id = header.partition_id;
+    header.maker_code = u16::from_le_bytes(*b"01");
+    header.version = 2;
+    header.product_code[0..10].copy_from_slice(b"CTR-P-FXTR");
+    header.content_type.is_data = 1;
+    header.key_config.fixed_key = 1;
+    header.key_config.no_crypto = 1;
+    header.exefs_offset = 1;
+    header.exefs_size = exefs_units as u32;
+    header.exefs_hash_region_size = 1;
+    header.exefs_hash.copy_from_slice(&Sha256::digest(&exefs));
+    let signed = Sha256::digest(&header.to_bytes()[0x100..]);
+    let signature = test_key()
+        .sign(
+            PaddingScheme::PKCS1v15,
+            Some(&hash::Hashes::SHA2_256),
+            &signed,
+        )
+        .unwrap();
+    header.signature.copy_from_slice(&signature);
+
+    let mut ncch = header.to_bytes();
+    ncch.extend(exefs);
+    ncch.extend(smdh);
+    ncch.resize(0x200 * (1 + exefs_units), 0);
+    ncch
+}
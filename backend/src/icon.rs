@@ -0,0 +1,293 @@
+use crate::api::IconFilter;
+
+// SMDH icons are RGB565 pixels in 8x8 tiles, each tile in Morton order
+
+pub fn icon_width(pixel_count: usize) -> Option<usize> {
+    match pixel_count {
+        576 => Some(24),
+        2304 => Some(48),
+        _ => None,
+    }
+}
+
+pub struct Image {
+    width: usize,
+    height: usize,
+    rgba: Vec<u8>,
+}
+
+impl Image {
+    // Untiles the icon. Icons have no transparency, so alpha is always opaque.
+    pub fn decode(icon: &[u16]) -> Result<Image, String> {
+        let width =
+            icon_width(icon.len()).ok_or_else(|| format!("unexpected icon size {}", icon.len()))?;
+        let block_count = width / 8;
+
+        let mut buffer = Vec::with_capacity(width * width * 4);
+        let xlut = [0x00, 0x01, 0x04, 0x05, 0x10, 0x11, 0x14, 0x15];
+        let ylut = [0x00, 0x02, 0x08, 0x0a, 0x20, 0x22, 0x28, 0x2a];
+        fn convert5(v: u16) -> u8 {
+            ((v << 3) | (v >> 2)) as u8
+        }
+        fn convert6(v: u16) -> u8 {
+            ((v << 2) | (v >> 4)) as u8
+        }
+
+        for y in 0..width {
+            for x in 0..width {
+                let bx = x / 8;
+                let by = y / 8;
+                let cx = x % 8;
+                let cy = y % 8;
+                let i = xlut[cx] + ylut[cy] + (bx + by * block_count) * 64;
+                let pixel = icon[i];
+
+                let r = convert5(pixel >> 11);
+                let g = convert6((pixel >> 5) & 0b11_1111);
+                let b = convert5(pixel & 0b11111);
+                buffer.push(r);
+                buffer.push(g);
+                buffer.push(b);
+                buffer.push(0xFF);
+            }
+        }
+        Ok(Image {
+            width,
+            height: width,
+            rgba: buffer,
+        })
+    }
+
+    // Scales a square image
+    pub fn scale(&self, size: usize, filter: IconFilter) -> Image {
+        if size == self.width {
+            return Image {
+                width: size,
+                height: size,
+                rgba: self.rgba.clone(),
+            };
+        }
+
+        let ratio = self.width as f32 / size as f32;
+        let last = self.width - 1;
+        let pixel = |x: usize, y: usize| &self.rgba[(y * self.width + x) * 4..][..4];
+        let mut buffer = Vec::with_capacity(size * size * 4);
+        for y in 0..size {
+            for x in 0..size {
+                // Position of the destination pixel center in the source image
+                let sx = (x as f32 + 0.5) * ratio - 0.5;
+                let sy = (y as f32 + 0.5) * ratio - 0.5;
+                match filter {
+                    IconFilter::Nearest => {
+                        let nx = (sx.round().max(0.0) as usize).min(last);
+                        let ny = (sy.round().max(0.0) as usize).min(last);
+                        buffer.extend_from_slice(pixel(nx, ny));
+                    }
+                    IconFilter::Bilinear => {
+                        let sx = sx.max(0.0).min(last as f32);
+                        let sy = sy.max(0.0).min(last as f32);
+                        let (x0, y0) = (sx as usize, sy as usize);
+                        let (x1, y1) = ((x0 + 1).min(last), (y0 + 1).min(last));
+                        let (fx, fy) = (sx - x0 as f32, sy - y0 as f32);
+                        for c in 0..4 {
+                            let top =
+                                pixel(x0, y0)[c] as f32 * (1.0 - fx) + pixel(x1, y0)[c] as f32 * fx;
+                            let bottom =
+                                pixel(x0, y1)[c] as f32 * (1.0 - fx) + pixel(x1, y1)[c] as f32 * fx;
+                            buffer.push((top * (1.0 - fy) + bottom * fy).round() as u8);
+                        }
+                    }
+                }
+            }
+        }
+        Image {
+            width: size,
+            height: size,
+            rgba: buffer,
+        }
+    }
+
+    // Packs same-sized images left to right, top to bottom, and returns the sheet along with
+    // the position of each image
+    pub fn sheet(images: &[Image], columns: usize) -> (Image, Vec<(usize, usize)>) {
+        let (cell_width, cell_height) = match images.first() {
+            Some(image) => (image.width, image.height),
+            None => {
+                let empty = Image {
+                    width: 0,
+                    height: 0,
+                    rgba: vec![],
+                };
+                return (empty, vec![]);
+            }
+        };
+        let columns = columns.min(images.len()).max(1);
+        let rows = (images.len() - 1) / columns + 1;
+        let width = cell_width * columns;
+        let height = cell_height * rows;
+
+        let mut rgba = vec![0; width * height * 4];
+        let mut positions = Vec::with_capacity(images.len());
+        for (i, image) in images.iter().enumerate() {
+            let x = i % columns * cell_width;
+            let y = i / columns * cell_height;
+            for (row, line) in image.rgba.chunks(image.width * 4).enumerate() {
+                let start = ((y + row) * width + x) * 4;
+                rgba[start..start + line.len()].copy_from_slice(line);
+            }
+            positions.push((x, y));
+        }
+        (
+            Image {
+                width,
+                height,
+                rgba,
+            },
+            positions,
+        )
+    }
+
+    // Difference hash: shrinks the grayscale image to 9x8 by averaging, and sets one bit for each
+    // pixel that is darker than its right neighbour
+    pub fn dhash(&self) -> u64 {
+        let mut gray = [[0u32; 9]; 8];
+        for (y, row) in gray.iter_mut().enumerate() {
+            let (top, bottom) = (y * self.height / 8, (y + 1) * self.height / 8);
+            for (x, cell) in row.iter_mut().enumerate() {
+                let (left, right) = (x * self.width / 9, (x + 1) * self.width / 9);
+                let mut sum = 0;
+                for sy in top..bottom {
+                    for sx in left..right {
+                        let p = &self.rgba[(sy * self.width + sx) * 4..];
+                        sum += p[0] as u32 * 299 + p[1] as u32 * 587 + p[2] as u32 * 114;
+                    }
+                }
+                *cell = sum / ((bottom - top) * (right - left)).max(1) as u32;
+            }
+        }
+
+        let mut hash = 0;
+        for row in gray.iter() {
+            for x in 0..8 {
+                hash = (hash << 1) | (row[x] < row[x + 1]) as u64;
+            }
+        }
+        hash
+    }
+
+    pub fn png(&self) -> Result<Vec<u8>, String> {
+        let mut encoded = std::io::Cursor::new(Vec::new());
+        let mut encoder = png::Encoder::new(&mut encoded, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .map_err(|e| format!("PNG write_header error: {}", e))?
+            .write_image_data(&self.rgba)
+            .map_err(|e| format!("PNG write_image_data error: {}", e))?;
+        Ok(encoded.into_inner())
+    }
+
+    // 32-bit uncompressed BMP, stored bottom-up
+    pub fn bmp(&self) -> Vec<u8> {
+        let pixel_len = self.rgba.len() as u32;
+        let mut bmp = Vec::with_capacity(54 + pixel_len as usize);
+        // BITMAPFILEHEADER
+        bmp.extend_from_slice(b"BM");
+        bmp.extend_from_slice(&(54 + pixel_len).to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes());
+        bmp.extend_from_slice(&54u32.to_le_bytes());
+        // BITMAPINFOHEADER
+        bmp.extend_from_slice(&40u32.to_le_bytes());
+        bmp.extend_from_slice(&(self.width as i32).to_le_bytes());
+        bmp.extend_from_slice(&(self.height as i32).to_le_bytes());
+        bmp.extend_from_slice(&1u16.to_le_bytes());
+        bmp.extend_from_slice(&32u16.to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes()); // BI_RGB
+        bmp.extend_from_slice(&pixel_len.to_le_bytes());
+        bmp.extend_from_slice(&2835u32.to_le_bytes()); // 72 DPI
+        bmp.extend_from_slice(&2835u32.to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes());
+        for row in self.rgba.chunks(self.width * 4).rev() {
+            for pixel in row.chunks(4) {
+                bmp.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+            }
+        }
+        bmp
+    }
+
+    // An ICO with a single PNG image, which is supported since Windows Vista. At most 256 pixels.
+    pub fn ico(&self) -> Result<Vec<u8>, String> {
+        if self.width > 256 || self.height != self.width {
+            return Err(format!(
+                "ICO can't be {}x{} pixels",
+                self.width, self.height
+            ));
+        }
+        let png = self.png()?;
+        // 0 means 256 in ICONDIRENTRY
+        let width = (self.width % 256) as u8;
+        let mut ico = Vec::with_capacity(22 + png.len());
+        // ICONDIR
+        ico.extend_from_slice(&0u16.to_le_bytes());
+        ico.extend_from_slice(&1u16.to_le_bytes());
+        ico.extend_from_slice(&1u16.to_le_bytes());
+        // ICONDIRENTRY
+        ico.extend_from_slice(&[width, width, 0, 0]);
+        ico.extend_from_slice(&1u16.to_le_bytes());
+        ico.extend_from_slice(&32u16.to_le_bytes());
+        ico.extend_from_slice(&(png.len() as u32).to_le_bytes());
+        ico.extend_from_slice(&22u32.to_le_bytes());
+        ico.extend_from_slice(&png);
+        Ok(ico)
+    }
+}
+
+pub fn encode_png(icon: &[u16]) -> Result<Vec<u8>, String> {
+    Image::decode(icon)?.png()
+}
+
+#[test]
+fn scale_test() {
+    let image = Image::decode(&[0xF800; 576]).unwrap();
+    for &filter in &[IconFilter::Nearest, IconFilter::Bilinear] {
+        let scaled = image.scale(100, filter);
+        assert_eq!(scaled.rgba.len(), 100 * 100 * 4);
+        assert!(scaled.rgba.chunks(4).all(|p| p == [0xFF, 0, 0, 0xFF]));
+    }
+}
+
+#[test]
+fn format_test() {
+    let image = Image::decode(&[0xF800; 576]).unwrap();
+    assert_eq!(&image.png().unwrap()[..4], b"\x89PNG");
+    assert_eq!(image.bmp().len(), 54 + 24 * 24 * 4);
+    assert_eq!(&image.ico().unwrap()[22..26], b"\x89PNG");
+    assert!(image.scale(257, IconFilter::Nearest).ico().is_err());
+}
+
+#[test]
+fn sheet_test() {
+    let image = Image::decode(&[0xF800; 576]).unwrap();
+    let images: Vec<Image> = (0..5)
+        .map(|_| image.scale(24, IconFilter::Nearest))
+        .collect();
+    let (sheet, positions) = Image::sheet(&images, 2);
+    assert_eq!((sheet.width, sheet.height), (48, 72));
+    assert_eq!(positions[3], (24, 24));
+    // The cell after the last image is left transparent
+    assert_eq!(&sheet.rgba[(71 * 48 + 23) * 4..][..4], &[0xFF, 0, 0, 0xFF]);
+    assert_eq!(&sheet.rgba[(71 * 48 + 24) * 4..][..4], &[0, 0, 0, 0]);
+    assert_eq!(Image::sheet(&[], 2).1, vec![]);
+}
+
+#[test]
+fn dhash_test() {
+    // Flat colors have no edges to hash, while vertical stripes do
+    let gradient: Vec<u16> = (0..2304)
+        .map(|i| (i & 0x5 == 0x5) as u16 * 0xFFFF)
+        .collect();
+    assert_eq!(Image::decode(&[0x1234; 2304]).unwrap().dhash(), 0);
+    assert_ne!(Image::decode(&gradient).unwrap().dhash(), 0);
+}
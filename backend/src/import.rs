@@ -0,0 +1,70 @@
+use crate::api::*;
+use crate::database::{Database, DatabaseError, NcchRecord, NcchStore};
+use log::{error, info, warn};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+#[derive(Debug, Default)]
+struct ImportSummary {
+    inserted: usize,
+    updated: usize,
+    skipped: usize,
+    failed: usize,
+}
+
+// Imports an NDJSON dump produced by /export. Existing records are skipped, or overwritten if
+// upgrade is set.
+pub fn import(database: &Database, path: &str, upgrade: bool) -> std::io::Result<()> {
+    let file = BufReader::new(File::open(path)?);
+    let connection = database
+        .get_connection()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
+
+    let mut summary = ImportSummary::default();
+    for (i, line) in file.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record = match serde_json::from_str::<NcchInfo>(&line)
+            .map_err(|e| e.to_string())
+            .and_then(|info| NcchRecord::from_ncch_info(&info))
+        {
+            Ok(record) => record,
+            Err(e) => {
+                error!("line {}: {}", i + 1, e);
+                summary.failed += 1;
+                continue;
+            }
+        };
+
+        match connection.insert_ncch_record(&record) {
+            Ok(()) => summary.inserted += 1,
+            Err(DatabaseError::Conflict) if upgrade => {
+                match connection.update_ncch_record(&record) {
+                    Ok(()) => summary.updated += 1,
+                    Err(_) => {
+                        error!("line {}: failed to update {}", i + 1, record.ncch.id);
+                        summary.failed += 1;
+                    }
+                }
+            }
+            Err(DatabaseError::Conflict) => {
+                warn!("line {}: skipping existing {}", i + 1, record.ncch.id);
+                summary.skipped += 1;
+            }
+            Err(_) => {
+                error!("line {}: failed to insert {}", i + 1, record.ncch.id);
+                summary.failed += 1;
+            }
+        }
+    }
+
+    info!("import finished: {:?}", summary);
+    println!(
+        "inserted {}, updated {}, skipped {}, failed {}",
+        summary.inserted, summary.updated, summary.skipped, summary.failed
+    );
+    Ok(())
+}
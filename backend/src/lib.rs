@@ -0,0 +1,858 @@
+#![recursion_limit = "128"]
+
+mod aes;
+mod api;
+mod dat;
+pub mod database;
+mod export;
+#[cfg(any(test, feature = "fixture"))]
+pub mod fixture;
+mod icon;
+pub mod import;
+mod key;
+pub mod maker;
+pub mod offline;
+pub mod release;
+pub mod reprocess;
+mod rsa2048;
+mod schema;
+mod sql_types;
+mod verify;
+
+#[macro_use]
+extern crate diesel;
+
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use api::*;
+use database::{Database, DatabaseError, Icon, IconSize, NcchBlob, NcchStore};
+use log::{error, info, warn};
+use rand::prelude::*;
+use std::collections::HashMap;
+use std::mem::drop;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use verify::{NcchVerifier, NcchVerifierStep};
+
+// Out of the 64 bits of the icon hash
+const MAX_SIMILAR_ICON_DISTANCE: u32 = 12;
+const SIMILAR_ICON_LIMIT: i64 = 20;
+
+// An upload in progress, which hands the regions sent by the client to the verifier and stores
+// the verified record
+#[derive(Debug)]
+struct PostNcchSession {
+    id: u32,
+    store: Arc<dyn NcchStore + Send + Sync>,
+    last_touch: Instant,
+    verifier: NcchVerifier,
+}
+
+fn respond_with_icon(request: &HttpRequest, icon: Icon, param: &IconParam) -> HttpResponse {
+    let format = param.format.unwrap_or(IconFormat::Png);
+    let filter = param.filter.unwrap_or(IconFilter::Nearest);
+    let size = match param.size.as_ref().map(|size| size.value()) {
+        None => None,
+        Some(Some(size)) if (1..=MAX_ICON_SIZE).contains(&size) => Some(size as usize),
+        Some(_) => {
+            warn!("invalid icon size");
+            return NcchInfoResponse::InvalidParam.http();
+        }
+    };
+
+    // Icons are keyed by content hash, so the hash and the options make a strong ETag
+    let etag = match (format, size) {
+        (IconFormat::Png, None) => format!("\"{}\"", icon.hash),
+        (IconFormat::Raw, _) => format!("\"{}-raw\"", icon.hash),
+        (format, size) => format!(
+            "\"{}-{:?}-{}-{:?}\"",
+            icon.hash,
+            format,
+            size.map_or("native".to_owned(), |size| size.to_string()),
+            filter
+        )
+        .to_lowercase(),
+    };
+    let cache_control = "public, max-age=31536000";
+    let not_modified = request
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |value| {
+            value
+                .split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        });
+    if not_modified {
+        return HttpResponse::NotModified()
+            .header(header::ETAG, etag)
+            .header(header::CACHE_CONTROL, cache_control)
+            .finish();
+    }
+
+    let content_type = match format {
+        IconFormat::Png => "image/png",
+        IconFormat::Bmp => "image/bmp",
+        IconFormat::Ico => "image/x-icon",
+        IconFormat::Raw => "application/octet-stream",
+    };
+    let body = match (format, size) {
+        (IconFormat::Raw, _) => Ok(icon.data),
+        // Served from the PNG encoded on insert
+        (IconFormat::Png, None) => icon
+            .png
+            .ok_or_else(|| format!("icon {} has no PNG", icon.hash)),
+        (format, size) => icon::Image::decode(&icon.pixels()).and_then(|image| {
+            let image = match size {
+                Some(size) => image.scale(size, filter),
+                None => image,
+            };
+            match format {
+                IconFormat::Bmp => Ok(image.bmp()),
+                IconFormat::Ico => image.ico(),
+                _ => image.png(),
+            }
+        }),
+    };
+    match body {
+        Ok(body) => HttpResponse::Ok()
+            .content_type(content_type)
+            .header(header::ETAG, etag)
+            .header(header::CACHE_CONTROL, cache_control)
+            .body(body),
+        Err(e) => {
+            error!("{}", e);
+            NcchInfoResponse::InternalServerError.http()
+        }
+    }
+}
+
+// The original bytes of a section, if the NCCH was uploaded after blobs started being stored,
+// checked against the hashes of the record by `check`
+fn stored_section(
+    connection: &database::Connection,
+    record: &database::NcchRecord,
+    section: fn(&NcchBlob) -> Result<Option<Vec<u8>>, String>,
+    check: fn(&database::NcchRecord, Vec<u8>) -> database::Rebuilt,
+) -> Result<Option<database::Rebuilt>, HttpResponse> {
+    let blob = match connection.get_ncch_blob(&record.ncch.id) {
+        Ok(blob) => blob,
+        Err(DatabaseError::NotFound) => return Ok(None),
+        Err(_) => {
+            error!("unhandled error when getting NCCH blob");
+            return Err(NcchInfoResponse::InternalServerError.http());
+        }
+    };
+    match section(&blob) {
+        Ok(data) => Ok(data.map(|data| check(record, data))),
+        Err(e) => {
+            error!("{}", e);
+            Err(NcchInfoResponse::InternalServerError.http())
+        }
+    }
+}
+
+// X-Rebuild tells whether the download is the section verified on upload: exact, inexact, or
+// unknown for records that were imported without the hashes. Stored sections are checked again,
+// and sections rebuilt from the fields are usually inexact.
+fn respond_with_rebuilt(
+    ncch_id: &str,
+    file_name: &str,
+    rebuilt: Option<database::Rebuilt>,
+) -> HttpResponse {
+    let rebuilt = match rebuilt {
+        Some(rebuilt) => rebuilt,
+        None => {
+            warn!("NCCH has no {}", file_name);
+            return NcchInfoResponse::NotFound.http();
+        }
+    };
+    let verification = match rebuilt.exact {
+        Some(true) => "exact",
+        Some(false) => "inexact",
+        None => "unknown",
+    };
+    HttpResponse::Ok()
+        .content_type("application/octet-stream")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.{}\"", ncch_id, file_name),
+        )
+        .header("X-Rebuild", verification)
+        .body(rebuilt.data)
+}
+
+// Lays out the small icons of the records for icon_sheet.png, each distinct icon once in the
+// order of the records. This is the layout Image::sheet packs them in.
+fn icon_sheet_layout(records: &[database::NcchRecord]) -> NcchIconSheet {
+    let icon_size = 24;
+    let mut hashes: Vec<&str> = vec![];
+    let mut icons = vec![];
+    for record in records {
+        let hash = match &record.ncch.small_icon_hash {
+            Some(hash) => hash.as_str(),
+            None => continue,
+        };
+        let index = match hashes.iter().position(|&h| h == hash) {
+            Some(index) => index,
+            None => {
+                hashes.push(hash);
+                hashes.len() - 1
+            }
+        } as u32;
+        icons.push(IconOffset {
+            ncch_id: record.ncch.id.clone(),
+            x: index % ICON_SHEET_COLUMNS * icon_size,
+            y: index / ICON_SHEET_COLUMNS * icon_size,
+        });
+    }
+    let image = if hashes.is_empty() {
+        String::new()
+    } else {
+        format!("{}?icons={}", url::icon_sheet(), hashes.join(","))
+    };
+    NcchIconSheet {
+        image,
+        icon_size,
+        icons,
+    }
+}
+
+// Packs the small icons into one PNG in the order of the hashes, or None if one of them is not a
+// stored small icon
+fn build_icon_sheet(hashes: &[String], icons: Vec<Icon>) -> Result<Option<Vec<u8>>, String> {
+    let icons: HashMap<String, Icon> = icons
+        .into_iter()
+        .map(|icon| (icon.hash.clone(), icon))
+        .collect();
+    let mut images = vec![];
+    for hash in hashes {
+        let pixels = match icons.get(hash) {
+            Some(icon) => icon.pixels(),
+            None => return Ok(None),
+        };
+        if icon::icon_width(pixels.len()) != Some(24) {
+            return Ok(None);
+        }
+        images.push(icon::Image::decode(&pixels)?);
+    }
+    let (sheet, _) = icon::Image::sheet(&images, ICON_SHEET_COLUMNS as usize);
+    sheet.png().map(Some)
+}
+
+impl PostNcchSession {
+    pub fn new(id: u32, store: Arc<dyn NcchStore + Send + Sync>) -> PostNcchSession {
+        PostNcchSession {
+            id,
+            store,
+            last_touch: Instant::now(),
+            verifier: NcchVerifier::new(),
+        }
+    }
+
+    fn finalize(&mut self, record: database::NcchRecord) -> PostNcchResponse {
+        match self.store.insert_ncch_record(&record) {
+            Ok(()) => PostNcchResponse::Finished(NcchExist {
+                ncch_id: record.ncch.id,
+            }),
+            Err(DatabaseError::Conflict) => PostNcchResponse::Conflict(NcchExist {
+                ncch_id: record.ncch.id,
+            }),
+            Err(_) => PostNcchResponse::InternalServerError,
+        }
+    }
+
+    pub fn next(&mut self, data: web::Bytes) -> HttpResponse {
+        self.last_touch = Instant::now();
+        match self.verifier.next(&data) {
+            NcchVerifierStep::Need(offset, len) => PostNcchResponse::AppendNeeded(AppendRequest {
+                session_id: self.id,
+                offset,
+                len,
+            }),
+            NcchVerifierStep::Verified(record) => self.finalize(*record),
+            NcchVerifierStep::Failed(response) => response,
+        }
+        .http()
+    }
+
+    pub fn finished(&self) -> bool {
+        self.verifier.finished()
+    }
+
+    pub fn last_touch(&self) -> Instant {
+        self.last_touch
+    }
+}
+
+// A SHA-256 or an NCCH signature in hex
+fn parse_lookup_hash(hash: &str) -> Option<Vec<u8>> {
+    // from_str_radix alone would take a sign
+    if (hash.len() != 64 && hash.len() != 0x200) || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hash.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hash[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[test]
+fn parse_lookup_hash_test() {
+    let hash = "00".repeat(32);
+    assert_eq!(parse_lookup_hash(&hash), Some(vec![0; 32]));
+    assert_eq!(parse_lookup_hash(&format!("+f{}", &hash[2..])), None);
+    assert_eq!(parse_lookup_hash(&format!("0g{}", &hash[2..])), None);
+    assert_eq!(parse_lookup_hash(&hash[2..]), None);
+}
+
+// The upload sessions in progress, shared by the workers of the server
+pub struct Sessions {
+    map: RwLock<HashMap<u32, Arc<Mutex<PostNcchSession>>>>,
+    max_count: usize,
+    cleanup_period: Duration,
+}
+
+impl Sessions {
+    pub fn new(max_count: usize, cleanup_period: Duration) -> Sessions {
+        Sessions {
+            map: RwLock::new(HashMap::new()),
+            max_count,
+            cleanup_period,
+        }
+    }
+
+    // Drops the sessions that finished or have been idle for the cleanup period
+    pub fn cleanup(&self) {
+        let mut session_map = self.map.write().unwrap();
+        session_map.retain(|_, session| {
+            if let Ok(session) = session.try_lock() {
+                (!session.finished() && session.last_touch().elapsed() < self.cleanup_period)
+            } else {
+                true
+            }
+        })
+    }
+}
+
+// Registers the API routes. The pages and the static files are left to the caller, so that tests
+// can run the API without them.
+pub fn configure(
+    config: &mut web::ServiceConfig,
+    database_root: &Arc<Database>,
+    sessions_root: &Arc<Sessions>,
+) {
+    let sessions = sessions_root.clone();
+    let database = database_root.clone();
+    let post_ncch = move |ncch_header: web::Bytes| {
+        info!("post_ncch called");
+        info!("ncch_header.len = {}", ncch_header.len());
+        let mut session_map = {
+            let session_map_write = sessions.map.write().unwrap();
+            if session_map_write.len() <= sessions.max_count {
+                session_map_write
+            } else {
+                warn!("Session capacity reached! Try cleaning up");
+                drop(session_map_write);
+                sessions.cleanup();
+                let session_map_write = sessions.map.write().unwrap();
+                if session_map_write.len() <= sessions.max_count {
+                    session_map_write
+                } else {
+                    error!("Session capacity reached and could not reduce!");
+                    return PostNcchResponse::Busy.http();
+                }
+            }
+        };
+
+        let session_id = loop {
+            let session_id = random();
+            if !session_map.contains_key(&session_id) {
+                break session_id;
+            }
+        };
+
+        let session = Arc::new(Mutex::new(PostNcchSession::new(
+            session_id,
+            database.clone(),
+        )));
+        session_map.insert(session_id, session.clone());
+        drop(session_map);
+
+        let mut session = session.lock().unwrap();
+        session.next(ncch_header)
+    };
+
+    let sessions = sessions_root.clone();
+    let append_ncch = move |path: web::Path<(u32,)>, data: web::Bytes| {
+        info!("append_ncch called");
+        let session_id = path.0;
+        info!("session_id = {}, data.len = {}", session_id, data.len());
+        let session_map = sessions.map.read().unwrap();
+        let session = session_map.get(&session_id).cloned();
+        drop(session_map);
+        if let Some(session) = session {
+            let mut session = session.lock().unwrap();
+            session.next(data)
+        } else {
+            PostNcchResponse::NotFound.http()
+        }
+    };
+
+    let database = database_root.clone();
+    let ncch_info = move |request: HttpRequest,
+                          path: web::Path<(String, String)>,
+                          param: web::Query<IconParam>| {
+        info!("ncch_info called");
+        let ncch_id = &path.0;
+        let info_type: &str = &path.1;
+        info!("ncch_id = {}, info_type = {}", ncch_id, info_type);
+        let connection = match database.get_connection() {
+            Ok(connection) => connection,
+            Err(e) => {
+                error!("failed to get database connection: {}", e);
+                return NcchInfoResponse::InternalServerError.http();
+            }
+        };
+
+        let icon_size = match info_type {
+            "icon_small.png" => Some(IconSize::Small),
+            "icon_large.png" => Some(IconSize::Large),
+            _ => None,
+        };
+        if let Some(icon_size) = icon_size {
+            return match connection.get_ncch_icon(ncch_id, icon_size) {
+                Ok(icon) => respond_with_icon(&request, icon, &param),
+                Err(DatabaseError::NotFound) => {
+                    warn!("icon not found");
+                    NcchInfoResponse::NotFound.http()
+                }
+                Err(_) => {
+                    error!("unhandled error when getting icon");
+                    NcchInfoResponse::InternalServerError.http()
+                }
+            };
+        }
+
+        if info_type == "similar_icons" {
+            return match connection.query_similar_icons(
+                ncch_id,
+                MAX_SIMILAR_ICON_DISTANCE,
+                SIMILAR_ICON_LIMIT,
+            ) {
+                Ok(similar) => NcchSimilarIconsResponse::Ok(SimilarNcchVec {
+                    ncchs: similar
+                        .into_iter()
+                        .map(|(record, distance)| SimilarNcch {
+                            distance,
+                            ncch: record.to_ncch_info(),
+                        })
+                        .collect(),
+                })
+                .http(),
+                Err(DatabaseError::NotFound) => {
+                    warn!("NCCH record not found");
+                    NcchSimilarIconsResponse::NotFound.http()
+                }
+                Err(_) => {
+                    error!("unhandled error when querying similar icons");
+                    NcchSimilarIconsResponse::InternalServerError.http()
+                }
+            };
+        }
+
+        let record = match connection.get_ncch_record(ncch_id) {
+            Ok(record) => record,
+            Err(DatabaseError::NotFound) => {
+                warn!("NCCH record not found");
+                return NcchInfoResponse::NotFound.http();
+            }
+            Err(_) => {
+                error!("unhandled error when getting NCCH record");
+                return NcchInfoResponse::InternalServerError.http();
+            }
+        };
+
+        match info_type {
+            "info" => NcchInfoResponse::Ok(record.to_ncch_info()).http(),
+            "exheader.bin" => {
+                let exheader = match stored_section(
+                    &connection,
+                    &record,
+                    NcchBlob::raw_exheader,
+                    database::NcchRecord::stored_exheader,
+                ) {
+                    Ok(Some(stored)) => Some(stored),
+                    Ok(None) => record.rebuild_exheader(),
+                    Err(response) => return response,
+                };
+                respond_with_rebuilt(ncch_id, info_type, exheader)
+            }
+            "smdh.bin" => {
+                match stored_section(
+                    &connection,
+                    &record,
+                    NcchBlob::raw_smdh,
+                    database::NcchRecord::stored_smdh,
+                ) {
+                    Ok(Some(stored)) => {
+                        return respond_with_rebuilt(ncch_id, info_type, Some(stored))
+                    }
+                    Ok(None) => (),
+                    Err(response) => return response,
+                }
+                let hashes: Vec<String> = record
+                    .ncch
+                    .small_icon_hash
+                    .iter()
+                    .chain(record.ncch.large_icon_hash.iter())
+                    .cloned()
+                    .collect();
+                let icons = match connection.get_icons(&hashes) {
+                    Ok(icons) => icons,
+                    Err(_) => {
+                        error!("unhandled error when getting icons");
+                        return NcchInfoResponse::InternalServerError.http();
+                    }
+                };
+                let find = |hash: &Option<String>| {
+                    icons.iter().find(|icon| Some(&icon.hash) == hash.as_ref())
+                };
+                let smdh = record.rebuild_smdh(
+                    find(&record.ncch.small_icon_hash),
+                    find(&record.ncch.large_icon_hash),
+                );
+                respond_with_rebuilt(ncch_id, info_type, smdh)
+            }
+            _ => NcchInfoResponse::NotFound.http(),
+        }
+    };
+
+    let database = database_root.clone();
+    let query_ncch = move |param: web::Query<NcchQueryParam>| {
+        info!("NCCH query called");
+        let connection = match database.get_connection() {
+            Ok(connection) => connection,
+            Err(e) => {
+                error!("failed to get database connection: {}", e);
+                return NcchQueryResponse::InternalServerError.http();
+            }
+        };
+
+        let facets = if param.facets.as_ref().and_then(|f| f.value()) == Some(true) {
+            match connection.query_ncch_facets(&param.filter) {
+                Ok(facets) => Some(facets),
+                Err(DatabaseError::InvalidQuery(e)) => {
+                    warn!("invalid query: {}", e);
+                    return NcchQueryResponse::InvalidQuery(e).http();
+                }
+                Err(_) => {
+                    error!("unhandled error when getting NCCH facets");
+                    return NcchQueryResponse::InternalServerError.http();
+                }
+            }
+        } else {
+            None
+        };
+
+        match connection.query_ncch(&param) {
+            Ok(records) => NcchQueryResponse::Ok(NcchInfoVec {
+                ncchs: records
+                    .iter()
+                    .map(database::NcchRecord::to_ncch_info)
+                    .collect(),
+                facets,
+            })
+            .http(),
+            Err(DatabaseError::InvalidQuery(e)) => {
+                warn!("invalid query: {}", e);
+                NcchQueryResponse::InvalidQuery(e).http()
+            }
+            Err(_) => {
+                error!("unhandled error when getting NCCH record");
+                NcchQueryResponse::InternalServerError.http()
+            }
+        }
+    };
+
+    let database = database_root.clone();
+    let query_ncch_icons = move |param: web::Query<NcchQueryParam>| {
+        info!("NCCH icon query called");
+        let connection = match database.get_connection() {
+            Ok(connection) => connection,
+            Err(e) => {
+                error!("failed to get database connection: {}", e);
+                return NcchIconSheetResponse::InternalServerError.http();
+            }
+        };
+
+        match connection.query_ncch(&param) {
+            Ok(records) => NcchIconSheetResponse::Ok(icon_sheet_layout(&records)).http(),
+            Err(DatabaseError::InvalidQuery(e)) => {
+                warn!("invalid query: {}", e);
+                NcchIconSheetResponse::InvalidQuery(e).http()
+            }
+            Err(_) => {
+                error!("unhandled error when getting NCCH record");
+                NcchIconSheetResponse::InternalServerError.http()
+            }
+        }
+    };
+
+    let database = database_root.clone();
+    let icon_sheet = move |param: web::Query<IconSheetParam>| {
+        info!("icon sheet called");
+        let hashes: Vec<String> = param.icons.split(',').map(str::to_owned).collect();
+        if hashes.len() > MAX_ICON_SHEET_ICONS {
+            warn!("too many icons for a sheet");
+            return NcchInfoResponse::InvalidParam.http();
+        }
+        let icons = match database.get_icons(&hashes) {
+            Ok(icons) => icons,
+            Err(_) => {
+                error!("unhandled error when getting icons");
+                return NcchInfoResponse::InternalServerError.http();
+            }
+        };
+
+        match build_icon_sheet(&hashes, icons) {
+            // The URL names every icon in the sheet by its content hash
+            Ok(Some(png)) => HttpResponse::Ok()
+                .content_type("image/png")
+                .header(header::CACHE_CONTROL, "public, max-age=31536000")
+                .body(png),
+            Ok(None) => {
+                warn!("icon not found");
+                NcchInfoResponse::NotFound.http()
+            }
+            Err(e) => {
+                error!("{}", e);
+                NcchInfoResponse::InternalServerError.http()
+            }
+        }
+    };
+
+    let database = database_root.clone();
+    let query_ncch_count = move |param: web::Query<NcchFilterParam>| {
+        info!("NCCH query count called");
+        let connection = match database.get_connection() {
+            Ok(connection) => connection,
+            Err(e) => {
+                error!("failed to get database connection: {}", e);
+                return NcchQueryResponse::InternalServerError.http();
+            }
+        };
+
+        match connection.query_ncch_count(&param) {
+            Ok(count) => NcchQueryCountResponse::Ok(NcchCount { count }).http(),
+            Err(DatabaseError::InvalidQuery(e)) => {
+                warn!("invalid query: {}", e);
+                NcchQueryCountResponse::InvalidQuery(e).http()
+            }
+            Err(_) => {
+                error!("unhandled error when getting NCCH record");
+                NcchQueryResponse::InternalServerError.http()
+            }
+        }
+    };
+
+    let database = database_root.clone();
+    let stats = move |param: web::Query<NcchFilterParam>| {
+        info!("NCCH stats called");
+        let connection = match database.get_connection() {
+            Ok(connection) => connection,
+            Err(e) => {
+                error!("failed to get database connection: {}", e);
+                return NcchStatsResponse::InternalServerError.http();
+            }
+        };
+
+        match connection.query_ncch_stats(&param) {
+            Ok(stats) => NcchStatsResponse::Ok(stats).http(),
+            Err(DatabaseError::InvalidQuery(e)) => {
+                warn!("invalid query: {}", e);
+                NcchStatsResponse::InvalidQuery(e).http()
+            }
+            Err(_) => {
+                error!("unhandled error when getting NCCH stats");
+                NcchStatsResponse::InternalServerError.http()
+            }
+        }
+    };
+
+    let database = database_root.clone();
+    let makers = move || {
+        info!("makers called");
+        match database.query_makers() {
+            Ok(makers) => MakerResponse::Ok(MakerVec { makers }).http(),
+            Err(_) => {
+                error!("unhandled error when getting makers");
+                MakerResponse::InternalServerError.http()
+            }
+        }
+    };
+
+    let database = database_root.clone();
+    let lookup = move |param: web::Query<LookupParam>| {
+        info!("NCCH lookup called");
+        info!("hash = {}", param.hash);
+        let hash = match parse_lookup_hash(&param.hash) {
+            Some(hash) => hash,
+            None => {
+                warn!("invalid hash");
+                return NcchLookupResponse::InvalidParam.http();
+            }
+        };
+        match database.lookup_hash(&hash) {
+            Ok(matches) => NcchLookupResponse::Ok(LookupMatchVec {
+                ncchs: matches
+                    .into_iter()
+                    .map(|(record, kind)| LookupMatch {
+                        kind,
+                        ncch: record.to_ncch_info(),
+                    })
+                    .collect(),
+            })
+            .http(),
+            Err(_) => {
+                error!("unhandled error when looking up NCCH");
+                NcchLookupResponse::InternalServerError.http()
+            }
+        }
+    };
+
+    let database = database_root.clone();
+    let lookup_batch = move |request: web::Json<LookupBatchRequest>| {
+        info!("NCCH batch lookup called");
+        info!("{} IDs", request.ids.len());
+        let connection = match database.get_connection() {
+            Ok(connection) => connection,
+            Err(e) => {
+                error!("failed to get database connection: {}", e);
+                return NcchLookupBatchResponse::InternalServerError.http();
+            }
+        };
+
+        match database::lookup_batch(&connection, &request.ids) {
+            Ok(ncchs) => NcchLookupBatchResponse::Ok(LookupBatch { ncchs }).http(),
+            Err(DatabaseError::InvalidParam) => {
+                warn!("too many IDs");
+                NcchLookupBatchResponse::InvalidParam.http()
+            }
+            Err(_) => {
+                error!("unhandled error when looking up NCCH batch");
+                NcchLookupBatchResponse::InternalServerError.http()
+            }
+        }
+    };
+
+    let database = database_root.clone();
+    let diff = move |param: web::Query<diff::NcchDiffParam>| {
+        info!("NCCH diff called");
+        info!("a = {}, b = {}", param.a, param.b);
+        let connection = match database.get_connection() {
+            Ok(connection) => connection,
+            Err(e) => {
+                error!("failed to get database connection: {}", e);
+                return diff::NcchDiffResponse::InternalServerError.http();
+            }
+        };
+
+        let mut infos = vec![];
+        for ncch_id in &[&param.a, &param.b] {
+            match connection.get_ncch_record(ncch_id) {
+                Ok(record) => infos.push(record.to_ncch_info()),
+                Err(DatabaseError::NotFound) => {
+                    warn!("NCCH record not found");
+                    return diff::NcchDiffResponse::NotFound.http();
+                }
+                Err(_) => {
+                    error!("unhandled error when getting NCCH record");
+                    return diff::NcchDiffResponse::InternalServerError.http();
+                }
+            }
+        }
+        let b = infos.pop().unwrap();
+        let a = infos.pop().unwrap();
+        diff::NcchDiffResponse::Ok(diff::NcchDiff::new(a, b)).http()
+    };
+
+    let database = database_root.clone();
+    let export = move |param: web::Query<NcchExportParam>| {
+        info!("NCCH export called");
+        let connection = match database.get_connection() {
+            Ok(connection) => connection,
+            Err(e) => {
+                error!("failed to get database connection: {}", e);
+                return NcchExportResponse::InternalServerError.http();
+            }
+        };
+
+        match export::ExportStream::new(connection, param.into_inner()) {
+            Ok(stream) => HttpResponse::Ok()
+                .content_type(stream.content_type())
+                .streaming(stream),
+            Err(DatabaseError::InvalidQuery(e)) => {
+                warn!("invalid query: {}", e);
+                NcchExportResponse::InvalidQuery(e).http()
+            }
+            Err(_) => {
+                error!("unhandled error when exporting NCCH records");
+                NcchExportResponse::InternalServerError.http()
+            }
+        }
+    };
+
+    let database = database_root.clone();
+    let dat = move |param: web::Query<NcchFilterParam>| {
+        info!("NCCH DAT export called");
+        let connection = match database.get_connection() {
+            Ok(connection) => connection,
+            Err(e) => {
+                error!("failed to get database connection: {}", e);
+                return NcchExportResponse::InternalServerError.http();
+            }
+        };
+
+        match dat::DatStream::new(connection, param.into_inner()) {
+            Ok(stream) => HttpResponse::Ok()
+                .content_type("application/xml; charset=utf-8")
+                .streaming(stream),
+            Err(DatabaseError::InvalidQuery(e)) => {
+                warn!("invalid query: {}", e);
+                NcchExportResponse::InvalidQuery(e).http()
+            }
+            Err(_) => {
+                error!("unhandled error when exporting NCCH DAT");
+                NcchExportResponse::InternalServerError.http()
+            }
+        }
+    };
+
+    config
+        .route(url::post_ncch(), web::post().to(post_ncch))
+        .route(
+            &url::append_ncch("{session_id}"),
+            web::post().to(append_ncch),
+        )
+        .route(
+            &url::ncch_info("{ncch_id}", "{info_type}"),
+            web::get().to(ncch_info),
+        )
+        .route(url::query_ncch(), web::get().to(query_ncch))
+        .route(url::query_ncch_icons(), web::get().to(query_ncch_icons))
+        .route(url::icon_sheet(), web::get().to(icon_sheet))
+        .route(url::query_ncch_count(), web::get().to(query_ncch_count))
+        .route(url::stats(), web::get().to(stats))
+        .route(url::export(), web::get().to(export))
+        .route(url::dat(), web::get().to(dat))
+        .route(url::lookup(), web::get().to(lookup))
+        .route(
+            url::lookup_batch(),
+            web::post()
+                .data(web::JsonConfig::default().limit(1 << 20))
+                .to(lookup_batch),
+        )
+        .route(url::diff(), web::get().to(diff))
+        .route(url::makers(), web::get().to(makers));
+}
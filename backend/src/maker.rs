@@ -0,0 +1,86 @@
+use crate::database::{Database, MakerRow, NcchStore};
+use log::{info, warn};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+// TOML registry, e.g.
+// [makers]
+// 01 = "Nintendo"
+#[derive(Deserialize)]
+struct MakerFile {
+    makers: BTreeMap<String, String>,
+}
+
+// CSV registry with "code,name" rows. A header row and lines starting with # are skipped.
+fn parse_csv(text: &str) -> Result<BTreeMap<String, String>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .comment(Some(b'#'))
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+    let mut makers = BTreeMap::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| e.to_string())?;
+        match (record.get(0), record.get(1), record.len()) {
+            (Some("code"), Some("name"), 2) if i == 0 => continue,
+            (Some(code), Some(name), 2) => {
+                if makers.insert(code.to_owned(), name.to_owned()).is_some() {
+                    warn!("maker code {} is listed more than once", code);
+                }
+            }
+            _ => return Err(format!("expected code and name on record {}", i + 1)),
+        }
+    }
+    Ok(makers)
+}
+
+fn parse_toml(text: &str) -> Result<BTreeMap<String, String>, String> {
+    toml::from_str::<MakerFile>(text)
+        .map(|file| file.makers)
+        .map_err(|e| e.to_string())
+}
+
+// Reads a .toml or .csv registry. Later entries of the same code replace earlier ones.
+fn parse(path: &str, text: &str) -> Result<Vec<MakerRow>, String> {
+    let makers = if path.ends_with(".toml") {
+        parse_toml(text)?
+    } else {
+        parse_csv(text)?
+    };
+    makers
+        .iter()
+        .map(|(code, name)| MakerRow::new(code, name))
+        .collect()
+}
+
+// Replaces the maker registry with the content of the file
+pub fn import_makers(database: &Database, path: &str) -> std::io::Result<()> {
+    let text = std::fs::read_to_string(path)?;
+    let makers =
+        parse(path, &text).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    database
+        .replace_makers(&makers)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("{:?}", e)))?;
+    info!("maker registry replaced");
+    println!("stored {} makers", makers.len());
+    Ok(())
+}
+
+#[test]
+fn parse_test() {
+    let csv = "code,name\n# comment\n01, Nintendo\n\"08\",\"Capcom Co., Ltd.\"\n01,Nintendo Co.\n";
+    let makers = parse("makers.csv", csv).unwrap();
+    assert_eq!(makers.len(), 2);
+    assert_eq!(makers[0].code(), "01");
+    assert_eq!(makers[0].name, "Nintendo Co.");
+    assert_eq!(makers[1].name, "Capcom Co., Ltd.");
+
+    let toml = "[makers]\n01 = \"Nintendo\"\n\"52\" = \"Activision\"\n";
+    let makers = parse("makers.toml", toml).unwrap();
+    assert_eq!(makers[1].code(), "52");
+    assert_eq!(makers[1].name, "Activision");
+
+    assert!(parse("makers.csv", "001,Nintendo\n").is_err());
+    assert!(parse("makers.csv", "01,\n").is_err());
+    assert!(parse("makers.csv", "01,Nintendo Co., Ltd.\n").is_err());
+}
@@ -0,0 +1,70 @@
+use crate::api::NcchInfo;
+use crate::verify::NcchVerifier;
+use index3ds_formats::{walk_containers, ContainerFile, NcchLocation};
+use log::warn;
+use serde::Serialize;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+// One line of the output per NCCH found
+#[derive(Serialize, Debug)]
+struct OfflineResult {
+    file: String,
+    // Partition or content of an NCSD or CIA, empty for a plain NCCH
+    label: String,
+    offset: u64,
+    // "Verified", "Encrypted" for a CIA content encrypted with the title key, or the
+    // PostNcchResponse the upload would have failed with
+    status: String,
+    verified: Vec<&'static str>,
+    ncch: Option<NcchInfo>,
+}
+
+fn index_file(
+    path: &Path,
+    locations: &[NcchLocation],
+    out: &mut impl Write,
+) -> std::io::Result<usize> {
+    let mut file = File::open(path)?;
+    for location in locations {
+        let mut verifier = NcchVerifier::new();
+        let (status, ncch) = if location.encrypted {
+            ("Encrypted".to_owned(), None)
+        } else {
+            match verifier.read_from(&mut file, location.offset) {
+                Ok(record) => ("Verified".to_owned(), Some(record.to_ncch_info())),
+                Err(response) => (format!("{:?}", response), None),
+            }
+        };
+        let result = OfflineResult {
+            file: path.display().to_string(),
+            label: location.label.clone(),
+            offset: location.offset,
+            status,
+            verified: verifier.verified().to_vec(),
+            ncch,
+        };
+        writeln!(out, "{}", serde_json::to_string(&result)?)?;
+    }
+    Ok(locations.len())
+}
+
+// Runs the upload verification on local dumps and writes the results to stdout as NDJSON, without
+// touching the database. Directories are walked, skipping files that are not an NCCH, NCSD or
+// CIA.
+pub fn index_offline(paths: &[String]) -> std::io::Result<()> {
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    let mut count = 0;
+    for path in paths {
+        for ContainerFile { path, locations } in walk_containers(Path::new(path)) {
+            match locations.and_then(|locations| index_file(&path, &locations, &mut out)) {
+                Ok(indexed) => count += indexed,
+                Err(e) => warn!("{}: {}", path.display(), e),
+            }
+        }
+    }
+    eprintln!("indexed {} NCCHs", count);
+    Ok(())
+}
@@ -0,0 +1,190 @@
+use crate::api::{product_code, Release};
+use crate::database::{Database, DatabaseError, NcchStore, ReleaseRow};
+use log::{info, warn};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+// Entries whose NCCHs are looked up together
+const MATCH_CHUNK_SIZE: usize = 1000;
+
+// An entry of a 3dsdb-style release list, e.g.
+// <release>
+//   <name>Super Mario 3D Land</name>
+//   <serial>CTR-P-AREE</serial>
+//   <titleid>0004000000054000</titleid>
+//   ...
+// </release>
+// The JSON version is an array of objects with the same fields. Other fields are ignored.
+#[derive(Deserialize)]
+struct ReleaseEntry {
+    name: String,
+    #[serde(alias = "titleid")]
+    title_id: String,
+    serial: Option<String>,
+    region: Option<String>,
+    publisher: Option<String>,
+    #[serde(alias = "releasedate")]
+    release_date: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ReleaseList {
+    #[serde(rename = "release", default)]
+    releases: Vec<ReleaseEntry>,
+}
+
+fn parse(path: &str, text: &str) -> Result<Vec<ReleaseEntry>, String> {
+    if path.ends_with(".json") {
+        serde_json::from_str(text).map_err(|e| e.to_string())
+    } else {
+        serde_xml_rs::from_str::<ReleaseList>(text)
+            .map(|list| list.releases)
+            .map_err(|e| e.to_string())
+    }
+}
+
+// Empty elements count as missing
+fn non_empty(value: &Option<String>) -> Option<String> {
+    value
+        .as_ref()
+        .map(|v| v.trim().to_owned())
+        .filter(|v| !v.is_empty())
+}
+
+fn title_id(entry: &ReleaseEntry) -> Option<u64> {
+    if entry.title_id.len() != 16 {
+        return None;
+    }
+    u64::from_str_radix(&entry.title_id, 16).ok()
+}
+
+// For each entry, the NCCHs with its program ID, and its product code if it has one. The serial
+// takes the same wildcards as the product_code filter.
+fn find_matches(
+    database: &Database,
+    entries: &[ReleaseEntry],
+) -> Result<Vec<Vec<String>>, DatabaseError> {
+    let program_ids: Vec<u64> = entries.iter().filter_map(title_id).collect();
+    let mut by_program_id = HashMap::<u64, Vec<(String, String)>>::new();
+    for record in database.query_ncch_by_ids(&program_ids, &[])? {
+        let info = record.to_ncch_info();
+        let program_id = u64::from_str_radix(&info.program_id, 16).unwrap_or_default();
+        by_program_id
+            .entry(program_id)
+            .or_default()
+            .push((info.id, info.product_code));
+    }
+    Ok(entries
+        .iter()
+        .map(|entry| {
+            let serial = non_empty(&entry.serial);
+            let matches_serial = |code: &str| match &serial {
+                None => true,
+                Some(serial) if product_code::is_wildcard(serial) => {
+                    product_code::wildcard_match(&serial.to_uppercase(), code)
+                }
+                Some(serial) => serial == code,
+            };
+            title_id(entry)
+                .and_then(|program_id| by_program_id.get(&program_id))
+                .into_iter()
+                .flatten()
+                .filter(|(_, code)| matches_serial(code))
+                .map(|(id, _)| id.clone())
+                .collect()
+        })
+        .collect())
+}
+
+fn release_rows(
+    database: &Database,
+    entries: Vec<ReleaseEntry>,
+) -> std::io::Result<Vec<ReleaseRow>> {
+    let mut rows = vec![];
+    let mut seen = HashSet::new();
+    let mut matched = 0;
+    let total = entries.len();
+    let entries: Vec<ReleaseEntry> = entries
+        .into_iter()
+        .filter(|entry| {
+            let valid = title_id(entry).is_some();
+            if !valid {
+                warn!(
+                    "skipping {}: invalid title ID \"{}\"",
+                    entry.name, entry.title_id
+                );
+            }
+            valid
+        })
+        .collect();
+    let mut matches = vec![];
+    for chunk in entries.chunks(MATCH_CHUNK_SIZE) {
+        matches.extend(
+            find_matches(database, chunk)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("{:?}", e)))?,
+        );
+    }
+    for (entry, ids) in entries.into_iter().zip(matches) {
+        if !ids.is_empty() {
+            matched += 1;
+        }
+        let release = Release {
+            serial: non_empty(&entry.serial).unwrap_or_default(),
+            name: entry.name.trim().to_owned(),
+            region: non_empty(&entry.region),
+            publisher: non_empty(&entry.publisher),
+            release_date: non_empty(&entry.release_date),
+        };
+        for id in ids {
+            if seen.insert((id.clone(), release.serial.clone())) {
+                rows.push(ReleaseRow::new(&id, release.clone()));
+            } else {
+                warn!("{} is listed more than once for {}", release.serial, id);
+            }
+        }
+    }
+    println!("matched {} of {} releases", matched, total);
+    Ok(rows)
+}
+
+// Replaces the stored releases with the matches of the release list. NCCHs uploaded later are
+// only matched by importing the list again.
+pub fn import_releases(database: &Database, path: &str) -> std::io::Result<()> {
+    let text = std::fs::read_to_string(path)?;
+    let entries =
+        parse(path, &text).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    let rows = release_rows(database, entries)?;
+    database
+        .replace_releases(&rows)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("{:?}", e)))?;
+    info!("release list imported");
+    println!("stored {} releases", rows.len());
+    Ok(())
+}
+
+#[test]
+fn parse_test() {
+    let xml = "<releases>\n\
+               <release><id>1</id><name>Super Mario 3D Land</name><publisher>Nintendo</publisher>\
+               <region>USA</region><serial>CTR-P-AREE</serial><titleid>0004000000054000</titleid>\
+               </release>\n\
+               <release><name>Pilotwings Resort</name><titleid>0004000000032D00</titleid></release>\n\
+               </releases>";
+    let entries = parse("releases.xml", xml).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].serial.as_ref().unwrap(), "CTR-P-AREE");
+    assert_eq!(entries[0].title_id, "0004000000054000");
+    assert_eq!(entries[1].publisher, None);
+
+    let json = r#"[{"name": "Super Mario 3D Land", "titleid": "0004000000054000",
+        "serial": "", "release_date": "2011-11-13"}]"#;
+    let entries = parse("releases.json", json).unwrap();
+    assert_eq!(non_empty(&entries[0].serial), None);
+    assert_eq!(entries[0].release_date.as_ref().unwrap(), "2011-11-13");
+
+    assert!(parse(
+        "releases.xml",
+        "<releases><release><name>x</name></release></releases>"
+    )
+    .is_err());
+}
@@ -0,0 +1,87 @@
+use crate::database::{Database, NcchRecord, NcchStore};
+use log::{error, info};
+
+const BATCH_SIZE: i64 = 100;
+
+#[derive(Debug, Default)]
+struct ReprocessSummary {
+    updated: usize,
+    failed: usize,
+    icons_hashed: usize,
+}
+
+// Derives every record that has a stored blob again, for example after a column is added or the
+// parser is fixed. Records uploaded before blobs were stored are left as they are. Icons stored
+// before they had a dhash are hashed as well.
+pub fn reprocess(database: &Database) -> std::io::Result<()> {
+    let error = |e: String| std::io::Error::new(std::io::ErrorKind::Other, e);
+    let connection = database
+        .get_connection()
+        .map_err(|e| error(e.to_string()))?;
+
+    let mut summary = ReprocessSummary::default();
+    let mut last_id: Option<String> = None;
+    loop {
+        let blobs = connection
+            .query_ncch_blobs(last_id.as_ref().map(|s| s.as_str()), BATCH_SIZE)
+            .map_err(|_| error("failed to load blobs".to_owned()))?;
+        let last = match blobs.last() {
+            Some(last) => last.ncch_id.clone(),
+            None => break,
+        };
+
+        for blob in blobs {
+            let sections = match blob.sections() {
+                Ok(sections) => sections,
+                Err(e) => {
+                    error!("{}: {}", blob.ncch_id, e);
+                    summary.failed += 1;
+                    continue;
+                }
+            };
+            let mut record = NcchRecord::new(
+                sections.header,
+                sections.exheader,
+                sections.exefs_header,
+                sections.smdh,
+            );
+            if record.ncch.id != blob.ncch_id {
+                error!(
+                    "{}: derived a different ID {}",
+                    blob.ncch_id, record.ncch.id
+                );
+                summary.failed += 1;
+                continue;
+            }
+            // The blob itself is unchanged
+            record.blob = None;
+            match connection.update_ncch_record(&record) {
+                Ok(()) => summary.updated += 1,
+                Err(_) => {
+                    error!("failed to update {}", blob.ncch_id);
+                    summary.failed += 1;
+                }
+            }
+        }
+        last_id = Some(last);
+    }
+
+    let mut last_hash: Option<String> = None;
+    loop {
+        let (count, last) = connection
+            .fill_icon_dhash(last_hash.as_ref().map(|s| s.as_str()), BATCH_SIZE)
+            .map_err(|e| error(e.to_string()))?;
+        summary.icons_hashed += count;
+        match last {
+            Some(last) => last_hash = Some(last),
+            None => break,
+        }
+    }
+
+    info!("reprocess finished: {:?}", summary);
+    println!(
+        "updated {}, failed {}, hashed {} icons",
+        summary.updated, summary.failed, summary.icons_hashed
+    );
+    Ok(())
+}
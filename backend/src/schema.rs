@@ -1,4 +1,7 @@
 table! {
+    use diesel::sql_types::*;
+    use crate::sql_types::PortableArray;
+
     ncch (id) {
         id -> Text,
         ncch_signature -> Bytea,
@@ -21,7 +24,7 @@ table! {
         exheader_name -> Nullable<Bytea>,
         sd_app -> Nullable<Bool>,
         remaster_version -> Nullable<Int2>,
-        dependencies -> Nullable<Array<Int8>>,
+        dependencies -> Nullable<PortableArray<Int8>>,
         save_data_size -> Nullable<Int8>,
         jump_id -> Nullable<Int8>,
         exheader_program_id -> Nullable<Int8>,
@@ -33,29 +36,98 @@ table! {
         ideal_processor -> Nullable<Int2>,
         affinity_mask -> Nullable<Int2>,
         thread_priority -> Nullable<Int2>,
-        resource_limit_desc -> Nullable<Array<Int2>>,
+        resource_limit_desc -> Nullable<PortableArray<Int2>>,
         extdata_id -> Nullable<Int8>,
         system_savedata_id0 -> Nullable<Int4>,
         system_savedata_id1 -> Nullable<Int4>,
         storage_access_id -> Nullable<Int8>,
         filesystem_flag -> Nullable<Int8>,
-        services -> Nullable<Array<Bytea>>,
+        services -> Nullable<PortableArray<Bytea>>,
         resource_limit_category -> Nullable<Int2>,
-        kernel_desc -> Nullable<Array<Int4>>,
+        kernel_desc -> Nullable<PortableArray<Int4>>,
         arm9_flag -> Nullable<Int4>,
         arm9_flag_version -> Nullable<Int2>,
-        short_title -> Nullable<Array<Int2>>,
-        long_title -> Nullable<Array<Int2>>,
-        publisher -> Nullable<Array<Int2>>,
-        ratings -> Nullable<Array<Int2>>,
+        ratings -> Nullable<PortableArray<Int2>>,
         region_lockout -> Nullable<Int4>,
         match_maker_id -> Nullable<Int4>,
         match_maker_bit_id -> Nullable<Int8>,
         smdh_flags -> Nullable<Int4>,
         eula_version -> Nullable<Int2>,
         cec_id -> Nullable<Int4>,
-        small_icon -> Nullable<Array<Int2>>,
-        large_icon -> Nullable<Array<Int2>>,
+        small_icon_hash -> Nullable<Text>,
+        large_icon_hash -> Nullable<Text>,
+        exheader_hash -> Nullable<Bytea>,
+        smdh_hash -> Nullable<Bytea>,
         keyword -> Text,
     }
 }
+
+table! {
+    icon (hash) {
+        hash -> Text,
+        data -> Bytea,
+        png -> Nullable<Bytea>,
+        dhash -> Nullable<Int8>,
+    }
+}
+
+table! {
+    maker (code) {
+        code -> Int2,
+        name -> Text,
+    }
+}
+
+table! {
+    ncch_blob (ncch_id) {
+        ncch_id -> Text,
+        header -> Bytea,
+        exheader -> Nullable<Bytea>,
+        exefs_header -> Nullable<Bytea>,
+        smdh -> Nullable<Bytea>,
+    }
+}
+
+table! {
+    ncch_hash (ncch_id, kind) {
+        ncch_id -> Text,
+        kind -> Text,
+        hash -> Bytea,
+    }
+}
+
+table! {
+    ncch_release (ncch_id, serial) {
+        ncch_id -> Text,
+        serial -> Text,
+        name -> Text,
+        region -> Nullable<Text>,
+        publisher -> Nullable<Text>,
+        release_date -> Nullable<Text>,
+    }
+}
+
+table! {
+    ncch_title (ncch_id, language) {
+        ncch_id -> Text,
+        language -> Int2,
+        short_title -> Text,
+        long_title -> Text,
+        publisher -> Text,
+    }
+}
+
+joinable!(ncch_blob -> ncch (ncch_id));
+joinable!(ncch_hash -> ncch (ncch_id));
+joinable!(ncch_release -> ncch (ncch_id));
+joinable!(ncch_title -> ncch (ncch_id));
+
+allow_tables_to_appear_in_same_query!(
+    icon,
+    maker,
+    ncch,
+    ncch_blob,
+    ncch_hash,
+    ncch_release,
+    ncch_title,
+);
@@ -0,0 +1,125 @@
+use diesel::backend::Backend;
+use diesel::deserialize::{self, FromSql};
+use diesel::expression::bound::Bound;
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgMetadataLookup, PgTypeMetadata};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::{Array, HasSqlType, NotNull, Nullable, SingleValue, Text};
+use diesel::sqlite::{Sqlite, SqliteType};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::Write;
+
+// An array column that is a native array on Postgres, and a JSON encoded TEXT on SQLite, which
+// has no array type.
+#[derive(QueryId)]
+pub struct PortableArray<ST>(ST);
+
+impl<ST> NotNull for PortableArray<ST> {}
+impl<ST> SingleValue for PortableArray<ST> {}
+
+impl<ST> HasSqlType<PortableArray<ST>> for Pg
+where
+    Pg: HasSqlType<ST>,
+{
+    fn metadata(lookup: &PgMetadataLookup) -> PgTypeMetadata {
+        <Pg as HasSqlType<Array<ST>>>::metadata(lookup)
+    }
+}
+
+impl<ST> HasSqlType<PortableArray<ST>> for Sqlite {
+    fn metadata(_: &()) -> SqliteType {
+        SqliteType::Text
+    }
+}
+
+// Rust side of PortableArray. This is a newtype because diesel doesn't allow other crates to
+// implement AsExpression for Vec.
+#[derive(FromSqlRow, Debug, Clone, PartialEq)]
+pub struct PortableVec<T>(pub Vec<T>);
+
+impl<T> std::ops::Deref for PortableVec<T> {
+    type Target = Vec<T>;
+    fn deref(&self) -> &Vec<T> {
+        &self.0
+    }
+}
+
+impl<T> From<Vec<T>> for PortableVec<T> {
+    fn from(v: Vec<T>) -> PortableVec<T> {
+        PortableVec(v)
+    }
+}
+
+impl<T> std::iter::FromIterator<T> for PortableVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> PortableVec<T> {
+        PortableVec(iter.into_iter().collect())
+    }
+}
+
+impl<T, ST> FromSql<PortableArray<ST>, Pg> for PortableVec<T>
+where
+    Vec<T>: FromSql<Array<ST>, Pg>,
+{
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        Ok(PortableVec(FromSql::<Array<ST>, Pg>::from_sql(bytes)?))
+    }
+}
+
+impl<T, ST> ToSql<PortableArray<ST>, Pg> for PortableVec<T>
+where
+    Vec<T>: ToSql<Array<ST>, Pg>,
+    T: std::fmt::Debug,
+{
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<Array<ST>, Pg>::to_sql(&self.0, out)
+    }
+}
+
+impl<T, ST> FromSql<PortableArray<ST>, Sqlite> for PortableVec<T>
+where
+    T: DeserializeOwned,
+{
+    fn from_sql(value: Option<&<Sqlite as Backend>::RawValue>) -> deserialize::Result<Self> {
+        let text: String = FromSql::<Text, Sqlite>::from_sql(value)?;
+        Ok(PortableVec(serde_json::from_str(&text)?))
+    }
+}
+
+impl<T, ST> ToSql<PortableArray<ST>, Sqlite> for PortableVec<T>
+where
+    T: Serialize + std::fmt::Debug,
+{
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Sqlite>) -> serialize::Result {
+        let text = serde_json::to_string(&self.0)?;
+        ToSql::<Text, Sqlite>::to_sql(&text, out)
+    }
+}
+
+impl<T, ST, DB> ToSql<Nullable<PortableArray<ST>>, DB> for PortableVec<T>
+where
+    DB: Backend,
+    PortableVec<T>: ToSql<PortableArray<ST>, DB>,
+    T: std::fmt::Debug,
+{
+    fn to_sql<W: Write>(&self, out: &mut Output<W, DB>) -> serialize::Result {
+        ToSql::<PortableArray<ST>, DB>::to_sql(self, out)
+    }
+}
+
+macro_rules! portable_vec_as_expression {
+    ($ty:ty, $sql_type:ty) => {
+        impl<'a, ST, T> AsExpression<$sql_type> for $ty {
+            type Expression = Bound<$sql_type, Self>;
+
+            fn as_expression(self) -> Self::Expression {
+                Bound::new(self)
+            }
+        }
+    };
+}
+
+portable_vec_as_expression!(PortableVec<T>, PortableArray<ST>);
+portable_vec_as_expression!(PortableVec<T>, Nullable<PortableArray<ST>>);
+portable_vec_as_expression!(&'a PortableVec<T>, PortableArray<ST>);
+portable_vec_as_expression!(&'a PortableVec<T>, Nullable<PortableArray<ST>>);
@@ -0,0 +1,481 @@
+use crate::aes::*;
+use crate::api::PostNcchResponse;
+use crate::database::NcchRecord;
+use crate::key;
+use crate::rsa2048::*;
+use index3ds_formats::*;
+use log::{error, info, warn};
+use sha2::*;
+use std::io::{Read, Seek, SeekFrom};
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+enum NcchVerifierState {
+    HeaderNeeeded,
+    ExheaderNeeded(NcchHeader, [u8; 16], [u8; 16], [u8; 16]),
+    ExefsNeeded(NcchHeader, Option<Exheader>, [u8; 16], [u8; 16]),
+    IconNeeded(
+        NcchHeader,
+        Option<Exheader>,
+        ExefsHeader,
+        [u8; 32],
+        Option<([u8; 16], [u8; 16], u64)>,
+    ),
+    Finished,
+    Undefined,
+}
+
+#[derive(Debug)]
+pub enum NcchVerifierStep {
+    // Offset and length of the next region, relative to the start of the NCCH
+    Need(usize, usize),
+    Verified(Box<NcchRecord>),
+    // One of UnexpectedLength, UnexpectedFormat, VerificationFailed and AlreadyFinished
+    Failed(PostNcchResponse),
+}
+
+// Verifies an NCCH and extracts its record, asking for one region at a time. The upload session
+// feeds it the regions the client sends, and read_from reads them from a file directly.
+#[derive(Debug)]
+pub struct NcchVerifier {
+    state: NcchVerifierState,
+    // Sections whose signature or hash checked out so far
+    verified: Vec<&'static str>,
+}
+
+impl Default for NcchVerifier {
+    fn default() -> NcchVerifier {
+        NcchVerifier::new()
+    }
+}
+
+impl NcchVerifier {
+    pub fn new() -> NcchVerifier {
+        NcchVerifier {
+            state: NcchVerifierState::HeaderNeeeded,
+            verified: vec![],
+        }
+    }
+
+    fn verify_and_fix_header(header: &mut NcchHeader, public_key: &[u8]) -> bool {
+        info!("verifying NCCH header");
+        if verify_signature(&header.to_bytes()[0x100..], &header.signature, public_key) {
+            return true;
+        }
+
+        info!("verifying NCCH first try failed. Trying with modified encryption flag");
+        info!(
+            "Original flag: secondary_key_slot = {}, {:?}",
+            header.secondary_key_slot, header.key_config
+        );
+        header.key_config.no_crypto = 0;
+        header.key_config.fixed_key = 0;
+        for &secondary_key_slot in &[0, 1, 10, 11] {
+            for &seed_crypto in &[0, 1] {
+                info!(
+                    "attempting secondary_key_slot = {} seed_crypto = {}",
+                    secondary_key_slot, seed_crypto
+                );
+                header.secondary_key_slot = secondary_key_slot;
+                header.key_config.seed_crypto = seed_crypto;
+                if verify_signature(&header.to_bytes()[0x100..], &header.signature, public_key) {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    fn request_exheader(
+        &mut self,
+        mut header: NcchHeader,
+        key: [u8; 16],
+        ctr_exheader: [u8; 16],
+        ctr_exefs: [u8; 16],
+    ) -> NcchVerifierStep {
+        if header.exheader_size != 0 {
+            info!("requesting exheader");
+            if header.exheader_size != 0x400 {
+                warn!("unexpected exheader size {}", header.exheader_size);
+                return NcchVerifierStep::Failed(PostNcchResponse::UnexpectedFormat);
+            }
+            self.state = NcchVerifierState::ExheaderNeeded(header, key, ctr_exheader, ctr_exefs);
+            NcchVerifierStep::Need(0x200, 0x800)
+        } else {
+            info!("skipping exheader, verifying signature as CFA");
+
+            if !NcchVerifier::verify_and_fix_header(&mut header, &*key::CFA_PUBLIC_KEY) {
+                warn!("NCCH header verification failed");
+                return NcchVerifierStep::Failed(PostNcchResponse::VerificationFailed);
+            }
+            self.verified.push("header");
+
+            self.request_exefs(header, None, key, ctr_exefs)
+        }
+    }
+
+    fn request_exefs(
+        &mut self,
+        header: NcchHeader,
+        exheader: Option<Exheader>,
+        key: [u8; 16],
+        ctr_exefs: [u8; 16],
+    ) -> NcchVerifierStep {
+        if header.exefs_offset != 0 {
+            info!("requesting exefs");
+            let unit_size = header.unit_size();
+            let exefs_offset = header.exefs_offset as usize * unit_size;
+            let exefs_needed_len = std::cmp::max(
+                header.exefs_hash_region_size as usize * unit_size,
+                ExefsHeader::BYTE_LEN,
+            );
+
+            self.state = NcchVerifierState::ExefsNeeded(header, exheader, key, ctr_exefs);
+            NcchVerifierStep::Need(exefs_offset, exefs_needed_len)
+        } else {
+            info!("skipping exefs");
+            self.finalize(header, exheader, None, None)
+        }
+    }
+
+    fn request_icon(
+        &mut self,
+        header: NcchHeader,
+        exheader: Option<Exheader>,
+        exefs: ExefsHeader,
+        exefs_crypto: Option<([u8; 16], [u8; 16])>,
+    ) -> NcchVerifierStep {
+        let icon = exefs
+            .file("icon")
+            .map(|(file, hash)| (file.offset as usize, file.size as usize, *hash));
+        if let Some((icon_offset, icon_len, hash)) = icon {
+            info!("requesting icon");
+            let unit_size = header.unit_size();
+            let exefs_offset = header.exefs_offset as usize * unit_size;
+
+            let icon_offset = ExefsHeader::BYTE_LEN + icon_offset;
+            if icon_len != Smdh::BYTE_LEN {
+                warn!("unexpected icon size {}. Skipping icon", icon_len);
+                if icon_len != 0 {
+                    error!("Really strange icon here");
+                }
+                return self.finalize(header, exheader, Some(exefs), None);
+            }
+            self.state = NcchVerifierState::IconNeeded(
+                header,
+                exheader,
+                exefs,
+                hash,
+                exefs_crypto.map(|(key, ctr)| (key, ctr, icon_offset as u64)),
+            );
+            NcchVerifierStep::Need(exefs_offset + icon_offset, icon_len)
+        } else {
+            info!("skipping icon");
+            self.finalize(header, exheader, Some(exefs), None)
+        }
+    }
+
+    fn finalize(
+        &mut self,
+        header: NcchHeader,
+        exheader: Option<Exheader>,
+        exefs: Option<ExefsHeader>,
+        icon: Option<Smdh>,
+    ) -> NcchVerifierStep {
+        info!("finalizing NCCH");
+        NcchVerifierStep::Verified(Box::new(NcchRecord::new(header, exheader, exefs, icon)))
+    }
+
+    fn receive_header(&mut self, data: &[u8]) -> NcchVerifierStep {
+        info!("reading NCCH header");
+        if data.len() != NcchHeader::BYTE_LEN {
+            warn!("unexpected NCCH header len: {}", data.len());
+            return NcchVerifierStep::Failed(PostNcchResponse::UnexpectedLength);
+        }
+
+        let header = NcchHeader::read_bytes(data);
+
+        if header.magic != *b"NCCH" {
+            warn!("unexpected NCCH magic: {:?}", header.magic);
+            return NcchVerifierStep::Failed(PostNcchResponse::UnexpectedFormat);
+        }
+
+        let key = if header.key_config.fixed_key != 0 {
+            [0; 16]
+        } else {
+            get_ncch_key(&header.key_y())
+        };
+
+        info!("NCCH version = {}", header.version);
+        let (ctr_exheader, ctr_exefs) = header.counters().unwrap_or_else(|| {
+            error!("Unknown NCCH version!");
+            ([0; 16], [0; 16])
+        });
+
+        if header.unit_size() != 0x200 {
+            error!("weird unit size: {}", header.unit_size());
+        }
+
+        self.request_exheader(header, key, ctr_exheader, ctr_exefs)
+    }
+
+    fn receive_exheader(
+        &mut self,
+        data: &[u8],
+        mut header: NcchHeader,
+        key: [u8; 16],
+        ctr_exheader: [u8; 16],
+        ctr_exefs: [u8; 16],
+    ) -> NcchVerifierStep {
+        info!("reading Exheader");
+        if data.len() != Exheader::BYTE_LEN {
+            warn!("unexpected Exheader header len: {}", data.len());
+            return NcchVerifierStep::Failed(PostNcchResponse::UnexpectedLength);
+        }
+
+        let mut data = data;
+        let mut temp;
+        let mut hasher = Sha256::new();
+        hasher.input(&data[0..0x400]);
+        if hasher.result()[..] != header.exheader_hash[..] {
+            info!("decrypting exheader");
+            temp = data[..].to_vec();
+            aes_ctr_decrypt(&mut temp, &key, &ctr_exheader, 0);
+            data = &temp[..];
+
+            let mut hasher = Sha256::new();
+            hasher.input(&data[0..0x400]);
+            if hasher.result()[..] != header.exheader_hash[..] {
+                warn!("Exheader hash mismatch");
+                return NcchVerifierStep::Failed(PostNcchResponse::VerificationFailed);
+            }
+        }
+
+        let exheader = Exheader::read_bytes(data);
+
+        if !verify_signature(
+            &data[0x500..],
+            &exheader.signature,
+            &*key::EXHEADER_PUBLIC_KEY,
+        ) {
+            warn!("Exheader verification failed");
+            return NcchVerifierStep::Failed(PostNcchResponse::VerificationFailed);
+        }
+        self.verified.push("exheader");
+
+        if !NcchVerifier::verify_and_fix_header(&mut header, &exheader.public_key) {
+            warn!("NCCH header verification failed");
+            return NcchVerifierStep::Failed(PostNcchResponse::VerificationFailed);
+        }
+        self.verified.push("header");
+
+        self.request_exefs(header, Some(exheader), key, ctr_exefs)
+    }
+
+    fn receive_exefs(
+        &mut self,
+        data: &[u8],
+        header: NcchHeader,
+        exheader: Option<Exheader>,
+        key: [u8; 16],
+
+        ctr_exefs: [u8; 16],
+    ) -> NcchVerifierStep {
+        info!("reading Exefs");
+
+        let unit_size = header.unit_size();
+        let exefs_hash_region_size = header.exefs_hash_region_size as usize * unit_size;
+
+        if data.len() != std::cmp::max(ExefsHeader::BYTE_LEN, exefs_hash_region_size) {
+            warn!("unexpected Exefs len: {}", data.len());
+            return NcchVerifierStep::Failed(PostNcchResponse::UnexpectedLength);
+        }
+
+        let mut icon_crypto = None;
+
+        let mut data = data;
+        let mut temp;
+        let mut hasher = Sha256::new();
+        hasher.input(&data[0..exefs_hash_region_size]);
+        if hasher.result()[..] != header.exefs_hash[..] {
+            info!("decrypting exefs");
+            temp = data.to_vec();
+            aes_ctr_decrypt(&mut temp, &key, &ctr_exefs, 0);
+            data = &temp[..];
+            icon_crypto = Some((key, ctr_exefs));
+
+            let mut hasher = Sha256::new();
+            hasher.input(&data[0..exefs_hash_region_size]);
+            if hasher.result()[..] != header.exefs_hash[..] {
+                warn!("Exefs hash mismatch");
+                return NcchVerifierStep::Failed(PostNcchResponse::VerificationFailed);
+            }
+        }
+        self.verified.push("exefs");
+
+        let exefs = ExefsHeader::read_bytes(&data[0..ExefsHeader::BYTE_LEN]);
+
+        self.request_icon(header, exheader, exefs, icon_crypto)
+    }
+
+    fn receive_icon(
+        &mut self,
+        data: &[u8],
+        header: NcchHeader,
+        exheader: Option<Exheader>,
+        exefs: ExefsHeader,
+        hash: [u8; 32],
+        icon_crypto: Option<([u8; 16], [u8; 16], u64)>,
+    ) -> NcchVerifierStep {
+        info!("reading icon");
+
+        if data.len() != Smdh::BYTE_LEN {
+            warn!("unexpected icon len: {}", data.len());
+            return NcchVerifierStep::Failed(PostNcchResponse::UnexpectedLength);
+        }
+
+        let mut data = data;
+        let mut temp;
+        if let Some((key, ctr, offset)) = icon_crypto {
+            info!("decrypting icon");
+            temp = data.to_vec();
+            aes_ctr_decrypt(&mut temp, &key, &ctr, offset);
+            data = &temp[..];
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.input(data);
+        if hasher.result()[..] != hash {
+            warn!("icon hash mismatch");
+            return NcchVerifierStep::Failed(PostNcchResponse::VerificationFailed);
+        }
+        self.verified.push("icon");
+
+        let smdh = Smdh::read_bytes(data);
+        if !smdh.has_magic() {
+            error!("unexpected SMDH magic: {:?}", smdh.magic);
+            return self.finalize(header, exheader, Some(exefs), None);
+        }
+
+        self.finalize(header, exheader, Some(exefs), Some(smdh))
+    }
+
+    // Takes the region asked for by the previous step, or the header for the first step
+    pub fn next(&mut self, data: &[u8]) -> NcchVerifierStep {
+        let step = match std::mem::replace(&mut self.state, NcchVerifierState::Undefined) {
+            NcchVerifierState::HeaderNeeeded => self.receive_header(data),
+
+            NcchVerifierState::ExheaderNeeded(header, key, ctr_exheader, ctr_exefs) => {
+                self.receive_exheader(data, header, key, ctr_exheader, ctr_exefs)
+            }
+
+            NcchVerifierState::ExefsNeeded(header, exheader, key, ctr_exefs) => {
+                self.receive_exefs(data, header, exheader, key, ctr_exefs)
+            }
+
+            NcchVerifierState::IconNeeded(header, exheader, exefs, hash, icon_crypto) => {
+                self.receive_icon(data, header, exheader, exefs, hash, icon_crypto)
+            }
+
+            NcchVerifierState::Finished => {
+                warn!("already finished session");
+                NcchVerifierStep::Failed(PostNcchResponse::AlreadyFinished)
+            }
+
+            NcchVerifierState::Undefined => {
+                error!("The session is in the undefined state");
+                NcchVerifierStep::Failed(PostNcchResponse::AlreadyFinished)
+            }
+        };
+        match step {
+            NcchVerifierStep::Need(..) => (),
+            _ => self.state = NcchVerifierState::Finished,
+        }
+        step
+    }
+
+    // Runs the whole pipeline on the NCCH starting at `offset` of the source. A region past the
+    // end of the source fails with UnexpectedLength, the same as a short upload.
+    pub fn read_from<F: Read + Seek>(
+        &mut self,
+        source: &mut F,
+        offset: u64,
+    ) -> Result<NcchRecord, PostNcchResponse> {
+        let mut region = (0, NcchHeader::BYTE_LEN);
+        loop {
+            let mut data = vec![0; region.1];
+            source
+                .seek(SeekFrom::Start(offset + region.0 as u64))
+                .and_then(|_| source.read_exact(&mut data))
+                .map_err(|e| {
+                    warn!("failed to read region {:?}: {}", region, e);
+                    PostNcchResponse::UnexpectedLength
+                })?;
+            match self.next(&data) {
+                NcchVerifierStep::Need(region_offset, len) => region = (region_offset, len),
+                NcchVerifierStep::Verified(record) => return Ok(*record),
+                NcchVerifierStep::Failed(response) => return Err(response),
+            }
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        match self.state {
+            NcchVerifierState::Finished | NcchVerifierState::Undefined => true,
+            _ => false,
+        }
+    }
+
+    pub fn verified(&self) -> &[&'static str] {
+        &self.verified
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::fixture_ncch;
+    use std::io::Cursor;
+
+    #[test]
+    fn read_from_test() {
+        let mut file = vec![0xFF; 0x40];
+        file.extend(fixture_ncch());
+        let mut verifier = NcchVerifier::new();
+        let record = verifier.read_from(&mut Cursor::new(file), 0x40).unwrap();
+        assert_eq!(verifier.verified(), &["header", "exefs", "icon"]);
+        assert!(verifier.finished());
+        let info = record.to_ncch_info();
+        assert_eq!(info.product_code, "CTR-P-FXTR");
+        assert_eq!(info.short_title.unwrap()[1], "Fixture");
+
+        // The product code is covered by the signature
+        let mut ncch = fixture_ncch();
+        ncch[0x150] = b'X';
+        match NcchVerifier::new().read_from(&mut Cursor::new(ncch), 0) {
+            Err(PostNcchResponse::VerificationFailed) => (),
+            other => panic!("{:?}", other.map(|record| record.ncch.id)),
+        }
+    }
+
+    #[test]
+    fn read_from_truncated_test() {
+        let mut ncch = fixture_ncch();
+        // Cut in the middle of the icon
+        ncch.truncate(0x800);
+        let mut verifier = NcchVerifier::new();
+        match verifier.read_from(&mut Cursor::new(ncch), 0) {
+            Err(PostNcchResponse::UnexpectedLength) => (),
+            other => panic!("{:?}", other.map(|record| record.ncch.id)),
+        }
+        assert_eq!(verifier.verified(), &["header", "exefs"]);
+
+        let mut verifier = NcchVerifier::new();
+        match verifier.read_from(&mut Cursor::new(vec![0; 0x100]), 0) {
+            Err(PostNcchResponse::UnexpectedLength) => (),
+            other => panic!("{:?}", other.map(|record| record.ncch.id)),
+        }
+        assert!(verifier.verified().is_empty());
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{
+    count_result, icon_error, ncch_info_result, query_result, read_region, trim_host, upload_step,
+    ClientError, IconSize, UploadStep, Uploaded,
+};
+use index3ds_common::*;
+use reqwest::Response;
+use serde::de::DeserializeOwned;
+use std::io::{Read, Seek};
+
+fn json<T: DeserializeOwned>(mut response: Response) -> Result<T, ClientError> {
+    Ok(response.json()?)
+}
+
+// A client that waits for every response before returning
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    host: String,
+}
+
+impl Client {
+    // `host` is the root of the site, such as "http://127.0.0.1:8080"
+    pub fn new(host: &str) -> Client {
+        Client::with_http_client(reqwest::Client::new(), host)
+    }
+
+    pub fn with_http_client(http: reqwest::Client, host: &str) -> Client {
+        Client {
+            http,
+            host: trim_host(host),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.host, path)
+    }
+
+    fn post(&self, path: &str, body: Vec<u8>) -> Result<UploadStep, ClientError> {
+        let response = self.http.post(&self.url(path)).body(body).send()?;
+        upload_step(json(response)?)
+    }
+
+    // Starts an upload session with the 0x200-byte NCCH header
+    pub fn post_ncch(&self, header: Vec<u8>) -> Result<UploadStep, ClientError> {
+        self.post(url::post_ncch(), header)
+    }
+
+    pub fn append_ncch(&self, session_id: u32, data: Vec<u8>) -> Result<UploadStep, ClientError> {
+        self.post(&url::append_ncch(&session_id.to_string()), data)
+    }
+
+    // Uploads the NCCH at `offset` of the source, sending whatever the server asks for
+    pub fn upload<F: Read + Seek>(
+        &self,
+        source: &mut F,
+        offset: u64,
+    ) -> Result<Uploaded, ClientError> {
+        let mut step = self.post_ncch(read_region(source, offset, 0, 0x200)?)?;
+        loop {
+            match step {
+                UploadStep::Done(uploaded) => return Ok(uploaded),
+                UploadStep::Need(request) => {
+                    let data = read_region(source, offset, request.offset, request.len)?;
+                    step = self.append_ncch(request.session_id, data)?;
+                }
+            }
+        }
+    }
+
+    pub fn ncch_info(&self, ncch_id: &str) -> Result<NcchInfo, ClientError> {
+        let response = self
+            .http
+            .get(&self.url(&url::ncch_info(ncch_id, "info")))
+            .send()?;
+        ncch_info_result(json(response)?)
+    }
+
+    pub fn query_ncch(&self, param: &NcchQueryParam) -> Result<NcchInfoVec, ClientError> {
+        let response = self
+            .http
+            .get(&self.url(url::query_ncch()))
+            .query(param)
+            .send()?;
+        query_result(json(response)?)
+    }
+
+    pub fn query_ncch_count(&self, filter: &NcchFilterParam) -> Result<i64, ClientError> {
+        let response = self
+            .http
+            .get(&self.url(url::query_ncch_count()))
+            .query(filter)
+            .send()?;
+        count_result(json(response)?)
+    }
+
+    // The icon image, in the format asked for by `param`
+    pub fn icon(
+        &self,
+        ncch_id: &str,
+        size: IconSize,
+        param: &IconParam,
+    ) -> Result<Vec<u8>, ClientError> {
+        let mut response = self
+            .http
+            .get(&self.url(&url::ncch_info(ncch_id, size.file_name())))
+            .query(param)
+            .send()?;
+        if !response.status().is_success() {
+            return Err(icon_error(response.json(), response.status().as_u16()));
+        }
+        let mut icon = vec![];
+        response.copy_to(&mut icon)?;
+        Ok(icon)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serve;
+    use index3ds::fixture::fixture_ncch;
+    use std::io::Cursor;
+
+    #[test]
+    fn upload_test() {
+        let (host, _server) = serve(16);
+        let client = Client::new(&host);
+        let mut file = vec![0xAA; 0x10];
+        file.extend(fixture_ncch());
+        let uploaded = client.upload(&mut Cursor::new(file.clone()), 0x10).unwrap();
+        assert!(uploaded.added);
+        let info = client.ncch_info(&uploaded.ncch_id).unwrap();
+        assert_eq!(info.product_code, "CTR-P-FXTR");
+        let again = client.upload(&mut Cursor::new(file), 0x10).unwrap();
+        assert_eq!(
+            again,
+            Uploaded {
+                ncch_id: uploaded.ncch_id,
+                added: false
+            }
+        );
+    }
+
+    #[test]
+    fn upload_error_test() {
+        let (host, _server) = serve(0);
+        let client = Client::new(&host);
+        let mut ncch = fixture_ncch();
+        // The session waiting for the ExeFS leaves no room for another
+        match client.post_ncch(ncch[..0x200].to_vec()) {
+            Ok(UploadStep::Need(_)) => (),
+            other => panic!("{:?}", other),
+        }
+        match client.upload(&mut Cursor::new(ncch.clone()), 0) {
+            Err(ClientError::Busy) => (),
+            other => panic!("{:?}", other),
+        }
+
+        let (host, _server) = serve(16);
+        let client = Client::new(&host);
+        // Too short for the header
+        match client.upload(&mut Cursor::new(ncch[..0x100].to_vec()), 0) {
+            Err(ClientError::Io(_)) => (),
+            other => panic!("{:?}", other),
+        }
+        // The product code is covered by the signature
+        ncch[0x150] = b'X';
+        match client.upload(&mut Cursor::new(ncch), 0) {
+            Err(ClientError::VerificationFailed) => (),
+            other => panic!("{:?}", other),
+        }
+        match client.ncch_info("0") {
+            Err(ClientError::NotFound) => (),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn query_test() {
+        let (host, _server) = serve(16);
+        let client = Client::new(&host);
+        client.upload(&mut Cursor::new(fixture_ncch()), 0).unwrap();
+        let mut param = NcchQueryParam {
+            offset: 0,
+            limit: 20,
+            facets: None,
+            order_by_title: None,
+            filter: NcchFilterParam::default(),
+        };
+        let ncchs = client.query_ncch(&param).unwrap().ncchs;
+        assert_eq!(ncchs.len(), 1);
+        assert_eq!(ncchs[0].product_code, "CTR-P-FXTR");
+        param.filter.query = Some("product_code:CTR-P-*".to_owned());
+        assert_eq!(client.query_ncch_count(&param.filter).unwrap(), 1);
+        param.filter.query = Some("(".to_owned());
+        match client.query_ncch(&param) {
+            Err(ClientError::InvalidQuery(_)) => (),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn icon_test() {
+        let (host, _server) = serve(16);
+        let client = Client::new(&host);
+        let uploaded = client.upload(&mut Cursor::new(fixture_ncch()), 0).unwrap();
+        let param = IconParam {
+            size: Some(StringWrapper::new(48)),
+            ..IconParam::default()
+        };
+        let icon = client
+            .icon(&uploaded.ncch_id, IconSize::Small, &param)
+            .unwrap();
+        assert_eq!(icon[..4], b"\x89PNG"[..]);
+        match client.icon("0", IconSize::Small, &param) {
+            Err(ClientError::NotFound) => (),
+            other => panic!("{:?}", other),
+        }
+    }
+}
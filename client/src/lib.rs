@@ -0,0 +1,186 @@
+mod blocking;
+mod nonblocking;
+
+pub use blocking::Client;
+pub use nonblocking::AsyncClient;
+
+use index3ds_common::query::QueryError;
+use index3ds_common::*;
+use std::io::{Read, Seek, SeekFrom};
+
+#[derive(Debug)]
+pub enum ClientError {
+    // The request failed to send, or the response failed to read or decode
+    Http(reqwest::Error),
+    // Reading the NCCH to upload failed
+    Io(std::io::Error),
+    NotFound,
+    InvalidParam,
+    InvalidQuery(QueryError),
+    InternalServerError,
+    AlreadyFinished,
+    UnexpectedLength,
+    UnexpectedFormat,
+    VerificationFailed,
+    // The server is out of upload sessions
+    Busy,
+    // A status the API doesn't define for the request, such as an error page of a proxy
+    UnexpectedStatus(u16),
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ClientError::Http(e) => e.fmt(f),
+            ClientError::Io(e) => e.fmt(f),
+            ClientError::NotFound => write!(f, "not found"),
+            ClientError::InvalidParam => write!(f, "invalid parameter"),
+            ClientError::InvalidQuery(e) => write!(f, "invalid query: {}", e),
+            ClientError::InternalServerError => write!(f, "internal server error"),
+            ClientError::AlreadyFinished => write!(f, "upload session already finished"),
+            ClientError::UnexpectedLength => write!(f, "unexpected length"),
+            ClientError::UnexpectedFormat => write!(f, "not an NCCH"),
+            ClientError::VerificationFailed => write!(f, "verification failed"),
+            ClientError::Busy => write!(f, "server busy"),
+            ClientError::UnexpectedStatus(status) => write!(f, "unexpected status {}", status),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> ClientError {
+        ClientError::Http(e)
+    }
+}
+
+impl From<std::io::Error> for ClientError {
+    fn from(e: std::io::Error) -> ClientError {
+        ClientError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Uploaded {
+    pub ncch_id: String,
+    // False if the NCCH was already in the database
+    pub added: bool,
+}
+
+// Where an upload session is after the last request
+#[derive(Debug, Clone)]
+pub enum UploadStep {
+    Done(Uploaded),
+    // The region to send next with append_ncch
+    Need(AppendRequest),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IconSize {
+    Small,
+    Large,
+}
+
+impl IconSize {
+    fn file_name(self) -> &'static str {
+        match self {
+            IconSize::Small => "icon_small.png",
+            IconSize::Large => "icon_large.png",
+        }
+    }
+}
+
+fn upload_step(response: PostNcchResponse) -> Result<UploadStep, ClientError> {
+    match response {
+        PostNcchResponse::Finished(NcchExist { ncch_id }) => Ok(UploadStep::Done(Uploaded {
+            ncch_id,
+            added: true,
+        })),
+        PostNcchResponse::Conflict(NcchExist { ncch_id }) => Ok(UploadStep::Done(Uploaded {
+            ncch_id,
+            added: false,
+        })),
+        PostNcchResponse::AppendNeeded(request) => Ok(UploadStep::Need(request)),
+        PostNcchResponse::AlreadyFinished => Err(ClientError::AlreadyFinished),
+        PostNcchResponse::UnexpectedLength => Err(ClientError::UnexpectedLength),
+        PostNcchResponse::UnexpectedFormat => Err(ClientError::UnexpectedFormat),
+        PostNcchResponse::VerificationFailed => Err(ClientError::VerificationFailed),
+        PostNcchResponse::Busy => Err(ClientError::Busy),
+        PostNcchResponse::InternalServerError => Err(ClientError::InternalServerError),
+        PostNcchResponse::NotFound => Err(ClientError::NotFound),
+    }
+}
+
+fn ncch_info_result(response: NcchInfoResponse) -> Result<NcchInfo, ClientError> {
+    match response {
+        NcchInfoResponse::Ok(info) => Ok(info),
+        NcchInfoResponse::NotFound => Err(ClientError::NotFound),
+        NcchInfoResponse::InvalidParam => Err(ClientError::InvalidParam),
+        NcchInfoResponse::InternalServerError => Err(ClientError::InternalServerError),
+    }
+}
+
+fn query_result(response: NcchQueryResponse) -> Result<NcchInfoVec, ClientError> {
+    match response {
+        NcchQueryResponse::Ok(ncchs) => Ok(ncchs),
+        NcchQueryResponse::InvalidQuery(e) => Err(ClientError::InvalidQuery(e)),
+        NcchQueryResponse::InternalServerError => Err(ClientError::InternalServerError),
+    }
+}
+
+fn count_result(response: NcchQueryCountResponse) -> Result<i64, ClientError> {
+    match response {
+        NcchQueryCountResponse::Ok(NcchCount { count }) => Ok(count),
+        NcchQueryCountResponse::InvalidQuery(e) => Err(ClientError::InvalidQuery(e)),
+        NcchQueryCountResponse::InternalServerError => Err(ClientError::InternalServerError),
+    }
+}
+
+// Icons come as an image, but errors come as an NcchInfoResponse
+fn icon_error(body: Result<NcchInfoResponse, reqwest::Error>, status: u16) -> ClientError {
+    match body.map(ncch_info_result) {
+        Ok(Err(e)) => e,
+        _ => ClientError::UnexpectedStatus(status),
+    }
+}
+
+fn trim_host(host: &str) -> String {
+    host.trim_end_matches('/').to_owned()
+}
+
+// A region of the NCCH that starts at `base` of the source
+fn read_region<F: Read + Seek>(
+    source: &mut F,
+    base: u64,
+    offset: usize,
+    len: usize,
+) -> Result<Vec<u8>, ClientError> {
+    let mut data = vec![0; len];
+    source.seek(SeekFrom::Start(base + offset as u64))?;
+    source.read_exact(&mut data)?;
+    Ok(data)
+}
+
+// Runs the API of the backend on an empty in-memory database, with room for `max_sessions`
+// uploads at once. Returns the host, and the server, which stops when dropped.
+#[cfg(test)]
+fn serve(max_sessions: usize) -> (String, actix_http_test::TestServerRuntime) {
+    use actix_http::HttpService;
+    use actix_web::App;
+    use index3ds::database::Database;
+    use index3ds::Sessions;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let database = Arc::new(Database::connect_to("sqlite://:memory:"));
+    let sessions = Arc::new(Sessions::new(max_sessions, Duration::from_secs(60)));
+    let server = actix_http_test::TestServer::new(move || {
+        let database = database.clone();
+        let sessions = sessions.clone();
+        HttpService::new(
+            App::new().configure(move |config| index3ds::configure(config, &database, &sessions)),
+        )
+    });
+    (format!("http://{}", server.addr()), server)
+}
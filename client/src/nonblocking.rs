@@ -0,0 +1,215 @@
+use crate::{
+    count_result, icon_error, ncch_info_result, query_result, read_region, trim_host, upload_step,
+    ClientError, IconSize, UploadStep, Uploaded,
+};
+use futures::future::{self, Either, Loop};
+use futures::{Future, Stream};
+use index3ds_common::*;
+use reqwest::r#async::{Client, RequestBuilder};
+use serde::de::DeserializeOwned;
+use std::io::{Read, Seek};
+
+fn json<T: DeserializeOwned + Send + 'static>(
+    request: RequestBuilder,
+) -> impl Future<Item = T, Error = ClientError> {
+    request
+        .send()
+        .and_then(|mut response| response.json())
+        .from_err()
+}
+
+// A client whose requests are futures, to be run on a tokio runtime
+#[derive(Clone)]
+pub struct AsyncClient {
+    http: Client,
+    host: String,
+}
+
+impl AsyncClient {
+    // `host` is the root of the site, such as "http://127.0.0.1:8080"
+    pub fn new(host: &str) -> AsyncClient {
+        AsyncClient::with_http_client(Client::new(), host)
+    }
+
+    pub fn with_http_client(http: Client, host: &str) -> AsyncClient {
+        AsyncClient {
+            http,
+            host: trim_host(host),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.host, path)
+    }
+
+    fn post(
+        &self,
+        path: &str,
+        body: Vec<u8>,
+    ) -> impl Future<Item = UploadStep, Error = ClientError> {
+        json(self.http.post(&self.url(path)).body(body)).and_then(upload_step)
+    }
+
+    // Starts an upload session with the 0x200-byte NCCH header
+    pub fn post_ncch(
+        &self,
+        header: Vec<u8>,
+    ) -> impl Future<Item = UploadStep, Error = ClientError> {
+        self.post(url::post_ncch(), header)
+    }
+
+    pub fn append_ncch(
+        &self,
+        session_id: u32,
+        data: Vec<u8>,
+    ) -> impl Future<Item = UploadStep, Error = ClientError> {
+        self.post(&url::append_ncch(&session_id.to_string()), data)
+    }
+
+    // Uploads the NCCH at `offset` of the source, sending whatever the server asks for. The
+    // source is read in place, which blocks the runtime for as long as a read takes.
+    pub fn upload<F: Read + Seek + Send + 'static>(
+        &self,
+        mut source: F,
+        offset: u64,
+    ) -> impl Future<Item = Uploaded, Error = ClientError> {
+        let client = self.clone();
+        future::result(read_region(&mut source, offset, 0, 0x200))
+            .and_then(move |header| client.post_ncch(header).map(|step| (client, source, step)))
+            .and_then(move |state| {
+                future::loop_fn(state, move |(client, mut source, step)| match step {
+                    UploadStep::Done(uploaded) => Either::A(future::ok(Loop::Break(uploaded))),
+                    UploadStep::Need(request) => Either::B(
+                        future::result(read_region(
+                            &mut source,
+                            offset,
+                            request.offset,
+                            request.len,
+                        ))
+                        .and_then(move |data| {
+                            client
+                                .append_ncch(request.session_id, data)
+                                .map(|step| Loop::Continue((client, source, step)))
+                        }),
+                    ),
+                })
+            })
+    }
+
+    pub fn ncch_info(&self, ncch_id: &str) -> impl Future<Item = NcchInfo, Error = ClientError> {
+        json(self.http.get(&self.url(&url::ncch_info(ncch_id, "info")))).and_then(ncch_info_result)
+    }
+
+    pub fn query_ncch(
+        &self,
+        param: &NcchQueryParam,
+    ) -> impl Future<Item = NcchInfoVec, Error = ClientError> {
+        json(self.http.get(&self.url(url::query_ncch())).query(param)).and_then(query_result)
+    }
+
+    pub fn query_ncch_count(
+        &self,
+        filter: &NcchFilterParam,
+    ) -> impl Future<Item = i64, Error = ClientError> {
+        json(
+            self.http
+                .get(&self.url(url::query_ncch_count()))
+                .query(filter),
+        )
+        .and_then(count_result)
+    }
+
+    // The icon image, in the format asked for by `param`
+    pub fn icon(
+        &self,
+        ncch_id: &str,
+        size: IconSize,
+        param: &IconParam,
+    ) -> impl Future<Item = Vec<u8>, Error = ClientError> {
+        self.http
+            .get(&self.url(&url::ncch_info(ncch_id, size.file_name())))
+            .query(param)
+            .send()
+            .from_err()
+            .and_then(|mut response| {
+                let status = response.status();
+                if status.is_success() {
+                    Either::A(
+                        response
+                            .into_body()
+                            .concat2()
+                            .from_err()
+                            .map(|icon| icon.to_vec()),
+                    )
+                } else {
+                    Either::B(
+                        response
+                            .json()
+                            .then(move |body| Err(icon_error(body, status.as_u16()))),
+                    )
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serve;
+    use index3ds::fixture::fixture_ncch;
+    use std::io::Cursor;
+
+    fn run<F: Future>(future: F) -> Result<F::Item, F::Error> {
+        tokio::runtime::current_thread::block_on_all(future)
+    }
+
+    #[test]
+    fn upload_test() {
+        let (host, _server) = serve(16);
+        let client = AsyncClient::new(&host);
+        let uploaded = run(client.upload(Cursor::new(fixture_ncch()), 0)).unwrap();
+        assert!(uploaded.added);
+        let again = run(client.upload(Cursor::new(fixture_ncch()), 0)).unwrap();
+        assert!(!again.added);
+        let info = run(client.ncch_info(&uploaded.ncch_id)).unwrap();
+        assert_eq!(info.short_title.unwrap()[1], "Fixture");
+        match run(client.ncch_info("0")) {
+            Err(ClientError::NotFound) => (),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn query_test() {
+        let (host, _server) = serve(16);
+        let client = AsyncClient::new(&host);
+        run(client.upload(Cursor::new(fixture_ncch()), 0)).unwrap();
+        let mut filter = NcchFilterParam {
+            query: Some("product_code:CTR-N-*".to_owned()),
+            ..NcchFilterParam::default()
+        };
+        assert_eq!(run(client.query_ncch_count(&filter)).unwrap(), 0);
+        filter.query = Some("foo:1".to_owned());
+        match run(client.query_ncch_count(&filter)) {
+            Err(ClientError::InvalidQuery(_)) => (),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn icon_test() {
+        let (host, _server) = serve(16);
+        let client = AsyncClient::new(&host);
+        let uploaded = run(client.upload(Cursor::new(fixture_ncch()), 0)).unwrap();
+        let param = IconParam {
+            format: Some(IconFormat::Raw),
+            ..IconParam::default()
+        };
+        let icon = run(client.icon(&uploaded.ncch_id, IconSize::Large, &param)).unwrap();
+        assert_eq!(icon.len(), 48 * 48 * 2);
+        match run(client.icon("0", IconSize::Large, &param)) {
+            Err(ClientError::NotFound) => (),
+            other => panic!("{:?}", other),
+        }
+    }
+}
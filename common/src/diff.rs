@@ -0,0 +1,293 @@
+use crate::{NcchInfo, TITLE_LANGUAGES};
+use serde::{Deserialize, Serialize};
+
+// A field that differs between two NCCHs. Values are rendered as text, and None means the field
+// is absent, e.g. the NCCH has no Exheader or SMDH.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FieldDiff {
+    pub field: String,
+    pub a: Option<String>,
+    pub b: Option<String>,
+    // For lists, the elements only in a or only in b. For flags, the bits.
+    pub removed: Vec<String>,
+    pub added: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NcchDiff {
+    pub a: NcchInfo,
+    pub b: NcchInfo,
+    pub fields: Vec<FieldDiff>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NcchDiffParam {
+    pub a: String,
+    pub b: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "status")]
+pub enum NcchDiffResponse {
+    Ok(NcchDiff),
+    NotFound,
+    InternalServerError,
+}
+
+fn text<T: ToString>(value: &Option<T>) -> Option<String> {
+    value.as_ref().map(ToString::to_string)
+}
+
+fn hex<T: std::fmt::UpperHex>(value: &Option<T>, width: usize) -> Option<String> {
+    value
+        .as_ref()
+        .map(|v| format!("{:0width$X}", v, width = width))
+}
+
+fn join<T: ToString>(list: &Option<Vec<T>>) -> Option<String> {
+    list.as_ref().map(|list| {
+        list.iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    })
+}
+
+fn bits(value: Option<u64>) -> impl Iterator<Item = u32> {
+    let value = value.unwrap_or(0);
+    (0..64).filter(move |i| value & (1 << i) != 0)
+}
+
+#[derive(Default)]
+struct Differ {
+    fields: Vec<FieldDiff>,
+}
+
+impl Differ {
+    fn value(&mut self, field: &str, a: Option<String>, b: Option<String>) {
+        self.push(field, a, b, vec![], vec![]);
+    }
+
+    // Lists where order doesn't matter, such as services
+    fn set(&mut self, field: &str, a: Option<Vec<String>>, b: Option<Vec<String>>) {
+        let (list_a, list_b) = (a.clone().unwrap_or_default(), b.clone().unwrap_or_default());
+        let removed = list_a
+            .iter()
+            .filter(|e| !list_b.contains(e))
+            .cloned()
+            .collect::<Vec<_>>();
+        let added = list_b
+            .iter()
+            .filter(|e| !list_a.contains(e))
+            .cloned()
+            .collect::<Vec<_>>();
+        // Reordering alone is not a difference
+        if a.is_some() != b.is_some() || !removed.is_empty() || !added.is_empty() {
+            self.push(field, join(&a), join(&b), removed, added);
+        }
+    }
+
+    fn flags(&mut self, field: &str, a: Option<u64>, b: Option<u64>, width: usize) {
+        let removed = bits(a).filter(|&i| b.unwrap_or(0) & (1 << i) == 0);
+        let added = bits(b).filter(|&i| a.unwrap_or(0) & (1 << i) == 0);
+        self.push(
+            field,
+            hex(&a, width),
+            hex(&b, width),
+            removed.map(|i| i.to_string()).collect(),
+            added.map(|i| i.to_string()).collect(),
+        );
+    }
+
+    // One field per language, e.g. short_title.en
+    fn titles(&mut self, field: &str, a: &Option<Vec<String>>, b: &Option<Vec<String>>) {
+        for (i, language) in TITLE_LANGUAGES.iter().enumerate() {
+            let title =
+                |titles: &Option<Vec<String>>| titles.as_ref().and_then(|t| t.get(i).cloned());
+            self.value(&format!("{}.{}", field, language), title(a), title(b));
+        }
+    }
+
+    fn push(
+        &mut self,
+        field: &str,
+        a: Option<String>,
+        b: Option<String>,
+        removed: Vec<String>,
+        added: Vec<String>,
+    ) {
+        if a != b {
+            self.fields.push(FieldDiff {
+                field: field.to_owned(),
+                a,
+                b,
+                removed,
+                added,
+            })
+        }
+    }
+}
+
+impl NcchDiff {
+    // Compares every field but the ID, in the order they are declared in NcchInfo
+    pub fn new(a: NcchInfo, b: NcchInfo) -> NcchDiff {
+        let mut d = Differ::default();
+        macro_rules! value {
+            ($field:ident) => {
+                d.value(
+                    stringify!($field),
+                    text(&Some(&a.$field)),
+                    text(&Some(&b.$field)),
+                )
+            };
+            ($field:ident?) => {
+                d.value(stringify!($field), text(&a.$field), text(&b.$field))
+            };
+        }
+
+        d.value(
+            "ncch_signature",
+            Some(base64::encode(&a.ncch_signature)),
+            Some(base64::encode(&b.ncch_signature)),
+        );
+        value!(content_size);
+        value!(partition_id);
+        value!(maker_code);
+        value!(maker_name?);
+        value!(ncch_version);
+        value!(program_id);
+        value!(product_code);
+        value!(secondary_key_slot);
+        value!(platform);
+        value!(content_is_data);
+        value!(content_is_executable);
+        value!(content_category);
+        value!(content_unit_size);
+        value!(fixed_key);
+        value!(no_romfs);
+        value!(no_crypto);
+        value!(seed_crypto);
+
+        value!(exheader_name?);
+        value!(sd_app?);
+        value!(remaster_version?);
+        d.set(
+            "dependencies",
+            a.dependencies.clone(),
+            b.dependencies.clone(),
+        );
+        value!(save_data_size?);
+        value!(jump_id?);
+        value!(exheader_program_id?);
+        value!(core_version?);
+        value!(enable_l2_cache?);
+        value!(high_cpu_speed?);
+        value!(system_mode?);
+        value!(n3ds_system_mode?);
+        value!(ideal_processor?);
+        value!(affinity_mask?);
+        value!(thread_priority?);
+        d.value(
+            "resource_limit_desc",
+            join(&a.resource_limit_desc),
+            join(&b.resource_limit_desc),
+        );
+        value!(extdata_id?);
+        value!(system_savedata_id0?);
+        value!(system_savedata_id1?);
+        value!(storage_access_id?);
+        d.flags("filesystem_flag", a.filesystem_flag, b.filesystem_flag, 16);
+        d.set("services", a.services.clone(), b.services.clone());
+        value!(resource_limit_category?);
+        let kernel_desc = |info: &NcchInfo| {
+            info.kernel_desc
+                .as_ref()
+                .map(|desc| desc.iter().map(|d| format!("{:08X}", d)).collect())
+        };
+        d.set("kernel_desc", kernel_desc(&a), kernel_desc(&b));
+        let arm9_flag = |info: &NcchInfo| info.arm9_flag.map(u64::from);
+        d.flags("arm9_flag", arm9_flag(&a), arm9_flag(&b), 8);
+        value!(arm9_flag_version?);
+
+        d.titles("short_title", &a.short_title, &b.short_title);
+        d.titles("long_title", &a.long_title, &b.long_title);
+        d.titles("publisher", &a.publisher, &b.publisher);
+        d.value("ratings", join(&a.ratings), join(&b.ratings));
+        let region_lockout = |info: &NcchInfo| info.region_lockout.map(u64::from);
+        d.flags("region_lockout", region_lockout(&a), region_lockout(&b), 8);
+        value!(region?);
+        value!(match_maker_id?);
+        value!(match_maker_bit_id?);
+        let smdh_flags = |info: &NcchInfo| info.smdh_flags.map(u64::from);
+        d.flags("smdh_flags", smdh_flags(&a), smdh_flags(&b), 8);
+        value!(eula_version?);
+        value!(cec_id?);
+        let releases = |info: &NcchInfo| {
+            Some(
+                info.releases
+                    .iter()
+                    .map(|release| format!("{} {}", release.serial, release.name))
+                    .collect(),
+            )
+        };
+        d.set("releases", releases(&a), releases(&b));
+
+        NcchDiff {
+            a,
+            b,
+            fields: d.fields,
+        }
+    }
+}
+
+#[test]
+fn differ_test() {
+    let list = |l: &[&str]| Some(l.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+    let mut d = Differ::default();
+    d.set(
+        "services",
+        list(&["fs:USER", "hid:USER"]),
+        list(&["hid:USER", "fs:USER"]),
+    );
+    assert!(d.fields.is_empty());
+    d.set(
+        "services",
+        list(&["fs:USER", "ac:u"]),
+        list(&["fs:USER", "cfg:u"]),
+    );
+    d.flags("smdh_flags", Some(0b0101), Some(0b0110), 8);
+    d.titles("short_title", &list(&["a", "b"]), &None);
+    assert_eq!(
+        d.fields,
+        vec![
+            FieldDiff {
+                field: "services".to_owned(),
+                a: Some("fs:USER, ac:u".to_owned()),
+                b: Some("fs:USER, cfg:u".to_owned()),
+                removed: vec!["ac:u".to_owned()],
+                added: vec!["cfg:u".to_owned()],
+            },
+            FieldDiff {
+                field: "smdh_flags".to_owned(),
+                a: Some("00000005".to_owned()),
+                b: Some("00000006".to_owned()),
+                removed: vec!["0".to_owned()],
+                added: vec!["1".to_owned()],
+            },
+            FieldDiff {
+                field: "short_title.ja".to_owned(),
+                a: Some("a".to_owned()),
+                b: None,
+                removed: vec![],
+                added: vec![],
+            },
+            FieldDiff {
+                field: "short_title.en".to_owned(),
+                a: Some("b".to_owned()),
+                b: None,
+                removed: vec![],
+                added: vec![],
+            },
+        ]
+    );
+}
@@ -1,5 +1,9 @@
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+pub mod diff;
+pub mod product_code;
+pub mod query;
+
 fn as_base64<S>(data: &Vec<u8>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
@@ -24,6 +28,8 @@ pub struct NcchInfo {
     pub content_size: u32,
     pub partition_id: String,
     pub maker_code: String,
+    // From the maker registry, None if the code is not registered
+    pub maker_name: Option<String>,
     pub ncch_version: u16,
     pub program_id: String,
     pub product_code: String,
@@ -70,11 +76,27 @@ pub struct NcchInfo {
     pub publisher: Option<Vec<String>>,
     pub ratings: Option<Vec<u8>>,
     pub region_lockout: Option<u32>,
+    // Inferred from the product code and the region lockout
+    pub region: Option<String>,
     pub match_maker_id: Option<String>,
     pub match_maker_bit_id: Option<String>,
     pub smdh_flags: Option<u32>,
     pub eula_version: Option<u16>,
     pub cec_id: Option<String>,
+
+    // From the imported release list. Missing in dumps from before releases were imported.
+    #[serde(default)]
+    pub releases: Vec<Release>,
+}
+
+// An official release that an NCCH belongs to
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Release {
+    pub serial: String,
+    pub name: String,
+    pub region: Option<String>,
+    pub publisher: Option<String>,
+    pub release_date: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -82,6 +104,140 @@ pub struct NcchInfo {
 pub enum NcchInfoResponse {
     Ok(NcchInfo),
     NotFound,
+    InvalidParam,
+    InternalServerError,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IconFormat {
+    Png,
+    Bmp,
+    Ico,
+    // The tiled RGB565 pixels as stored in the SMDH
+    Raw,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IconFilter {
+    Nearest,
+    Bilinear,
+}
+
+// Options for icon_small.png and icon_large.png. Without any, the icon is served as a PNG at its
+// native 24 or 48 pixel size.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct IconParam {
+    pub format: Option<IconFormat>,
+    pub size: Option<StringWrapper<u32>>,
+    pub filter: Option<IconFilter>,
+}
+
+pub const MAX_ICON_SIZE: u32 = 256;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IconOffset {
+    pub ncch_id: String,
+    pub x: u32,
+    pub y: u32,
+}
+
+// The small icons of one page of query results, laid out in one sheet served by icon_sheet.png.
+// This is the offset index returned by query_ncch_icons.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NcchIconSheet {
+    // URL of the sheet, empty if there are no icons
+    pub image: String,
+    pub icon_size: u32,
+    // NCCHs without an icon are left out, and NCCHs with the same icon share its offset
+    pub icons: Vec<IconOffset>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "status")]
+pub enum NcchIconSheetResponse {
+    Ok(NcchIconSheet),
+    InvalidQuery(query::QueryError),
+    InternalServerError,
+}
+
+// The small icons to pack into icon_sheet.png, as their hashes joined by commas. Each icon comes
+// once, and the URL only changes with the icons, so the sheet can be cached for good.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IconSheetParam {
+    pub icons: String,
+}
+
+pub const ICON_SHEET_COLUMNS: u32 = 10;
+pub const MAX_ICON_SHEET_ICONS: usize = 100;
+
+// Distance is the number of differing bits between the perceptual hashes of the large icons
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SimilarNcch {
+    pub distance: u32,
+    pub ncch: NcchInfo,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SimilarNcchVec {
+    pub ncchs: Vec<SimilarNcch>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "status")]
+pub enum NcchSimilarIconsResponse {
+    Ok(SimilarNcchVec),
+    NotFound,
+    InternalServerError,
+}
+
+// Hex SHA-256 of an NCCH header, Exheader or SMDH, or the hex 0x100-byte NCCH signature
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LookupParam {
+    pub hash: String,
+}
+
+// Kind is one of "header", "exheader", "smdh" and "signature". The hashes are of the whole
+// section, 0x800 bytes for the Exheader.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LookupMatch {
+    pub kind: String,
+    pub ncch: NcchInfo,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LookupMatchVec {
+    pub ncchs: Vec<LookupMatch>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "status")]
+pub enum NcchLookupResponse {
+    Ok(LookupMatchVec),
+    InvalidParam,
+    InternalServerError,
+}
+
+pub const LOOKUP_BATCH_LIMIT: usize = 5000;
+
+// Inputs of 16 hex digits are program IDs, anything else is an NCCH ID
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LookupBatchRequest {
+    pub ids: Vec<String>,
+}
+
+// Keyed by the inputs as given. Inputs without a match have an empty list.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LookupBatch {
+    pub ncchs: std::collections::BTreeMap<String, Vec<NcchInfo>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "status")]
+pub enum NcchLookupBatchResponse {
+    Ok(LookupBatch),
+    InvalidParam,
     InternalServerError,
 }
 
@@ -159,12 +315,14 @@ impl<T: std::string::ToString + std::str::FromStr> StringWrapper<T> {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct NcchFilterParam {
     pub keyword: Option<String>,
+    pub query: Option<String>,
 
     pub content_size_cmp: Option<Comparator>,
     pub content_size_rhs: Option<StringWrapper<u32>>,
     pub partition_id: Option<String>,
     pub partition_id_mask: Option<String>,
     pub maker_code: Option<String>,
+    pub maker_name: Option<String>,
     pub ncch_version_cmp: Option<Comparator>,
     pub ncch_version_rhs: Option<StringWrapper<u16>>,
     pub program_id: Option<String>,
@@ -236,10 +394,22 @@ pub struct NcchFilterParam {
     pub cec_id: Option<String>,
 }
 
+// SMDH title languages in the order they are stored
+pub const TITLE_LANGUAGES: &[&str] = &[
+    "ja", "en", "fr", "de", "it", "es", "zh-hans", "ko", "nl", "pt", "ru", "zh-hant",
+];
+
+pub fn title_language(code: &str) -> Option<usize> {
+    TITLE_LANGUAGES.iter().position(|&l| l == code)
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NcchQueryParam {
     pub offset: i64,
     pub limit: i64,
+    pub facets: Option<StringWrapper<bool>>,
+    // Language code to sort by short title in, instead of by program ID
+    pub order_by_title: Option<String>,
     #[serde(flatten)]
     pub filter: NcchFilterParam,
 }
@@ -247,12 +417,14 @@ pub struct NcchQueryParam {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NcchInfoVec {
     pub ncchs: Vec<NcchInfo>,
+    pub facets: Option<NcchFacets>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "status")]
 pub enum NcchQueryResponse {
     Ok(NcchInfoVec),
+    InvalidQuery(query::QueryError),
     InternalServerError,
 }
 
@@ -265,6 +437,97 @@ pub struct NcchCount {
 #[serde(tag = "status")]
 pub enum NcchQueryCountResponse {
     Ok(NcchCount),
+    InvalidQuery(query::QueryError),
+    InternalServerError,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StatsBucket {
+    pub key: Option<String>,
+    pub count: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NcchStats {
+    pub count: i64,
+    pub platform: Vec<StatsBucket>,
+    pub content_category: Vec<StatsBucket>,
+    pub maker_code: Vec<StatsBucket>,
+    // one bucket per region lockout bit, keyed by the bit index
+    pub region_lockout: Vec<StatsBucket>,
+    pub system_mode: Vec<StatsBucket>,
+    pub core_version: Vec<StatsBucket>,
+    // power-of-two buckets, keyed by the lower bound in bytes
+    pub content_size: Vec<StatsBucket>,
+    pub save_data_size: Vec<StatsBucket>,
+}
+
+// boolean flags are keyed by 0 and 1
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NcchFacets {
+    pub content_is_data: Vec<StatsBucket>,
+    pub content_is_executable: Vec<StatsBucket>,
+    pub fixed_key: Vec<StatsBucket>,
+    pub no_romfs: Vec<StatsBucket>,
+    pub no_crypto: Vec<StatsBucket>,
+    pub seed_crypto: Vec<StatsBucket>,
+    pub sd_app: Vec<StatsBucket>,
+    pub enable_l2_cache: Vec<StatsBucket>,
+    pub high_cpu_speed: Vec<StatsBucket>,
+    pub platform: Vec<StatsBucket>,
+    pub content_category: Vec<StatsBucket>,
+    pub region_lockout: Vec<StatsBucket>,
+    pub maker_code: Vec<StatsBucket>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "status")]
+pub enum NcchStatsResponse {
+    Ok(NcchStats),
+    InvalidQuery(query::QueryError),
+    InternalServerError,
+}
+
+// A registered maker code with the number of NCCHs that have it. Several codes can belong to
+// the same company.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Maker {
+    pub code: String,
+    pub name: String,
+    pub count: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MakerVec {
+    pub makers: Vec<Maker>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "status")]
+pub enum MakerResponse {
+    Ok(MakerVec),
+    InternalServerError,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Ndjson,
+    Csv,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NcchExportParam {
+    pub format: ExportFormat,
+    #[serde(flatten)]
+    pub filter: NcchFilterParam,
+}
+
+// Only used for errors. A successful export is streamed as NDJSON or CSV instead.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "status")]
+pub enum NcchExportResponse {
+    InvalidQuery(query::QueryError),
     InternalServerError,
 }
 
@@ -298,10 +561,58 @@ pub mod url {
         "/query_ncch"
     }
 
+    pub fn query_ncch_icons() -> &'static str {
+        "/query_ncch_icons"
+    }
+
+    pub fn icon_sheet() -> &'static str {
+        "/icon_sheet.png"
+    }
+
     pub fn query_ncch_count() -> &'static str {
         "/query_ncch_count"
     }
 
+    pub fn stats() -> &'static str {
+        "/stats"
+    }
+
+    pub fn statistics() -> &'static str {
+        "/statistics"
+    }
+
+    pub fn export() -> &'static str {
+        "/export"
+    }
+
+    pub fn dat() -> &'static str {
+        "/dat"
+    }
+
+    pub fn lookup() -> &'static str {
+        "/lookup"
+    }
+
+    pub fn lookup_batch() -> &'static str {
+        "/lookup_batch"
+    }
+
+    pub fn makers() -> &'static str {
+        "/makers"
+    }
+
+    pub fn publishers() -> &'static str {
+        "/publishers"
+    }
+
+    pub fn diff() -> &'static str {
+        "/diff"
+    }
+
+    pub fn compare() -> &'static str {
+        "/compare"
+    }
+
     pub fn not_found_small() -> &'static str {
         "/notfound24.png"
     }
@@ -313,5 +624,4 @@ pub mod url {
     pub fn about() -> &'static str {
         "/about"
     }
-
 }
@@ -0,0 +1,162 @@
+// Product codes look like CTR-P-AXCE: platform, media, then a game code whose last letter is the
+// release region
+
+// Region lockout bits of the SMDH
+pub const REGION_NAME: &[&str] = &["JPN", "USA", "EUR", "AUS", "CHN", "KOR", "TWN"];
+// Region free, whether by lockout or by product code
+pub const REGION_ALL: &str = "ALL";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProductCode {
+    // CTR for 3DS, KTR for New 3DS exclusives
+    pub platform: String,
+    pub media: char,
+    pub game_code: String,
+}
+
+impl ProductCode {
+    pub fn parse(code: &str) -> Option<ProductCode> {
+        let mut parts = code.split('-');
+        let platform = parts.next()?;
+        let media = parts.next()?;
+        let game_code = parts.next()?;
+        if parts.next().is_some()
+            || platform.len() != 3
+            || media.len() != 1
+            || game_code.len() != 4
+            || !code.bytes().all(|c| c == b'-' || c.is_ascii_alphanumeric())
+        {
+            return None;
+        }
+        Some(ProductCode {
+            platform: platform.to_owned(),
+            media: media.chars().next()?,
+            game_code: game_code.to_owned(),
+        })
+    }
+
+    pub fn platform_name(&self) -> Option<&'static str> {
+        match self.platform.as_str() {
+            "CTR" => Some("3DS"),
+            "KTR" => Some("New 3DS"),
+            _ => None,
+        }
+    }
+
+    pub fn media_name(&self) -> Option<&'static str> {
+        match self.media {
+            'P' => Some("Game Card"),
+            'N' => Some("Download"),
+            'U' => Some("Update"),
+            'M' => Some("Add-on Content"),
+            _ => None,
+        }
+    }
+
+    pub fn region_letter(&self) -> char {
+        self.game_code.chars().last().unwrap_or(' ')
+    }
+
+    pub fn region(&self) -> Option<&'static str> {
+        match self.region_letter() {
+            'A' => Some(REGION_ALL),
+            'J' => Some("JPN"),
+            'E' => Some("USA"),
+            // Europe, with some languages having their own letter
+            'P' | 'D' | 'F' | 'I' | 'S' | 'H' | 'R' | 'V' | 'Z' => Some("EUR"),
+            'U' => Some("AUS"),
+            'C' => Some("CHN"),
+            'K' => Some("KOR"),
+            _ => None,
+        }
+    }
+}
+
+fn lockout_region(lockout: u32) -> Option<&'static str> {
+    let all = (1 << REGION_NAME.len()) - 1;
+    match lockout & all {
+        x if x == all => Some(REGION_ALL),
+        // Europe and Australia share releases
+        0b1100 => Some("EUR"),
+        x if x.count_ones() == 1 => REGION_NAME.get(x.trailing_zeros() as usize).cloned(),
+        _ => None,
+    }
+}
+
+// The product code region, as long as the region lockout allows it. Otherwise the lockout alone
+// decides, since that is what the console enforces.
+pub fn infer_region(product_code: &str, region_lockout: Option<u32>) -> Option<&'static str> {
+    let region = ProductCode::parse(product_code).and_then(|code| code.region());
+    let lockout = match region_lockout {
+        Some(lockout) => lockout,
+        None => return region,
+    };
+    let allowed = match region {
+        Some(REGION_ALL) => lockout_region(lockout) == Some(REGION_ALL),
+        Some(region) => {
+            let bit = REGION_NAME.iter().position(|&r| r == region).unwrap();
+            lockout & (1 << bit) != 0
+        }
+        None => false,
+    };
+    if allowed {
+        region
+    } else {
+        lockout_region(lockout)
+    }
+}
+
+// Product code patterns for search, where * matches any run of characters and ? matches one
+pub fn is_wildcard(pattern: &str) -> bool {
+    pattern.contains(&['*', '?'][..])
+}
+
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), text.chars().collect());
+    // matched[j]: whether the pattern so far matches the first j characters of text
+    let mut matched = vec![false; text.len() + 1];
+    matched[0] = true;
+    for p in pattern {
+        let mut next = vec![false; text.len() + 1];
+        for j in 0..=text.len() {
+            next[j] = match p {
+                '*' => matched[j] || (j > 0 && next[j - 1]),
+                '?' => j > 0 && matched[j - 1],
+                p => j > 0 && matched[j - 1] && text[j - 1] == p,
+            };
+        }
+        matched = next;
+    }
+    matched[text.len()]
+}
+
+#[test]
+fn product_code_test() {
+    let code = ProductCode::parse("CTR-P-AXCE").unwrap();
+    assert_eq!(code.platform_name(), Some("3DS"));
+    assert_eq!(code.media_name(), Some("Game Card"));
+    assert_eq!(code.game_code, "AXCE");
+    assert_eq!(code.region(), Some("USA"));
+    assert_eq!(ProductCode::parse("CTR-P-AXC"), None);
+    assert_eq!(ProductCode::parse("CTR-P-AXCE-X"), None);
+
+    assert_eq!(infer_region("CTR-P-AXCE", Some(0b10)), Some("USA"));
+    assert_eq!(infer_region("CTR-P-AXCE", None), Some("USA"));
+    assert_eq!(infer_region("CTR-P-AXCE", Some(0x7FFFFFFF)), Some("USA"));
+    // Locked to Japan despite the letter
+    assert_eq!(infer_region("CTR-P-AXCE", Some(0b1)), Some("JPN"));
+    assert_eq!(infer_region("CTR-N-HXXU", Some(0b1100)), Some("AUS"));
+    assert_eq!(infer_region("CTR-N-HXXX", Some(0b1100)), Some("EUR"));
+    assert_eq!(infer_region("CTR-P-CTAP", Some(0x7FFFFFFF)), Some("EUR"));
+    assert_eq!(infer_region("CTR-P-CTAA", Some(0x7FFFFFFF)), Some("ALL"));
+    assert_eq!(infer_region("CTR-P-CTAA", Some(0b1)), Some("JPN"));
+    assert_eq!(infer_region("CTR-P-CTAA", Some(0b11)), None);
+    assert_eq!(infer_region("", None), None);
+
+    assert!(wildcard_match("CTR-P-AXC?", "CTR-P-AXCE"));
+    assert!(!wildcard_match("CTR-P-AXC?", "CTR-P-AXC"));
+    assert!(wildcard_match("CTR-*", "CTR-N-JAAE"));
+    assert!(wildcard_match("*-AXC*", "KTR-P-AXCJ"));
+    assert!(!wildcard_match("CTR-*", "KTR-P-AXCJ"));
+}
@@ -0,0 +1,423 @@
+use crate::Comparator;
+use serde::{Deserialize, Serialize};
+
+// Boolean search query, e.g.
+// maker:01 AND (platform:2 OR n3ds_mode>0) AND NOT service:"ac:u" title:"mario"
+//
+// Grammar (juxtaposed terms are implicitly ANDed):
+//   or   := and ("OR" and)*
+//   and  := not ("AND"? not)*
+//   not  := "NOT" not | atom
+//   atom := "(" or ")" | term
+//   term := word | field (":" | "=" | "!=" | "<" | "<=" | ">" | ">=") word
+//   word := bare text | quoted text
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QueryTerm {
+    pub position: usize,
+    pub field: Option<String>,
+    pub comparator: Comparator,
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum QueryExpr {
+    And(Box<QueryExpr>, Box<QueryExpr>),
+    Or(Box<QueryExpr>, Box<QueryExpr>),
+    Not(Box<QueryExpr>),
+    Term(QueryTerm),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind")]
+pub enum QueryErrorKind {
+    UnexpectedEnd,
+    UnexpectedToken { token: String },
+    UnclosedQuote,
+    UnclosedParen,
+    UnknownField { field: String },
+    InvalidValue { field: String, value: String },
+    UnsupportedComparator { field: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QueryError {
+    pub position: usize,
+    #[serde(flatten)]
+    pub kind: QueryErrorKind,
+}
+
+impl QueryError {
+    pub fn new(position: usize, kind: QueryErrorKind) -> QueryError {
+        QueryError { position, kind }
+    }
+}
+
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            QueryErrorKind::UnexpectedEnd => write!(f, "unexpected end of query"),
+            QueryErrorKind::UnexpectedToken { token } => write!(f, "unexpected \"{}\"", token),
+            QueryErrorKind::UnclosedQuote => write!(f, "unclosed quote"),
+            QueryErrorKind::UnclosedParen => write!(f, "unclosed parenthesis"),
+            // Titles such as "Zelda: Ocarina" look like a field search, so point at the fix
+            QueryErrorKind::UnknownField { field } => write!(
+                f,
+                "unknown field \"{}\"; put text containing \":\" in quotes to search for it",
+                field
+            ),
+            QueryErrorKind::InvalidValue { field, value } => {
+                write!(f, "invalid value \"{}\" for field \"{}\"", value, field)
+            }
+            QueryErrorKind::UnsupportedComparator { field } => {
+                write!(f, "unsupported comparator for field \"{}\"", field)
+            }
+        }?;
+        write!(f, " at position {}", self.position)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Op(Comparator),
+    LeftParen,
+    RightParen,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(s) => s.clone(),
+            Token::Quoted(s) => format!("\"{}\"", s),
+            Token::Op(Comparator::Eq) => "=".to_owned(),
+            Token::Op(Comparator::Ne) => "!=".to_owned(),
+            Token::Op(Comparator::Lt) => "<".to_owned(),
+            Token::Op(Comparator::Le) => "<=".to_owned(),
+            Token::Op(Comparator::Gt) => ">".to_owned(),
+            Token::Op(Comparator::Ge) => ">=".to_owned(),
+            Token::LeftParen => "(".to_owned(),
+            Token::RightParen => ")".to_owned(),
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        match self {
+            Token::Word(s) => s == keyword,
+            _ => false,
+        }
+    }
+}
+
+fn tokenize(query: &str) -> Result<Vec<(usize, Token)>, QueryError> {
+    let chars: Vec<(usize, char)> = query.char_indices().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let (position, c) = chars[i];
+        let next = chars.get(i + 1).map(|&(_, c)| c);
+        match c {
+            _ if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push((position, Token::LeftParen));
+                i += 1;
+            }
+            ')' => {
+                tokens.push((position, Token::RightParen));
+                i += 1;
+            }
+            ':' | '=' => {
+                tokens.push((position, Token::Op(Comparator::Eq)));
+                i += 1;
+            }
+            '!' if next == Some('=') => {
+                tokens.push((position, Token::Op(Comparator::Ne)));
+                i += 2;
+            }
+            '!' => {
+                return Err(QueryError::new(
+                    position,
+                    QueryErrorKind::UnexpectedToken {
+                        token: "!".to_owned(),
+                    },
+                ))
+            }
+            '<' | '>' => {
+                let or_equal = next == Some('=');
+                let comparator = match (c, or_equal) {
+                    ('<', false) => Comparator::Lt,
+                    ('<', true) => Comparator::Le,
+                    ('>', false) => Comparator::Gt,
+                    _ => Comparator::Ge,
+                };
+                tokens.push((position, Token::Op(comparator)));
+                i += if or_equal { 2 } else { 1 };
+            }
+            '"' => {
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => {
+                            return Err(QueryError::new(position, QueryErrorKind::UnclosedQuote))
+                        }
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) if i + 1 < chars.len() => {
+                            text.push(chars[i + 1].1);
+                            i += 2;
+                        }
+                        Some(&(_, c)) => {
+                            text.push(c);
+                            i += 1;
+                        }
+                    }
+                }
+                tokens.push((position, Token::Quoted(text)));
+                i += 1;
+            }
+            _ => {
+                let mut text = String::new();
+                while let Some(&(_, c)) = chars.get(i) {
+                    if c.is_whitespace() || "()\":=!<>".contains(c) {
+                        break;
+                    }
+                    text.push(c);
+                    i += 1;
+                }
+                // Every character that stops a word has an arm above, so this can't happen, but
+                // an empty word would never advance
+                if text.is_empty() {
+                    return Err(QueryError::new(
+                        position,
+                        QueryErrorKind::UnexpectedToken {
+                            token: c.to_string(),
+                        },
+                    ));
+                }
+                tokens.push((position, Token::Word(text)));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    cursor: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.cursor).map(|(_, t)| t)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.cursor)
+            .map(|&(p, _)| p)
+            .unwrap_or(self.end)
+    }
+
+    fn unexpected(&self) -> QueryError {
+        match self.tokens.get(self.cursor) {
+            Some((position, token)) => QueryError::new(
+                *position,
+                QueryErrorKind::UnexpectedToken {
+                    token: token.describe(),
+                },
+            ),
+            None => QueryError::new(self.end, QueryErrorKind::UnexpectedEnd),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<QueryExpr, QueryError> {
+        let mut expr = self.parse_and()?;
+        while self.peek().map_or(false, |t| t.is_keyword("OR")) {
+            self.cursor += 1;
+            let rhs = self.parse_and()?;
+            expr = QueryExpr::Or(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<QueryExpr, QueryError> {
+        let mut expr = self.parse_not()?;
+        loop {
+            match self.peek() {
+                Some(t) if t.is_keyword("AND") => self.cursor += 1,
+                Some(t) if t.is_keyword("OR") => break,
+                Some(Token::RightParen) | None => break,
+                _ => (),
+            }
+            let rhs = self.parse_not()?;
+            expr = QueryExpr::And(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<QueryExpr, QueryError> {
+        if self.peek().map_or(false, |t| t.is_keyword("NOT")) {
+            self.cursor += 1;
+            Ok(QueryExpr::Not(Box::new(self.parse_not()?)))
+        } else {
+            self.parse_atom()
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<QueryExpr, QueryError> {
+        let position = self.position();
+        match self.peek().cloned() {
+            Some(Token::LeftParen) => {
+                self.cursor += 1;
+                let expr = self.parse_or()?;
+                if self.peek() != Some(&Token::RightParen) {
+                    return Err(QueryError::new(position, QueryErrorKind::UnclosedParen));
+                }
+                self.cursor += 1;
+                Ok(expr)
+            }
+            Some(Token::Quoted(value)) => {
+                self.cursor += 1;
+                Ok(QueryExpr::Term(QueryTerm {
+                    position,
+                    field: None,
+                    comparator: Comparator::Eq,
+                    value,
+                }))
+            }
+            Some(Token::Word(word)) => {
+                self.cursor += 1;
+                if let Some(Token::Op(comparator)) = self.peek().cloned() {
+                    self.cursor += 1;
+                    let value = match self.peek().cloned() {
+                        Some(Token::Word(value)) | Some(Token::Quoted(value)) => value,
+                        _ => return Err(self.unexpected()),
+                    };
+                    self.cursor += 1;
+                    Ok(QueryExpr::Term(QueryTerm {
+                        position,
+                        field: Some(word),
+                        comparator,
+                        value,
+                    }))
+                } else {
+                    Ok(QueryExpr::Term(QueryTerm {
+                        position,
+                        field: None,
+                        comparator: Comparator::Eq,
+                        value: word,
+                    }))
+                }
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+}
+
+pub fn parse(query: &str) -> Result<QueryExpr, QueryError> {
+    let mut parser = Parser {
+        tokens: tokenize(query)?,
+        cursor: 0,
+        end: query.len(),
+    };
+    let expr = parser.parse_or()?;
+    if parser.cursor != parser.tokens.len() {
+        return Err(parser.unexpected());
+    }
+    Ok(expr)
+}
+
+#[test]
+fn parse_test() {
+    fn term(
+        field: Option<&str>,
+        comparator: Comparator,
+        value: &str,
+        position: usize,
+    ) -> QueryExpr {
+        QueryExpr::Term(QueryTerm {
+            position,
+            field: field.map(str::to_owned),
+            comparator,
+            value: value.to_owned(),
+        })
+    }
+    let and = |a, b| QueryExpr::And(Box::new(a), Box::new(b));
+    let or = |a, b| QueryExpr::Or(Box::new(a), Box::new(b));
+    let not = |a| QueryExpr::Not(Box::new(a));
+
+    assert_eq!(
+        parse("maker:01 AND (platform:2 OR n3ds_mode>0) AND NOT service:\"ac:u\" title:\"mario\""),
+        Ok(and(
+            and(
+                and(
+                    term(Some("maker"), Comparator::Eq, "01", 0),
+                    or(
+                        term(Some("platform"), Comparator::Eq, "2", 14),
+                        term(Some("n3ds_mode"), Comparator::Gt, "0", 28)
+                    )
+                ),
+                not(term(Some("service"), Comparator::Eq, "ac:u", 49))
+            ),
+            term(Some("title"), Comparator::Eq, "mario", 64)
+        ))
+    );
+
+    assert_eq!(
+        parse("zelda core_version>=2"),
+        Ok(and(
+            term(None, Comparator::Eq, "zelda", 0),
+            term(Some("core_version"), Comparator::Ge, "2", 6)
+        ))
+    );
+
+    assert_eq!(
+        parse("(a OR b"),
+        Err(QueryError::new(0, QueryErrorKind::UnclosedParen))
+    );
+    assert_eq!(
+        parse("title:\"mario"),
+        Err(QueryError::new(6, QueryErrorKind::UnclosedQuote))
+    );
+    assert_eq!(
+        parse("a AND"),
+        Err(QueryError::new(5, QueryErrorKind::UnexpectedEnd))
+    );
+    assert_eq!(
+        parse("a )"),
+        Err(QueryError::new(
+            2,
+            QueryErrorKind::UnexpectedToken {
+                token: ")".to_owned()
+            }
+        ))
+    );
+}
+
+#[test]
+fn tokenize_test() {
+    let bang = |position| {
+        Err(QueryError::new(
+            position,
+            QueryErrorKind::UnexpectedToken {
+                token: "!".to_owned(),
+            },
+        ))
+    };
+    assert_eq!(tokenize("!x"), bang(0));
+    assert_eq!(tokenize("foo !"), bang(4));
+    assert_eq!(tokenize("a!=b").map(|tokens| tokens.len()), Ok(3));
+}
+
+#[test]
+fn unknown_field_hint_test() {
+    let error = QueryError::new(
+        0,
+        QueryErrorKind::UnknownField {
+            field: "Zelda".to_owned(),
+        },
+    );
+    assert!(error.to_string().contains("in quotes"));
+}
@@ -0,0 +1,288 @@
+use crate::ncsd::NcsdHeader;
+use byte_struct::ByteStruct;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+const CIA_HEADER_LEN: usize = 0x2020;
+
+// Where an NCCH starts in a file. The label tells apart the NCCHs of the same file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NcchLocation {
+    pub offset: u64,
+    pub label: String,
+    // CIA contents encrypted with the title key can't be read without the common key
+    pub encrypted: bool,
+}
+
+impl NcchLocation {
+    fn new(offset: u64, label: String) -> NcchLocation {
+        NcchLocation {
+            offset,
+            label,
+            encrypted: false,
+        }
+    }
+}
+
+pub fn read_at<F: Read + Seek>(file: &mut F, offset: u64, len: usize) -> std::io::Result<Vec<u8>> {
+    let mut data = vec![0; len];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+fn u16_le(data: &[u8], at: usize) -> u16 {
+    u16::from(data[at]) | u16::from(data[at + 1]) << 8
+}
+
+fn u32_le(data: &[u8], at: usize) -> u32 {
+    u32::from(u16_le(data, at)) | u32::from(u16_le(data, at + 2)) << 16
+}
+
+fn u16_be(data: &[u8], at: usize) -> u16 {
+    u16::from(data[at]) << 8 | u16::from(data[at + 1])
+}
+
+fn u32_be(data: &[u8], at: usize) -> u32 {
+    u32::from(u16_be(data, at)) << 16 | u32::from(u16_be(data, at + 2))
+}
+
+fn u64_be(data: &[u8], at: usize) -> u64 {
+    u64::from(u32_be(data, at)) << 32 | u64::from(u32_be(data, at + 4))
+}
+
+fn align64(offset: u64) -> u64 {
+    (offset + 0x3F) & !0x3F
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+// Every partition in the table with a non-zero size
+fn ncsd_partitions(header: &NcsdHeader) -> Vec<NcchLocation> {
+    header
+        .partition_ranges()
+        .into_iter()
+        .map(|(i, offset, _)| NcchLocation::new(offset, format!("partition {}", i)))
+        .collect()
+}
+
+// Length of the signature type, the signature and its padding at the start of a TMD
+fn signature_len(signature_type: u32) -> Option<u64> {
+    match signature_type {
+        0x10000 | 0x10003 => Some(4 + 0x200 + 0x3C),
+        0x10001 | 0x10004 => Some(4 + 0x100 + 0x3C),
+        0x10002 | 0x10005 => Some(4 + 0x3C + 0x40),
+        _ => None,
+    }
+}
+
+// Contents are stored one after another in TMD order, skipping those missing from the content
+// index of the CIA header
+fn cia_contents<F: Read + Seek>(file: &mut F, header: &[u8]) -> std::io::Result<Vec<NcchLocation>> {
+    let cert_offset = align64(u64::from(u32_le(header, 0)));
+    let ticket_offset = align64(cert_offset + u64::from(u32_le(header, 0x08)));
+    let tmd_offset = align64(ticket_offset + u64::from(u32_le(header, 0x0C)));
+    let tmd_len = u32_le(header, 0x10) as usize;
+    let mut offset = align64(tmd_offset + tmd_len as u64);
+
+    // The length comes from the file, so check it before allocating for it
+    if tmd_offset + tmd_len as u64 > file.seek(SeekFrom::End(0))? {
+        return Err(invalid("truncated TMD"));
+    }
+    let tmd = read_at(file, tmd_offset, tmd_len)?;
+    if tmd.len() < 4 {
+        return Err(invalid("truncated TMD"));
+    }
+    let tmd_header =
+        signature_len(u32_be(&tmd, 0)).ok_or_else(|| invalid("unknown TMD signature"))? as usize;
+    let chunks = tmd_header + 0xC4 + 0x900;
+    if tmd.len() < tmd_header + 0xC4 {
+        return Err(invalid("truncated TMD"));
+    }
+    let count = u16_be(&tmd, tmd_header + 0x9E) as usize;
+    if tmd.len() < chunks + count * 0x30 {
+        return Err(invalid("truncated TMD"));
+    }
+
+    let mut locations = vec![];
+    for chunk in (0..count).map(|i| &tmd[chunks + i * 0x30..chunks + (i + 1) * 0x30]) {
+        let id = u32_be(chunk, 0);
+        let index = u16_be(chunk, 4) as usize;
+        let content_type = u16_be(chunk, 6);
+        let size = u64_be(chunk, 8);
+        if header[0x20 + index / 8] & (0x80 >> (index % 8)) == 0 {
+            continue;
+        }
+        locations.push(NcchLocation {
+            offset,
+            label: format!("content {:08x}", id),
+            encrypted: content_type & 1 != 0,
+        });
+        offset += size;
+    }
+    Ok(locations)
+}
+
+// Finds the NCCHs in a file by the magic of its container. Returns None if the file is not an
+// NCCH, NCSD or CIA.
+pub fn locate<F: Read + Seek>(file: &mut F) -> std::io::Result<Option<Vec<NcchLocation>>> {
+    let header = match read_at(file, 0, 0x200) {
+        Ok(header) => header,
+        Err(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    match &header[0x100..0x104] {
+        b"NCCH" => return Ok(Some(vec![NcchLocation::new(0, String::new())])),
+        b"NCSD" => return Ok(Some(ncsd_partitions(&NcsdHeader::read_bytes(&header)))),
+        _ => (),
+    }
+    // CIA has no magic, but the header length and the type and version fields are fixed
+    if u32_le(&header, 0) as usize == CIA_HEADER_LEN && u32_le(&header, 4) == 0 {
+        let header = read_at(file, 0, CIA_HEADER_LEN)?;
+        return cia_contents(file, &header).map(Some);
+    }
+    Ok(None)
+}
+
+// A file to upload or index, with the NCCHs found in it
+#[derive(Debug)]
+pub struct ContainerFile {
+    pub path: PathBuf,
+    pub locations: std::io::Result<Vec<NcchLocation>>,
+}
+
+fn walk(path: &Path, explicit: bool, files: &mut Vec<ContainerFile>) {
+    if path.is_dir() {
+        // A symlinked directory can link back to one of its parents
+        let symlink = path
+            .symlink_metadata()
+            .map(|metadata| metadata.file_type().is_symlink())
+            .unwrap_or(false);
+        if symlink && !explicit {
+            return;
+        }
+        let entries = std::fs::read_dir(path).and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<std::io::Result<Vec<_>>>()
+        });
+        match entries {
+            Ok(mut entries) => {
+                entries.sort();
+                for entry in entries {
+                    walk(&entry, false, files);
+                }
+            }
+            Err(e) => files.push(ContainerFile {
+                path: path.to_owned(),
+                locations: Err(e),
+            }),
+        }
+        return;
+    }
+    let locations = match File::open(path).and_then(|mut file| locate(&mut file)) {
+        Ok(Some(locations)) => Ok(locations),
+        Ok(None) if !explicit => return,
+        Ok(None) => Err(invalid("not an NCCH, NCSD or CIA")),
+        Err(e) => Err(e),
+    };
+    files.push(ContainerFile {
+        path: path.to_owned(),
+        locations,
+    });
+}
+
+// Walks a file or directory in name order. Files found in a directory are skipped if they are not
+// an NCCH, NCSD or CIA, while a file given directly fails with InvalidData. Symlinked directories
+// are only followed if given directly.
+pub fn walk_containers(path: &Path) -> Vec<ContainerFile> {
+    let mut files = vec![];
+    walk(path, true, &mut files);
+    files
+}
+
+#[test]
+fn locate_test() {
+    use std::io::Cursor;
+
+    let mut ncsd = vec![0; 0x4200];
+    ncsd[0x100..0x104].copy_from_slice(b"NCSD");
+    ncsd[0x120] = 0x20;
+    ncsd[0x124] = 0x01;
+    ncsd[0x130] = 0x21;
+    ncsd[0x134] = 0x01;
+    let locations = locate(&mut Cursor::new(ncsd)).unwrap().unwrap();
+    assert_eq!(locations.len(), 2);
+    assert_eq!(locations[0].offset, 0x4000);
+    assert_eq!(locations[1].offset, 0x4200);
+    assert_eq!(locations[1].label, "partition 2");
+
+    // An RSA-2048 signed TMD with two contents, laid out as on 3dbrew
+    let tmd_header = 4 + 0x100 + 0x3C;
+    let chunks = tmd_header + 0xC4 + 0x40 * 0x24;
+    let mut tmd = vec![0; chunks + 2 * 0x30];
+    tmd[0..4].copy_from_slice(&0x10004u32.to_be_bytes());
+    tmd[tmd_header..tmd_header + 26].copy_from_slice(b"Root-CA00000003-CP0000000b");
+    tmd[tmd_header + 0x4C..tmd_header + 0x54]
+        .copy_from_slice(&0x0004_0000_0012_3400u64.to_be_bytes());
+    tmd[tmd_header + 0x9E..tmd_header + 0xA0].copy_from_slice(&2u16.to_be_bytes());
+    for i in 0..2 {
+        let chunk = &mut tmd[chunks + i * 0x30..chunks + (i + 1) * 0x30];
+        chunk[0..4].copy_from_slice(&(i as u32).to_be_bytes()); // ID
+        chunk[4..6].copy_from_slice(&(i as u16).to_be_bytes()); // index
+        chunk[6..8].copy_from_slice(&(1 + i as u16).to_be_bytes()); // type
+        chunk[8..16].copy_from_slice(&0x1000u64.to_be_bytes()); // size
+    }
+    assert_eq!(tmd.len(), 0xB64);
+
+    let (cert_len, ticket_len) = (0xA00, 0x350);
+    let mut cia = vec![0; 0x5000];
+    cia[0..4].copy_from_slice(&(CIA_HEADER_LEN as u32).to_le_bytes());
+    cia[0x08..0x0C].copy_from_slice(&(cert_len as u32).to_le_bytes());
+    cia[0x0C..0x10].copy_from_slice(&(ticket_len as u32).to_le_bytes());
+    cia[0x10..0x14].copy_from_slice(&(tmd.len() as u32).to_le_bytes());
+    cia[0x20] = 0x40; // only content index 1 is present
+    let tmd_offset = 0x2040 + cert_len + ticket_len + 0x30;
+    assert_eq!(tmd_offset, 0x2DC0);
+    cia[tmd_offset..tmd_offset + tmd.len()].copy_from_slice(&tmd);
+    let locations = locate(&mut Cursor::new(cia.clone())).unwrap().unwrap();
+    assert_eq!(locations.len(), 1);
+    assert_eq!(locations[0].offset, 0x3940);
+    assert_eq!(locations[0].label, "content 00000001");
+    assert!(!locations[0].encrypted);
+
+    // A TMD running past the end of the file
+    cia[0x10..0x14].copy_from_slice(&u32::max_value().to_le_bytes());
+    let error = locate(&mut Cursor::new(cia)).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+    assert_eq!(locate(&mut Cursor::new(vec![0; 0x10])).unwrap(), None);
+    assert_eq!(locate(&mut Cursor::new(vec![0; 0x400])).unwrap(), None);
+}
+
+#[test]
+fn walk_containers_test() {
+    let root = std::env::temp_dir().join(format!("index3ds-walk-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("b")).unwrap();
+    let mut ncch = vec![0; 0x200];
+    ncch[0x100..0x104].copy_from_slice(b"NCCH");
+    std::fs::write(root.join("b").join("game.cxi"), &ncch).unwrap();
+    std::fs::write(root.join("a.txt"), b"not a dump").unwrap();
+    // A loop back to the root is not followed
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(&root, root.join("b").join("loop")).unwrap();
+
+    let files = walk_containers(&root);
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].path, root.join("b").join("game.cxi"));
+    assert_eq!(files[0].locations.as_ref().unwrap()[0].offset, 0);
+
+    let files = walk_containers(&root.join("a.txt"));
+    assert_eq!(files.len(), 1);
+    let error = files[0].locations.as_ref().unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,35 @@
+use crate::ascii_string;
+use byte_struct::*;
+
+#[derive(ByteStruct, Debug)]
+#[byte_struct_le]
+pub struct ExefsFile {
+    pub name: [u8; 8],
+    pub offset: u32,
+    pub size: u32,
+}
+
+impl ExefsFile {
+    pub fn name_string(&self) -> String {
+        ascii_string(&self.name)
+    }
+}
+
+#[derive(ByteStruct, Debug)]
+#[byte_struct_le]
+pub struct ExefsHeader {
+    pub files: [ExefsFile; 10],
+    pub reserved: [u8; 32],
+    pub hashes: [[u8; 32]; 10],
+}
+
+impl ExefsHeader {
+    // The file with the name and its SHA-256. Hashes are stored in the reverse order of files.
+    pub fn file(&self, name: &str) -> Option<(&ExefsFile, &[u8; 32])> {
+        self.files
+            .iter()
+            .enumerate()
+            .find(|(_, file)| file.name_string() == name)
+            .map(|(index, file)| (file, &self.hashes[9 - index]))
+    }
+}
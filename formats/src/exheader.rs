@@ -0,0 +1,187 @@
+use crate::{ascii_string, trim_zero};
+use byte_struct::*;
+
+#[derive(ByteStruct, Debug)]
+#[byte_struct_le]
+pub struct ExheaderCodeSegment {
+    pub address: u32,
+    pub num_pages: u32,
+    pub code_size: u32,
+}
+
+bitfields!(
+    #[derive(Debug)]
+    pub ExheaderSystemControlFlag: u8 {
+        pub compress_code: 1,
+        pub sd_app: 1,
+        pub reserved: 6,
+    }
+);
+
+bitfields!(
+    #[derive(Debug)]
+    pub ExheaderCoreFlag: u32 {
+        pub enable_l2_cache: 1,
+        pub high_cpu_speed: 1,
+        pub reserved_a: 6,
+        pub n3ds_system_mode: 4,
+        pub reserved_b: 4,
+        pub ideal_processor: 2,
+        pub affinity_mask: 2,
+        pub system_mode: 4,
+        pub priority: 8,
+    }
+);
+
+// Memory layout on Old 3DS
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SystemMode {
+    Prod,
+    Dev1,
+    Dev2,
+    Dev3,
+    Dev4,
+    Unknown(u8),
+}
+
+// Memory layout on New 3DS. Legacy keeps the Old 3DS layout.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum N3dsSystemMode {
+    Legacy,
+    Prod,
+    Dev1,
+    Dev2,
+    Unknown(u8),
+}
+
+impl ExheaderCoreFlag {
+    pub fn memory_mode(&self) -> SystemMode {
+        match self.system_mode {
+            0 => SystemMode::Prod,
+            2 => SystemMode::Dev1,
+            3 => SystemMode::Dev2,
+            4 => SystemMode::Dev3,
+            5 => SystemMode::Dev4,
+            mode => SystemMode::Unknown(mode as u8),
+        }
+    }
+
+    pub fn n3ds_memory_mode(&self) -> N3dsSystemMode {
+        match self.n3ds_system_mode {
+            0 => N3dsSystemMode::Legacy,
+            1 => N3dsSystemMode::Prod,
+            2 => N3dsSystemMode::Dev1,
+            3 => N3dsSystemMode::Dev2,
+            mode => N3dsSystemMode::Unknown(mode as u8),
+        }
+    }
+
+    // The cores the main thread is allowed to run on
+    pub fn affinity(&self) -> Vec<u8> {
+        (0..2)
+            .filter(|i| self.affinity_mask & (1 << i) != 0)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResourceLimitCategory {
+    Application,
+    SystemApplet,
+    LibraryApplet,
+    Other,
+    Unknown(u8),
+}
+
+#[derive(ByteStruct, Debug)]
+#[byte_struct_le]
+pub struct ExheaderAccessControl {
+    pub program_id: u64,
+    pub core_version: u32,
+    pub core_flag: ExheaderCoreFlag,
+    pub resource_limit_desc: [u16; 16],
+    pub extdata_id: u64,
+    pub system_savedata_id: [u32; 2],
+    pub storage_access_id: u64,
+    pub filesystem_flag: u64,
+    pub services: GenericArray<[u8; 8], typenum::U34>,
+    pub reserved_a: [u8; 15],
+    pub resource_limit_category: u8,
+    pub kernel_desc: [u32; 28],
+    pub reserved_b: [u8; 16],
+    pub arm9_flag: u32,
+    pub arm9_flag_ext: [u8; 11],
+    pub arm9_flag_version: u8,
+}
+
+impl ExheaderAccessControl {
+    pub fn resource_limit(&self) -> ResourceLimitCategory {
+        match self.resource_limit_category {
+            0 => ResourceLimitCategory::Application,
+            1 => ResourceLimitCategory::SystemApplet,
+            2 => ResourceLimitCategory::LibraryApplet,
+            3 => ResourceLimitCategory::Other,
+            category => ResourceLimitCategory::Unknown(category),
+        }
+    }
+
+    // Names of the services the program may access, without the unused slots at the end
+    pub fn service_names(&self) -> Vec<String> {
+        trim_zero(&self.services[..])
+            .iter()
+            .map(|service| ascii_string(service))
+            .collect()
+    }
+}
+
+#[derive(ByteStruct, Debug)]
+#[byte_struct_le]
+pub struct Exheader {
+    pub name: [u8; 8],
+    pub reserved_a: [u8; 5],
+    pub system_control_flag: ExheaderSystemControlFlag,
+    pub remaster_version: u16,
+    pub segment_text: ExheaderCodeSegment,
+    pub stack_size: u32,
+    pub segment_ro: ExheaderCodeSegment,
+    pub reserved_b: [u8; 4],
+    pub segment_data: ExheaderCodeSegment,
+    pub bss_size: u32,
+    pub dependencies: GenericArray<u64, typenum::U48>,
+    pub save_data_size: u64,
+    pub jump_id: u64,
+    pub reserved_c: GenericArray<u8, typenum::U48>,
+
+    pub access_control: ExheaderAccessControl,
+    pub signature: GenericArray<u8, typenum::U256>,
+    pub public_key: GenericArray<u8, typenum::U256>,
+    pub access_control_limit: ExheaderAccessControl,
+}
+
+impl Exheader {
+    pub fn name_string(&self) -> String {
+        ascii_string(&self.name)
+    }
+
+    // Program IDs of the modules this one depends on, without the unused slots
+    pub fn dependency_ids(&self) -> Vec<u64> {
+        self.dependencies
+            .iter()
+            .cloned()
+            .filter(|&id| id != 0)
+            .collect()
+    }
+}
+
+#[test]
+fn core_flag_test() {
+    // system_mode 3, affinity mask 0b10, ideal processor 1, n3ds_system_mode 2
+    let flag = ExheaderCoreFlag::read_bytes_default_le(&[0x03, 0x02, 0x39, 0x30]);
+    assert_eq!(flag.enable_l2_cache, 1);
+    assert_eq!(flag.high_cpu_speed, 1);
+    assert_eq!(flag.n3ds_memory_mode(), N3dsSystemMode::Dev1);
+    assert_eq!(flag.ideal_processor, 1);
+    assert_eq!(flag.affinity(), vec![1]);
+    assert_eq!(flag.memory_mode(), SystemMode::Dev2);
+    assert_eq!(flag.priority, 0x30);
+}
@@ -0,0 +1,49 @@
+fn lrot128(a: &[u8], rot: usize) -> [u8; 16] {
+    let mut out = [0; 16];
+    let byte_shift = rot / 8;
+    let bit_shift = rot % 8;
+    for (i, o) in out.iter_mut().enumerate() {
+        let wrap_index_a = (i + byte_shift) % 16;
+        let wrap_index_b = (i + byte_shift + 1) % 16;
+        // note: the right shift would be UB for bit_shift = 0.
+        // good thing is that the values we will use for rot won't cause this
+        *o = (a[wrap_index_a] << bit_shift) | (a[wrap_index_b] >> (8 - bit_shift));
+    }
+    out
+}
+
+fn add128(a: &[u8], b: &[u8]) -> [u8; 16] {
+    let mut out = [0; 16];
+    let mut carry = 0;
+
+    for i in (0..16).rev() {
+        let sum = u32::from(a[i]) + u32::from(b[i]) + carry;
+        carry = sum >> 8;
+        out[i] = (sum & 0xFF) as u8;
+    }
+    out
+}
+
+fn xor128(a: &[u8], b: &[u8]) -> [u8; 16] {
+    let mut out = [0; 16];
+    for i in 0..16 {
+        out[i] = a[i] ^ b[i];
+    }
+    out
+}
+
+// The hardware key scrambler, which derives a normal key from KeyX and KeyY. The constant is not
+// included here and has to be supplied by the caller.
+pub fn scramble_key(key_x: &[u8], key_y: &[u8], scrambler: &[u8]) -> [u8; 16] {
+    lrot128(&add128(&xor128(&lrot128(key_x, 2), key_y), scrambler), 87)
+}
+
+#[test]
+fn scramble_key_test() {
+    let mut one = [0; 16];
+    one[15] = 1;
+    assert_eq!(lrot128(&one, 10)[14], 4);
+    assert_eq!(lrot128(&one, 2)[15], 4);
+    assert_eq!(add128(&[0xFF; 16], &one), [0; 16]);
+    assert_eq!(scramble_key(&[0; 16], &[0; 16], &[0; 16]), [0; 16]);
+}
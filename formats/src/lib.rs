@@ -0,0 +1,100 @@
+mod container;
+mod exefs;
+mod exheader;
+mod key;
+mod ncch;
+mod ncsd;
+mod smdh;
+
+pub use byte_struct::ByteStruct;
+pub use container::*;
+pub use exefs::*;
+pub use exheader::*;
+pub use key::*;
+pub use ncch::*;
+pub use ncsd::*;
+pub use smdh::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatError {
+    UnexpectedLength { expected: usize, actual: usize },
+}
+
+impl std::fmt::Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FormatError::UnexpectedLength { expected, actual } => {
+                write!(f, "expected {} bytes, got {}", expected, actual)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+// Parses and serializes every structure of this crate, checking the length on the way in
+pub trait ByteFormat: ByteStruct + Sized {
+    fn from_bytes(data: &[u8]) -> Result<Self, FormatError> {
+        if data.len() != Self::BYTE_LEN {
+            return Err(FormatError::UnexpectedLength {
+                expected: Self::BYTE_LEN,
+                actual: data.len(),
+            });
+        }
+        Ok(Self::read_bytes(data))
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![0; Self::BYTE_LEN];
+        self.write_bytes(&mut data);
+        data
+    }
+}
+
+impl<T: ByteStruct> ByteFormat for T {}
+
+// Strings in the formats are padded with zeros to a fixed length
+fn trim_zero<T: PartialEq + Default>(s: &[T]) -> &[T] {
+    let end = s
+        .iter()
+        .rposition(|c| *c != T::default())
+        .map_or(0, |i| i + 1);
+    &s[..end]
+}
+
+fn ascii_string(s: &[u8]) -> String {
+    trim_zero(s).iter().map(|&c| c as char).collect()
+}
+
+#[test]
+fn size_test() {
+    assert_eq!(NcchHeader::BYTE_LEN, 0x200);
+    assert_eq!(NcsdHeader::BYTE_LEN, 0x200);
+    assert_eq!(ExefsHeader::BYTE_LEN, 0x200);
+    assert_eq!(Smdh::BYTE_LEN, 0x36C0);
+    assert_eq!(Exheader::BYTE_LEN, 0x800);
+}
+
+#[test]
+fn round_trip_test() {
+    let mut data = vec![0; NcchHeader::BYTE_LEN];
+    data[0x100..0x104].copy_from_slice(b"NCCH");
+    data[0x150..0x154].copy_from_slice(b"CTR-");
+    data[0x18D] = 0x02 | 3 << 2;
+    let header = NcchHeader::from_bytes(&data).unwrap();
+    assert_eq!(header.product_code_string(), "CTR-");
+    assert_eq!(header.content_type.is_executable, 1);
+    assert_eq!(
+        header.content_type.content_category(),
+        ContentCategory::DlpChild
+    );
+    assert_eq!(header.to_bytes(), data);
+
+    assert_eq!(
+        NcchHeader::from_bytes(&data[1..]).unwrap_err(),
+        FormatError::UnexpectedLength {
+            expected: 0x200,
+            actual: 0x1FF
+        }
+    );
+}
@@ -0,0 +1,130 @@
+use crate::ascii_string;
+use byte_struct::*;
+
+bitfields!(
+    #[derive(Debug)]
+    pub NcchContentType: u8 {
+       pub is_data: 1,
+       pub is_executable: 1,
+       pub category: 6
+    }
+);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentCategory {
+    Application,
+    SystemUpdate,
+    Manual,
+    DlpChild,
+    Trial,
+    Unknown(u8),
+}
+
+impl NcchContentType {
+    pub fn content_category(&self) -> ContentCategory {
+        match self.category {
+            0 => ContentCategory::Application,
+            1 => ContentCategory::SystemUpdate,
+            2 => ContentCategory::Manual,
+            3 => ContentCategory::DlpChild,
+            4 => ContentCategory::Trial,
+            category => ContentCategory::Unknown(category),
+        }
+    }
+}
+
+bitfields!(
+    #[derive(Debug)]
+    pub NcchKeyConfig: u8 {
+        pub fixed_key: 1,
+        pub no_romfs: 1,
+        pub no_crypto: 1,
+        pub reserved_a: 2,
+        pub seed_crypto: 1,
+        pub reserved_b: 2
+    }
+);
+
+#[derive(ByteStruct, Debug)]
+#[byte_struct_le]
+pub struct NcchHeader {
+    pub signature: GenericArray<u8, typenum::U256>,
+    pub magic: [u8; 4],
+    pub content_size: u32,
+    pub partition_id: u64,
+    pub maker_code: u16,
+    pub version: u16,
+    pub seed_verifier: [u8; 4],
+    pub program_id: u64,
+    pub reserved_a: [u8; 16],
+    pub logo_hash: [u8; 32],
+    pub product_code: [u8; 16],
+    pub exheader_hash: [u8; 32],
+    pub exheader_size: u32,
+    pub reseved_b: [u8; 4],
+    pub flag0: u8,
+    pub flag1: u8,
+    pub flag2: u8,
+    pub secondary_key_slot: u8,
+    pub platform: u8,
+    pub content_type: NcchContentType,
+    pub content_unit_size: u8,
+    pub key_config: NcchKeyConfig,
+    pub sdk_info_offset: u32,
+    pub sdk_info_size: u32,
+    pub logo_offset: u32,
+    pub logo_size: u32,
+    pub exefs_offset: u32,
+    pub exefs_size: u32,
+    pub exefs_hash_region_size: u32,
+    pub reserved_c: [u8; 4],
+    pub romfs_offset: u32,
+    pub romfs_size: u32,
+    pub romfs_hash_region_size: u32,
+    pub reserved_d: [u8; 4],
+    pub exefs_hash: [u8; 32],
+    pub romfs_hash: [u8; 32],
+}
+
+impl NcchHeader {
+    pub fn unit_size(&self) -> usize {
+        0x200 * (1 << self.content_unit_size as usize)
+    }
+
+    pub fn product_code_string(&self) -> String {
+        ascii_string(&self.product_code)
+    }
+
+    // Two ASCII characters, stored low byte first
+    pub fn maker_code_string(&self) -> String {
+        ascii_string(&self.maker_code.to_le_bytes())
+    }
+
+    // The first 16 bytes of the signature are the KeyY of the normal key
+    pub fn key_y(&self) -> [u8; 16] {
+        let mut key_y = [0; 16];
+        key_y.copy_from_slice(&self.signature[0..0x10]);
+        key_y
+    }
+
+    // AES-CTR counters of the Exheader and the ExeFS. None for an unknown NCCH version.
+    pub fn counters(&self) -> Option<([u8; 16], [u8; 16])> {
+        let mut ctr_exheader = [0; 16];
+        let mut ctr_exefs;
+        if self.version == 0 || self.version == 2 {
+            ctr_exheader[0..8].copy_from_slice(&self.partition_id.to_be_bytes());
+            ctr_exefs = ctr_exheader;
+            ctr_exheader[8] = 1;
+            ctr_exefs[8] = 2;
+        } else if self.version == 1 {
+            ctr_exheader[0..8].copy_from_slice(&self.partition_id.to_le_bytes());
+            ctr_exefs = ctr_exheader;
+            ctr_exheader[12..16].copy_from_slice(&0x200u32.to_be_bytes());
+            ctr_exefs[12..16]
+                .copy_from_slice(&(self.exefs_size * (self.unit_size() as u32)).to_be_bytes())
+        } else {
+            return None;
+        }
+        Some((ctr_exheader, ctr_exefs))
+    }
+}
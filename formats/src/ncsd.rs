@@ -0,0 +1,51 @@
+use byte_struct::*;
+
+// Offsets and sizes in NCSD and NCCH are in media units
+pub const MEDIA_UNIT: u64 = 0x200;
+
+#[derive(ByteStruct, Debug)]
+#[byte_struct_le]
+pub struct NcsdPartition {
+    pub offset: u32,
+    pub size: u32,
+}
+
+#[derive(ByteStruct, Debug)]
+#[byte_struct_le]
+pub struct NcsdHeader {
+    pub signature: GenericArray<u8, typenum::U256>,
+    pub magic: [u8; 4],
+    pub size: u32,
+    pub media_id: u64,
+    pub partition_fs_type: [u8; 8],
+    pub partition_crypt_type: [u8; 8],
+    pub partitions: [NcsdPartition; 8],
+    pub exheader_hash: [u8; 32],
+    pub additional_header_size: u32,
+    pub sector_zero_offset: u32,
+    pub partition_flags: [u8; 8],
+    pub partition_ids: [u64; 8],
+    pub reserved: GenericArray<u8, typenum::U48>,
+}
+
+impl NcsdHeader {
+    pub fn has_magic(&self) -> bool {
+        self.magic == *b"NCSD"
+    }
+
+    // Index, offset and size in bytes of every partition that is present
+    pub fn partition_ranges(&self) -> Vec<(usize, u64, u64)> {
+        self.partitions
+            .iter()
+            .enumerate()
+            .filter(|(_, partition)| partition.size != 0)
+            .map(|(i, partition)| {
+                (
+                    i,
+                    u64::from(partition.offset) * MEDIA_UNIT,
+                    u64::from(partition.size) * MEDIA_UNIT,
+                )
+            })
+            .collect()
+    }
+}
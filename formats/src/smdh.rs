@@ -0,0 +1,56 @@
+use byte_struct::*;
+
+#[derive(ByteStruct, Debug)]
+#[byte_struct_le]
+pub struct SmdhTitle {
+    pub short: GenericArray<u16, typenum::U64>,
+    pub long: GenericArray<u16, typenum::U128>,
+    pub publisher: GenericArray<u16, typenum::U64>,
+}
+
+// Titles end at the first NUL
+fn utf16_string(s: &[u16]) -> String {
+    let end = s.iter().position(|&c| c == 0).unwrap_or(s.len());
+    String::from_utf16_lossy(&s[..end])
+}
+
+impl SmdhTitle {
+    pub fn short_title(&self) -> String {
+        utf16_string(&self.short)
+    }
+
+    pub fn long_title(&self) -> String {
+        utf16_string(&self.long)
+    }
+
+    pub fn publisher_name(&self) -> String {
+        utf16_string(&self.publisher)
+    }
+}
+
+#[derive(ByteStruct, Debug)]
+#[byte_struct_le]
+pub struct Smdh {
+    pub magic: [u8; 4],
+    pub version: u16,
+    pub reserved_a: u16,
+    pub title: [SmdhTitle; 16],
+    pub ratings: [u8; 16],
+    pub region_lockout: u32,
+    pub match_maker_id: u32,
+    pub match_maker_bit_id: u64,
+    pub flags: u32,
+    pub eula_version: u16,
+    pub reserved_b: u16,
+    pub banner_animation_frame: f32,
+    pub cec_id: u32,
+    pub reserved_c: [u8; 8],
+    pub small_icon: GenericArray<u16, typenum::U576>,
+    pub large_icon: GenericArray<u16, typenum::Prod<typenum::U64, typenum::U36>>,
+}
+
+impl Smdh {
+    pub fn has_magic(&self) -> bool {
+        self.magic == *b"SMDH"
+    }
+}
@@ -0,0 +1,160 @@
+use index3ds_common::diff::*;
+use index3ds_common::*;
+use serde::*;
+use yew::format::{json::Json, Nothing};
+use yew::prelude::*;
+use yew::services::fetch::*;
+
+pub enum Msg {
+    DiffReceived(NcchDiff),
+    DiffError(String),
+}
+
+#[derive(Serialize, Deserialize, Properties, PartialEq, Clone)]
+pub struct PageCompareProp {
+    #[props(required)]
+    pub a: String,
+    #[props(required)]
+    pub b: String,
+}
+
+enum DiffStatus {
+    Receiving,
+    Error(String),
+    Ready(NcchDiff),
+}
+
+pub struct PageCompare {
+    link: ComponentLink<PageCompare>,
+    props: PageCompareProp,
+    diff: DiffStatus,
+    fetch_service: FetchService,
+    fetch_task: Option<FetchTask>,
+}
+
+impl PageCompare {
+    fn refresh(&mut self) {
+        self.diff = DiffStatus::Receiving;
+        let query = serde_urlencoded::ser::to_string(&self.props).unwrap();
+        let request = Request::get(&format!("{}?{}", url::diff(), query))
+            .body(Nothing)
+            .unwrap();
+        self.fetch_task = Some(self.fetch_service.fetch(
+            request,
+            self.link.send_back(|response: Response<_>| {
+                let Json(body) = response.into_body();
+                match body {
+                    Ok(NcchDiffResponse::Ok(diff)) => Msg::DiffReceived(diff),
+                    Ok(NcchDiffResponse::NotFound) => Msg::DiffError("Not found".to_owned()),
+                    _ => Msg::DiffError("Error".to_owned()),
+                }
+            }),
+        ));
+    }
+}
+
+impl Component for PageCompare {
+    type Message = Msg;
+    type Properties = PageCompareProp;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let mut component = PageCompare {
+            link,
+            props,
+            diff: DiffStatus::Receiving,
+            fetch_service: FetchService::new(),
+            fetch_task: None,
+        };
+        component.refresh();
+        component
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            Msg::DiffReceived(diff) => self.diff = DiffStatus::Ready(diff),
+            Msg::DiffError(e) => self.diff = DiffStatus::Error(e),
+        }
+        true
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        if self.props != props {
+            self.props = props;
+            self.refresh();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl PageCompare {
+    fn header(info: &NcchInfo) -> Html<Self> {
+        let title = info
+            .short_title
+            .as_ref()
+            .and_then(|titles| titles.iter().find(|t| !t.is_empty()).cloned())
+            .unwrap_or_default();
+        html! {
+            <th>
+                <a href=format!("{}?{}", url::ncch(), info.id)>{&info.id}</a>
+                <p class="has-text-weight-normal">{title}</p>
+            </th>
+        }
+    }
+
+    // Lists and flags tag the elements or bits that only one side has, under the whole value.
+    // Other fields have the whole value highlighted.
+    fn value(value: &Option<String>, only: &[String], is_list: bool, class: &str) -> Html<Self> {
+        let value = value.as_ref().map(|v| v.as_str()).unwrap_or("N/A");
+        if is_list {
+            html! {
+                <td class="is-family-monospace">
+                    <p>{value}</p>
+                    <div class="tags">
+                        {for only.iter().map(|e| html! {
+                            <span class=format!("tag {}", class)>{e}</span>
+                        })}
+                    </div>
+                </td>
+            }
+        } else {
+            html! {
+                <td class="is-family-monospace">
+                    <span class=format!("tag {}", class)>{value}</span>
+                </td>
+            }
+        }
+    }
+}
+
+impl Renderable<PageCompare> for PageCompare {
+    fn view(&self) -> Html<Self> {
+        match &self.diff {
+            DiffStatus::Receiving => html! {"Receiving"},
+            DiffStatus::Error(e) => html! {<p class="has-text-danger">{e}</p>},
+            DiffStatus::Ready(diff) => html! {
+                <div>
+                    <p class="subtitle">{format!("{} fields differ", diff.fields.len())}</p>
+                    <table class="table is-fullwidth"><tbody>
+                        <tr>
+                            <th>{"Field"}</th>
+                            {PageCompare::header(&diff.a)}
+                            {PageCompare::header(&diff.b)}
+                        </tr>
+                        {for diff.fields.iter().map(|field| {
+                            let is_list = !field.removed.is_empty() || !field.added.is_empty();
+                            html! {
+                                <tr>
+                                    <td>{&field.field}</td>
+                                    {PageCompare::value(&field.a, &field.removed, is_list, "is-danger")}
+                                    {PageCompare::value(&field.b, &field.added, is_list, "is-success")}
+                                </tr>
+                            }
+                        })}
+                    </tbody></table>
+                </div>
+            },
+        }
+    }
+}
@@ -8,11 +8,17 @@ mod ncch;
 mod ncch_list;
 mod submit_ncch;
 mod about;
+mod stats;
+mod compare;
+mod publishers;
 
 use ncch::PageNcch;
 use ncch_list::PageNcchList;
 use submit_ncch::PageSubmitNcch;
 use about::PageAbout;
+use stats::PageStats;
+use compare::PageCompare;
+use publishers::PagePublishers;
 
 pub struct Model {
     burger_active: bool,
@@ -89,6 +95,24 @@ impl Model {
                 html! {<PageSubmitNcch/>}
             } else if pathname == url::about() {
                 html! {<PageAbout/>}
+            } else if pathname == url::statistics() {
+                let search = if search.is_empty() { "" } else { &search[1..] };
+                if let Ok(search) = serde_urlencoded::de::from_str::<stats::PageStatsProp>(search) {
+                    html! {<PageStats filter=search.filter/>}
+                } else {
+                    self.view_not_found()
+                }
+            } else if pathname == url::publishers() {
+                html! {<PagePublishers/>}
+            } else if pathname == url::compare() {
+                let search = if search.is_empty() { "" } else { &search[1..] };
+                if let Ok(search) =
+                    serde_urlencoded::de::from_str::<compare::PageCompareProp>(search)
+                {
+                    html! {<PageCompare a=search.a b=search.b/>}
+                } else {
+                    self.view_not_found()
+                }
             } else {
                 self.view_not_found()
             }
@@ -124,6 +148,12 @@ impl Renderable<Model> for Model {
                             <a class="navbar-item" href=url::submit_ncch()>
                                 {"Add"}
                             </a>
+                            <a class="navbar-item" href=url::publishers()>
+                                {"Publishers"}
+                            </a>
+                            <a class="navbar-item" href=url::statistics()>
+                                {"Statistics"}
+                            </a>
                             <a class="navbar-item" href=url::about()>
                                 {"About"}
                             </a>
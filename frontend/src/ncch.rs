@@ -1,13 +1,16 @@
+use index3ds_common::product_code::ProductCode;
 use index3ds_common::*;
 use yew::format::{json::Json, Nothing};
 use yew::prelude::*;
 use yew::services::fetch::*;
 
 use crate::language_map;
+use crate::publishers::publisher_url;
 
 pub enum Msg {
     NcchInfoReceived(NcchInfo),
     NcchInfoError,
+    SimilarIconsReceived(Vec<SimilarNcch>),
 }
 
 #[derive(PartialEq, Properties)]
@@ -27,6 +30,8 @@ pub struct PageNcch {
     ncch_info: NcchInfoStatus,
     fetch_service: FetchService,
     fetch: FetchTask,
+    similar_icons: Vec<SimilarNcch>,
+    similar_icons_fetch: FetchTask,
 }
 
 impl Component for PageNcch {
@@ -49,11 +54,30 @@ impl Component for PageNcch {
             }),
         );
 
+        // The strip is left out if this fails
+        let get_request = Request::get(&url::ncch_info(&props.ncch_id, "similar_icons"))
+            .body(Nothing)
+            .unwrap();
+        let similar_icons_fetch = fetch_service.fetch(
+            get_request,
+            link.send_back(|response: Response<_>| {
+                let Json(body) = response.into_body();
+                match body {
+                    Ok(NcchSimilarIconsResponse::Ok(similar)) => {
+                        Msg::SimilarIconsReceived(similar.ncchs)
+                    }
+                    _ => Msg::SimilarIconsReceived(vec![]),
+                }
+            }),
+        );
+
         PageNcch {
             props,
             ncch_info: NcchInfoStatus::Receiving,
             fetch_service,
             fetch,
+            similar_icons: vec![],
+            similar_icons_fetch,
         }
     }
 
@@ -61,6 +85,7 @@ impl Component for PageNcch {
         match msg {
             Msg::NcchInfoReceived(info) => self.ncch_info = NcchInfoStatus::Ready(info),
             Msg::NcchInfoError => self.ncch_info = NcchInfoStatus::Error,
+            Msg::SimilarIconsReceived(similar) => self.similar_icons = similar,
         }
         true
     }
@@ -81,7 +106,7 @@ const LANGUAGE_NAME: &[&str] = &[
     "Chinese(T)",
 ];
 
-const REGION_NAME: &[&str] = &["JPN", "USA", "EUR", "AUS", "CHN", "KOR", "TWN"];
+pub use index3ds_common::product_code::REGION_NAME;
 
 const RATING_NAME: &[&str] = &[
     "CERO",
@@ -97,7 +122,7 @@ const RATING_NAME: &[&str] = &[
     "CGSRR",
 ];
 
-const MEMORY_MODE: &[&str] = &[
+pub const MEMORY_MODE: &[&str] = &[
     "Mode 0",
     "???",
     "Mode 2",
@@ -156,14 +181,7 @@ impl PageNcch {
     }
 
     fn content_type_info(ncch: &NcchInfo) -> Html<Self> {
-        let category = match ncch.content_category {
-            0 => "Application",
-            1 => "System Update",
-            2 => "Manual",
-            3 => "DLP Child",
-            4 => "Trial",
-            _ => "???",
-        };
+        let category = format_category(ncch.content_category);
         html! {
             <div class="tags">
                 <span class="tag is-primary">{category}</span>
@@ -515,11 +533,97 @@ impl PageNcch {
             </tbody>
         }
     }
+
+    fn maker_info(ncch: &NcchInfo) -> Html<Self> {
+        match &ncch.maker_name {
+            Some(name) => html! {<a href=publisher_url(name)>{name}</a>},
+            None => html! {"Unknown"},
+        }
+    }
+
+    // The decoded parts, and a link to every release of the same game
+    fn product_code_info(ncch: &NcchInfo) -> Html<Self> {
+        let code = match ProductCode::parse(&ncch.product_code) {
+            Some(code) => code,
+            None => return html! {<span>{&ncch.product_code}</span>},
+        };
+        let filter = NcchFilterParam {
+            product_code: Some(format!("*-{}?", &code.game_code[..3])),
+            ..NcchFilterParam::default()
+        };
+        let releases = format!(
+            "{}?{}",
+            url::ncch_list(),
+            serde_urlencoded::ser::to_string(&filter).unwrap()
+        );
+        html! {
+            <div>
+                <a href=releases>{&ncch.product_code}</a>
+                <div class="tags">
+                    {for code.platform_name().map(|name| html! {<span class="tag">{name}</span>})}
+                    {for code.media_name().map(|name| html! {<span class="tag">{name}</span>})}
+                    {for code.region().map(|region| html! {<span class="tag is-info">{region}</span>})}
+                </div>
+            </div>
+        }
+    }
+
+    fn releases(ncch: &NcchInfo) -> Html<Self> {
+        if ncch.releases.is_empty() {
+            return html! {};
+        }
+        let text = |value: &Option<String>| value.clone().unwrap_or_default();
+        html! {
+            <div class="tile is-child">
+                <p class="title">{"Releases"}</p>
+                <table class="table">
+                    <thead>
+                        <th>{"Serial"}</th>
+                        <th>{"Name"}</th>
+                        <th>{"Region"}</th>
+                        <th>{"Publisher"}</th>
+                        <th>{"Release Date"}</th>
+                    </thead>
+                    <tbody>
+                    {for ncch.releases.iter().map(|release| html! {
+                        <tr>
+                            <td class="is-family-monospace">{&release.serial}</td>
+                            <td>{&release.name}</td>
+                            <td>{text(&release.region)}</td>
+                            <td>{text(&release.publisher)}</td>
+                            <td>{text(&release.release_date)}</td>
+                        </tr>
+                    })}
+                    </tbody>
+                </table>
+            </div>
+        }
+    }
+
+    fn similar_icons(&self) -> Html<Self> {
+        if self.similar_icons.is_empty() {
+            return html! {};
+        }
+        html! {
+            <div class="tile is-child">
+                <p class="title">{"Similar icons"}</p>
+                {for self.similar_icons.iter().map(|similar| {
+                    let id = &similar.ncch.id;
+                    html! {
+                        <a href=format!("{}?{}", url::ncch(), id)
+                            title=format!("{} (distance {})", id, similar.distance)>
+                            <img src=url::ncch_info(id, "icon_large.png")/>
+                        </a>
+                    }
+                })}
+            </div>
+        }
+    }
 }
 
 const CONTENT_SIZE_UNIT: &[&str] = &["GiB", "MiB", "KiB"];
 
-fn format_content_size(size: u64) -> String {
+pub fn format_content_size(size: u64) -> String {
     let mut thres = 1024u64.pow(CONTENT_SIZE_UNIT.len() as u32);
     for unit in CONTENT_SIZE_UNIT {
         if size >= thres {
@@ -535,7 +639,18 @@ fn format_content_size(size: u64) -> String {
     format!("{} Bytes", size)
 }
 
-fn format_platform(value: u8) -> String {
+pub fn format_category(value: u8) -> &'static str {
+    match value {
+        0 => "Application",
+        1 => "System Update",
+        2 => "Manual",
+        3 => "DLP Child",
+        4 => "Trial",
+        _ => "???",
+    }
+}
+
+pub fn format_platform(value: u8) -> String {
     match value {
         1 => "3DS".to_owned(),
         2 => "New 3DS".to_owned(),
@@ -620,14 +735,17 @@ impl Renderable<PageNcch> for PageNcch {
                                     </tr>
                                 </tbody></table>
                             </div>
+                            {self.similar_icons()}
 
                             <div class="tile is-child">
                                 <p class="title">{"Partition Information"}</p>
                                 <table class="table"><tbody>
                                     {PageNcch::field("Partition ID", &ncch_info.partition_id)}
                                     {PageNcch::field("Program ID", &ncch_info.program_id)}
-                                    {PageNcch::field("Product Code", &ncch_info.product_code)}
+                                    {PageNcch::field("Product Code", PageNcch::product_code_info(&ncch_info))}
+                                    {PageNcch::field("Region", ncch_info.region.as_ref().map(|x|&**x).unwrap_or("Unknown"))}
                                     {PageNcch::field("Maker Code", &ncch_info.maker_code)}
+                                    {PageNcch::field("Maker", PageNcch::maker_info(&ncch_info))}
                                     {PageNcch::field("Content Type", PageNcch::content_type_info(&ncch_info))}
                                     {PageNcch::field("Content Size", &format_content_size(ncch_info.content_size as u64 * unit_size))}
                                     {PageNcch::field("NCCH Version", &format!("{}", ncch_info.ncch_version))}
@@ -647,6 +765,7 @@ impl Renderable<PageNcch> for PageNcch {
                                     {PageNcch::titles(ncch_info)}
                                 </table>
                             </div>
+                            {PageNcch::releases(ncch_info)}
                             <div class="tile is-child">
                                 <p class="title">{"Home Menu Interaction"}</p>
                                 <table class="table"><tbody>
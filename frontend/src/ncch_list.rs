@@ -8,6 +8,7 @@ use yew::services::fetch::*;
 use yew::{html, Component, ComponentLink, Html, Renderable, ShouldRender};
 
 use crate::language_map::*;
+use crate::ncch::{format_platform, REGION_NAME};
 
 enum TableStatus {
     Loading,
@@ -19,19 +20,25 @@ pub struct PageNcchList {
     link: ComponentLink<PageNcchList>,
     fetch_service: FetchService,
     ncch_fetch_task: Option<FetchTask>,
+    icon_fetch_task: Option<FetchTask>,
     count_fetch_task: Option<FetchTask>,
     table_status: TableStatus,
+    icon_sheet: Option<NcchIconSheet>,
+    facets: Option<NcchFacets>,
     filter_param: NcchFilterParam,
     current_page: u32,
     total_page: Option<u32>,
     ncchs_in_page: u32,
     search_string: String,
+    query_error: Option<String>,
 }
 
 #[derive(Clone, Copy)]
 pub enum FilterField {
+    Query,
     ProductCode,
     MakerCode,
+    MakerName,
     IsData,
     IsExecutable,
     ContentSize,
@@ -42,20 +49,26 @@ pub enum FilterField {
     SdApp,
     EnableL2Cache,
     HighCpuSpeed,
+    Platform,
+    Region,
 }
 
 #[derive(Clone)]
 pub enum FilterChange {
     Delete,
     Bool(bool),
+    Number(u32),
+    Text(String),
 }
 
 #[derive(Clone)]
 pub enum Msg {
     PageChanged(u32),
     CountReceived(u32),
-    NcchReceived(Vec<NcchInfo>),
+    NcchReceived(NcchInfoVec),
+    IconSheetReceived(NcchIconSheet),
     NcchError,
+    QueryError(String),
     UpdateSearchBox(String),
     Search,
 
@@ -65,11 +78,18 @@ pub enum Msg {
 }
 
 impl PageNcchList {
-    fn refresh_table(&mut self) {
+    // Facets only depend on the filter, so they are kept when turning pages
+    fn refresh_table(&mut self, with_facets: bool) {
         self.table_status = TableStatus::Loading;
+        self.icon_sheet = None;
+        if with_facets {
+            self.facets = None;
+        }
         let param = NcchQueryParam {
             offset: (self.current_page * self.ncchs_in_page) as i64,
             limit: self.ncchs_in_page as i64,
+            facets: Some(StringWrapper::new(with_facets)),
+            order_by_title: None,
             filter: self.filter_param.clone(),
         };
         let query = serde_urlencoded::ser::to_string(param).unwrap();
@@ -81,11 +101,27 @@ impl PageNcchList {
             self.link.send_back(|response: Response<_>| {
                 let Json(body) = response.into_body();
                 match body {
-                    Ok(NcchQueryResponse::Ok(ncchs)) => Msg::NcchReceived(ncchs.ncchs),
+                    Ok(NcchQueryResponse::Ok(ncchs)) => Msg::NcchReceived(ncchs),
+                    Ok(NcchQueryResponse::InvalidQuery(e)) => Msg::QueryError(e.to_string()),
                     _ => Msg::NcchError,
                 }
             }),
         ));
+
+        // Every icon on the page comes from one sheet, so the rows don't request them one by one
+        let request = Request::get(&format!("{}?{}", url::query_ncch_icons(), query))
+            .body(Nothing)
+            .unwrap();
+        self.icon_fetch_task = Some(self.fetch_service.fetch(
+            request,
+            self.link.send_back(|response: Response<_>| {
+                let Json(body) = response.into_body();
+                match body {
+                    Ok(NcchIconSheetResponse::Ok(sheet)) => Msg::IconSheetReceived(sheet),
+                    _ => Msg::None,
+                }
+            }),
+        ));
     }
 
     fn refresh_page_selector(&mut self) {
@@ -126,22 +162,27 @@ impl Component for PageNcchList {
     fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
         let search_string = props
             .filter
-            .keyword
+            .query
             .clone()
+            .or_else(|| props.filter.keyword.clone())
             .unwrap_or_else(|| "".to_owned());
         let mut component = PageNcchList {
             link,
             fetch_service: FetchService::new(),
             ncch_fetch_task: None,
+            icon_fetch_task: None,
             count_fetch_task: None,
             table_status: TableStatus::Loading,
+            icon_sheet: None,
+            facets: None,
             filter_param: props.filter,
             current_page: props.current_page,
             total_page: None,
             ncchs_in_page: 20,
             search_string,
+            query_error: None,
         };
-        component.refresh_table();
+        component.refresh_table(true);
         component.refresh_page_selector();
         component
     }
@@ -149,7 +190,13 @@ impl Component for PageNcchList {
     fn update(&mut self, msg: Self::Message) -> ShouldRender {
         match msg {
             Msg::NcchReceived(ncchs) => {
-                self.table_status = TableStatus::Loaded(ncchs);
+                self.table_status = TableStatus::Loaded(ncchs.ncchs);
+                if ncchs.facets.is_some() {
+                    self.facets = ncchs.facets;
+                }
+            }
+            Msg::IconSheetReceived(sheet) => {
+                self.icon_sheet = Some(sheet);
             }
             Msg::CountReceived(count) => {
                 self.total_page = Some((std::cmp::max(count, 1) - 1) / self.ncchs_in_page + 1);
@@ -157,29 +204,38 @@ impl Component for PageNcchList {
             Msg::NcchError => {
                 self.table_status = TableStatus::Error;
             }
+            Msg::QueryError(e) => {
+                self.table_status = TableStatus::Error;
+                self.query_error = Some(e);
+            }
             Msg::PageChanged(page) => {
                 self.current_page = page;
-                self.refresh_table();
+                self.refresh_table(false);
                 self.push_history();
             }
             Msg::UpdateSearchBox(text) => {
                 self.search_string = text;
+                self.query_error = None;
             }
             Msg::Search => {
-                if self.search_string.is_empty() {
-                    self.filter_param.keyword = None;
+                self.filter_param.keyword = None;
+                if self.search_string.trim().is_empty() {
+                    self.filter_param.query = None;
+                } else if let Err(e) = query::parse(&self.search_string) {
+                    self.query_error = Some(e.to_string());
+                    return true;
                 } else {
-                    self.filter_param.keyword = Some(self.search_string.clone());
+                    self.filter_param.query = Some(self.search_string.clone());
                 }
                 self.current_page = 0;
-                self.refresh_table();
+                self.refresh_table(true);
                 self.refresh_page_selector();
                 self.push_history();
             }
             Msg::FilterUpdate(field, change) => {
                 self.apply_filter_change(&field, &change);
                 self.current_page = 0;
-                self.refresh_table();
+                self.refresh_table(true);
                 self.refresh_page_selector();
                 self.push_history();
             }
@@ -189,11 +245,14 @@ impl Component for PageNcchList {
     }
 
     fn change(&mut self, props: Self::Properties) -> ShouldRender {
-        if self.current_page != props.current_page || self.filter_param != props.filter {
+        let filter_changed = self.filter_param != props.filter;
+        if self.current_page != props.current_page || filter_changed {
             self.current_page = props.current_page;
             self.filter_param = props.filter;
-            self.refresh_table();
-            self.refresh_page_selector();
+            self.refresh_table(filter_changed);
+            if filter_changed {
+                self.refresh_page_selector();
+            }
             true
         } else {
             false
@@ -206,8 +265,22 @@ impl PageNcchList {
     fn apply_filter_change(&mut self, field: &FilterField, change: &FilterChange) {
         let filter = &mut self.filter_param;
         match field {
+            FilterField::Query => match change {
+                FilterChange::Delete => {
+                    filter.query = None;
+                    filter.keyword = None;
+                    self.search_string.clear();
+                    self.query_error = None;
+                }
+                _ => (),
+            },
             FilterField::MakerCode => match change {
                 FilterChange::Delete => filter.maker_code = None,
+                FilterChange::Text(value) => filter.maker_code = Some(value.clone()),
+                _ => (),
+            },
+            FilterField::MakerName => match change {
+                FilterChange::Delete => filter.maker_name = None,
                 _ => (),
             },
             FilterField::ProductCode => match change {
@@ -274,6 +347,24 @@ impl PageNcchList {
                 }
                 _ => (),
             },
+            FilterField::Platform => match change {
+                FilterChange::Delete => filter.platform = None,
+                FilterChange::Number(value) => {
+                    filter.platform = Some(StringWrapper::new(*value as u8))
+                }
+                _ => (),
+            },
+            FilterField::Region => match change {
+                FilterChange::Delete => {
+                    filter.region_lockout = None;
+                    filter.region_lockout_mask = None;
+                }
+                FilterChange::Number(bit) => {
+                    filter.region_lockout = Some(StringWrapper::new(1 << bit));
+                    filter.region_lockout_mask = Some(StringWrapper::new(1 << bit));
+                }
+                _ => (),
+            },
         }
     }
 
@@ -288,6 +379,39 @@ impl PageNcchList {
             .push_state((), "", Some(&format!("{}?{}", url::ncch_list(), search)))
     }
 
+    // One class carries the sheet, so that its URL is not repeated in every row
+    fn view_icon_style(&self) -> Html<Self> {
+        let sheet = match &self.icon_sheet {
+            Some(sheet) if !sheet.image.is_empty() => sheet,
+            _ => return html! {},
+        };
+        let style = format!(
+            ".icon-sheet {{ display: inline-block; width: {}px; height: {}px; \
+             background-image: url({}); }}",
+            sheet.icon_size, sheet.icon_size, sheet.image
+        );
+        html! {
+            <style>{style}</style>
+        }
+    }
+
+    fn view_icon(&self, ncch_id: &str) -> Html<Self> {
+        let offset = self
+            .icon_sheet
+            .as_ref()
+            .and_then(|sheet| sheet.icons.iter().find(|offset| offset.ncch_id == ncch_id));
+        if let Some(offset) = offset {
+            let style = format!("background-position: -{}px -{}px;", offset.x, offset.y);
+            html! {
+                <span class="icon-sheet" style=style></span>
+            }
+        } else {
+            html! {
+                <img src=url::not_found_small()/>
+            }
+        }
+    }
+
     fn view_page_selector(&self) -> Html<Self> {
         let loading = self.total_page.is_none();
         let total_page = self.total_page.unwrap_or(1);
@@ -374,6 +498,10 @@ impl PageNcchList {
         let filter = &self.filter_param;
         let mut tags = Vec::new();
 
+        if let Some(query) = filter.query.as_ref().or_else(|| filter.keyword.as_ref()) {
+            tags.push(self.filter_tag("Search", &query, FilterField::Query));
+        }
+
         if let Some(code) = &filter.product_code {
             tags.push(self.filter_tag("Product Code", &code, FilterField::ProductCode));
         }
@@ -382,6 +510,10 @@ impl PageNcchList {
             tags.push(self.filter_tag("Makder Code", &code, FilterField::MakerCode));
         }
 
+        if let Some(name) = &filter.maker_name {
+            tags.push(self.filter_tag("Publisher", &name, FilterField::MakerName));
+        }
+
         if let Some(flag) = &filter.content_is_data {
             tags.push(self.filter_tag(
                 "Data",
@@ -462,6 +594,23 @@ impl PageNcchList {
             ));
         }
 
+        if let Some(platform) = &filter.platform {
+            tags.push(self.filter_tag(
+                "Platform",
+                &format_platform(platform.value().unwrap_or(0)),
+                FilterField::Platform,
+            ));
+        }
+
+        if let Some(region) = &filter.region_lockout {
+            let bit = region.value().unwrap_or(0).trailing_zeros() as usize;
+            tags.push(self.filter_tag(
+                "Region",
+                REGION_NAME.get(bit).cloned().unwrap_or("???"),
+                FilterField::Region,
+            ));
+        }
+
         html! {
             <div class="field is-grouped is-grouped-multiline">
                 { for tags.into_iter() }
@@ -469,17 +618,116 @@ impl PageNcchList {
         }
     }
 
+    fn flag_facet(&self, field: FilterField) -> Option<&[StatsBucket]> {
+        let facets = self.facets.as_ref()?;
+        Some(match field {
+            FilterField::IsData => &facets.content_is_data,
+            FilterField::IsExecutable => &facets.content_is_executable,
+            FilterField::FixedKey => &facets.fixed_key,
+            FilterField::NoRomfs => &facets.no_romfs,
+            FilterField::NoCrypto => &facets.no_crypto,
+            FilterField::SeedCrypto => &facets.seed_crypto,
+            FilterField::SdApp => &facets.sd_app,
+            FilterField::EnableL2Cache => &facets.enable_l2_cache,
+            FilterField::HighCpuSpeed => &facets.high_cpu_speed,
+            _ => return None,
+        })
+    }
+
+    fn facet_count(buckets: Option<&[StatsBucket]>, key: &str) -> String {
+        let count = buckets.and_then(|buckets| {
+            buckets
+                .iter()
+                .find(|bucket| bucket.key.as_ref().map(|k| k.as_str()) == Some(key))
+                .map(|bucket| bucket.count)
+        });
+        match (buckets, count) {
+            (None, _) => "".to_owned(),
+            (Some(_), count) => format!(" ({})", count.unwrap_or(0)),
+        }
+    }
+
     fn filter_bool_editor(&self, field: &str, adder_field: FilterField) -> Html<Self> {
+        let facet = self.flag_facet(adder_field);
         html! {
             <>
                 <a class="button is-rounded is-small is-success is-outlined"
-                    onclick=|_|Msg::FilterUpdate(adder_field, FilterChange::Bool(true))>{field}</a>
+                    onclick=|_|Msg::FilterUpdate(adder_field, FilterChange::Bool(true))>
+                    {field}{PageNcchList::facet_count(facet, "1")}
+                </a>
                 <a class="button is-rounded is-small is-danger is-outlined"
-                    onclick=|_|Msg::FilterUpdate(adder_field, FilterChange::Bool(false))><s>{field}</s></a>
+                    onclick=|_|Msg::FilterUpdate(adder_field, FilterChange::Bool(false))>
+                    <s>{field}</s>{PageNcchList::facet_count(facet, "0")}
+                </a>
             </>
         }
     }
 
+    fn filter_value_editor(
+        &self,
+        label: String,
+        count: i64,
+        adder_field: FilterField,
+        change: FilterChange,
+    ) -> Html<Self> {
+        html! {
+            <a class="button is-rounded is-small is-info is-outlined"
+                onclick=|_|Msg::FilterUpdate(adder_field, change.clone())>
+                {format!("{} ({})", label, count)}
+            </a>
+        }
+    }
+
+    fn facet_editor(&self) -> Html<Self> {
+        let facets = if let Some(facets) = &self.facets {
+            facets
+        } else {
+            return html! {};
+        };
+
+        let mut maker_code: Vec<&StatsBucket> = facets.maker_code.iter().collect();
+        maker_code.sort_by_key(|bucket| -bucket.count);
+
+        html! {
+            <div class="level-item dropdown is-hoverable">
+                <div class="dropdown-trigger">
+                    <button class="button" aria-haspopup="true" aria-controls="dropdown-menu">
+                        <span>{"Platform & Region"}</span>
+                        <span class="icon is-small">
+                            <i class="fas fa-angle-down" aria-hidden="true"></i>
+                        </span>
+                    </button>
+                </div>
+                <div class="dropdown-menu" id="dropdown-menu" role="menu">
+                    <div class="dropdown-content">
+                        <div class="dropdown-item">
+                            {for facets.platform.iter().filter_map(|bucket| {
+                                let platform: u32 = bucket.key.as_ref()?.parse().ok()?;
+                                Some(self.filter_value_editor(format_platform(platform as u8),
+                                    bucket.count, FilterField::Platform, FilterChange::Number(platform)))
+                            })}
+                        </div>
+                        <div class="dropdown-item">
+                            {for facets.region_lockout.iter().filter_map(|bucket| {
+                                let bit: u32 = bucket.key.as_ref()?.parse().ok()?;
+                                Some(self.filter_value_editor(
+                                    REGION_NAME.get(bit as usize).cloned().unwrap_or("???").to_owned(),
+                                    bucket.count, FilterField::Region, FilterChange::Number(bit)))
+                            })}
+                        </div>
+                        <div class="dropdown-item">
+                            {for maker_code.into_iter().take(10).filter_map(|bucket| {
+                                let code = bucket.key.clone()?;
+                                Some(self.filter_value_editor(code.clone(),
+                                    bucket.count, FilterField::MakerCode, FilterChange::Text(code)))
+                            })}
+                        </div>
+                    </div>
+                </div>
+            </div>
+        }
+    }
+
     fn filter_editor(&self) -> Html<Self> {
         html! {
             <>
@@ -543,6 +791,8 @@ impl PageNcchList {
                         </div>
                     </div>
                 </div>
+
+                {self.facet_editor()}
             </>
         }
     }
@@ -584,6 +834,13 @@ impl Renderable<PageNcchList> for PageNcchList {
                                     <button class="button" onclick=|_|Msg::Search>{"Search"}</button>
                                 </p>
                             </div>
+                            {
+                                if let Some(e) = &self.query_error {
+                                    html!{<p class="help is-danger">{e}</p>}
+                                } else {
+                                    html!{}
+                                }
+                            }
                         </div>
                     </div>
                 </nav>
@@ -597,6 +854,7 @@ impl Renderable<PageNcchList> for PageNcchList {
                         {self.filter_editor()}
                     </div>
                 </nav>
+                {self.view_icon_style()}
                 {
                     if let TableStatus::Loaded(ncchs) = &self.table_status {
                         html!{
@@ -614,7 +872,7 @@ impl Renderable<PageNcchList> for PageNcchList {
                                 </thead>
                                 <tbody> {for ncchs.iter().map(|ncch|{
                                     let has_smdh = ncch.short_title.is_some();
-                                    let (title, publisher, icon) = if (has_smdh) {
+                                    let (title, publisher) = if (has_smdh) {
                                         let region = ncch.region_lockout.unwrap();
                                         let index = if (region & (1 << 1)) != 0 {
                                             1
@@ -627,18 +885,16 @@ impl Renderable<PageNcchList> for PageNcchList {
                                             }
                                         };
                                         (ncch.long_title.as_ref().unwrap()[index].as_str(),
-                                         ncch.publisher.as_ref().unwrap()[index].as_str(),
-                                         url::ncch_info(&ncch.id, "icon_small.png")
-                                        )
+                                         ncch.publisher.as_ref().unwrap()[index].as_str())
                                     } else {
-                                        ("", "", url::not_found_small().to_owned())
+                                        ("", "")
                                     };
 
                                     html!{<tr>
                                         <td><a href = format!("{}?{}", url::ncch(), ncch.id)>
                                             {"View"}
                                         </a></td>
-                                        <td><img src=icon/></td>
+                                        <td>{self.view_icon(&ncch.id)}</td>
                                         <td class="is-family-monospace">{&ncch.partition_id}</td>
                                         <td class="is-family-monospace">{&ncch.program_id}</td>
                                         <td class="is-family-monospace">{&ncch.product_code}</td>
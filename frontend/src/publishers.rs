@@ -0,0 +1,126 @@
+use index3ds_common::*;
+use yew::format::{json::Json, Nothing};
+use yew::prelude::*;
+use yew::services::fetch::*;
+
+pub enum Msg {
+    MakersReceived(Vec<Maker>),
+    MakersError,
+}
+
+// A company with all of its maker codes
+struct Publisher {
+    name: String,
+    codes: Vec<String>,
+    count: i64,
+}
+
+enum PublisherStatus {
+    Receiving,
+    Error,
+    Ready(Vec<Publisher>),
+}
+
+pub struct PagePublishers {
+    status: PublisherStatus,
+    fetch_service: FetchService,
+    fetch: FetchTask,
+}
+
+// The NCCH list filtered by a company
+pub fn publisher_url(name: &str) -> String {
+    let filter = NcchFilterParam {
+        maker_name: Some(name.to_owned()),
+        ..NcchFilterParam::default()
+    };
+    format!(
+        "{}?{}",
+        url::ncch_list(),
+        serde_urlencoded::ser::to_string(&filter).unwrap()
+    )
+}
+
+// Makers come sorted by name, so codes of the same company are adjacent
+fn group(makers: Vec<Maker>) -> Vec<Publisher> {
+    let mut publishers: Vec<Publisher> = vec![];
+    for maker in makers {
+        match publishers.last_mut() {
+            Some(last) if last.name == maker.name => {
+                last.codes.push(maker.code);
+                last.count += maker.count;
+            }
+            _ => publishers.push(Publisher {
+                name: maker.name,
+                codes: vec![maker.code],
+                count: maker.count,
+            }),
+        }
+    }
+    publishers
+}
+
+impl Component for PagePublishers {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(_: Self::Properties, mut link: ComponentLink<Self>) -> Self {
+        let mut fetch_service = FetchService::new();
+        let request = Request::get(url::makers()).body(Nothing).unwrap();
+        let fetch = fetch_service.fetch(
+            request,
+            link.send_back(|response: Response<_>| {
+                let Json(body) = response.into_body();
+                match body {
+                    Ok(MakerResponse::Ok(makers)) => Msg::MakersReceived(makers.makers),
+                    _ => Msg::MakersError,
+                }
+            }),
+        );
+        PagePublishers {
+            status: PublisherStatus::Receiving,
+            fetch_service,
+            fetch,
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            Msg::MakersReceived(makers) => self.status = PublisherStatus::Ready(group(makers)),
+            Msg::MakersError => self.status = PublisherStatus::Error,
+        }
+        true
+    }
+}
+
+impl Renderable<PagePublishers> for PagePublishers {
+    fn view(&self) -> Html<Self> {
+        match &self.status {
+            PublisherStatus::Receiving => html! {"Receiving"},
+            PublisherStatus::Error => html! {"Error"},
+            PublisherStatus::Ready(publishers) => html! {
+                <table class="table is-fullwidth is-hoverable">
+                    <thead>
+                        <th>{"Publisher"}</th>
+                        <th>{"Maker Codes"}</th>
+                        <th>{"Entries"}</th>
+                    </thead>
+                    <tbody>
+                    {for publishers.iter().map(|publisher| html! {
+                        <tr>
+                            <td><a href=publisher_url(&publisher.name)>{&publisher.name}</a></td>
+                            <td>
+                                <div class="tags">
+                                    {for publisher.codes.iter().map(|code| html! {
+                                        <span class="tag is-family-monospace">{code}</span>
+                                    })}
+                                </div>
+                            </td>
+                            <td class="is-family-monospace">{format!("{}", publisher.count)}</td>
+                        </tr>
+                    })}
+                    </tbody>
+                </table>
+            },
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use index3ds_common::*;
+use serde::*;
+use stdweb::web::*;
+use yew::format::{json::Json, Nothing};
+use yew::prelude::*;
+use yew::services::fetch::*;
+
+use crate::ncch::{
+    format_category, format_content_size, format_platform, MEMORY_MODE, REGION_NAME,
+};
+
+pub enum Msg {
+    StatsReceived(NcchStats),
+    StatsError(String),
+    UpdateSearchBox(String),
+    Search,
+    None,
+}
+
+#[derive(Serialize, Deserialize, Properties, PartialEq, Clone)]
+pub struct PageStatsProp {
+    #[serde(flatten)]
+    #[props(required)]
+    pub filter: NcchFilterParam,
+}
+
+enum StatsStatus {
+    Receiving,
+    Error(String),
+    Ready(NcchStats),
+}
+
+pub struct PageStats {
+    link: ComponentLink<PageStats>,
+    filter_param: NcchFilterParam,
+    search_string: String,
+    stats: StatsStatus,
+    fetch_service: FetchService,
+    fetch_task: Option<FetchTask>,
+}
+
+impl PageStats {
+    fn refresh(&mut self) {
+        self.stats = StatsStatus::Receiving;
+        let query = serde_urlencoded::ser::to_string(&self.filter_param).unwrap();
+        let request = Request::get(&format!("{}?{}", url::stats(), query))
+            .body(Nothing)
+            .unwrap();
+        self.fetch_task = Some(self.fetch_service.fetch(
+            request,
+            self.link.send_back(|response: Response<_>| {
+                let Json(body) = response.into_body();
+                match body {
+                    Ok(NcchStatsResponse::Ok(stats)) => Msg::StatsReceived(stats),
+                    Ok(NcchStatsResponse::InvalidQuery(e)) => Msg::StatsError(e.to_string()),
+                    _ => Msg::StatsError("Error".to_owned()),
+                }
+            }),
+        ));
+    }
+
+    fn push_history(&self) {
+        let search = serde_urlencoded::ser::to_string(&self.filter_param).unwrap();
+        window()
+            .history()
+            .push_state((), "", Some(&format!("{}?{}", url::statistics(), search)))
+    }
+}
+
+impl Component for PageStats {
+    type Message = Msg;
+    type Properties = PageStatsProp;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let search_string = props.filter.query.clone().unwrap_or_else(|| "".to_owned());
+        let mut component = PageStats {
+            link,
+            filter_param: props.filter,
+            search_string,
+            stats: StatsStatus::Receiving,
+            fetch_service: FetchService::new(),
+            fetch_task: None,
+        };
+        component.refresh();
+        component
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            Msg::StatsReceived(stats) => self.stats = StatsStatus::Ready(stats),
+            Msg::StatsError(e) => self.stats = StatsStatus::Error(e),
+            Msg::UpdateSearchBox(text) => self.search_string = text,
+            Msg::Search => {
+                if self.search_string.trim().is_empty() {
+                    self.filter_param.query = None;
+                } else if let Err(e) = query::parse(&self.search_string) {
+                    self.stats = StatsStatus::Error(e.to_string());
+                    return true;
+                } else {
+                    self.filter_param.query = Some(self.search_string.clone());
+                }
+                self.refresh();
+                self.push_history();
+            }
+            Msg::None => {}
+        }
+        true
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        if self.filter_param != props.filter {
+            self.filter_param = props.filter;
+            self.refresh();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl PageStats {
+    fn histogram<F: Fn(&str) -> String>(
+        title: &str,
+        buckets: &[StatsBucket],
+        total: i64,
+        label: F,
+    ) -> Html<Self> {
+        let total = std::cmp::max(total, 1);
+        html! {
+            <div class="tile is-child">
+                <p class="title">{title}</p>
+                <table class="table is-narrow"><tbody>
+                {for buckets.iter().map(|bucket| html!{
+                    <tr>
+                        <th>{bucket.key.as_ref().map(|k|label(k)).unwrap_or_else(||"N/A".to_owned())}</th>
+                        <td class="is-family-monospace">{format!("{}", bucket.count)}</td>
+                        <td>
+                            <progress class="progress is-info" value=bucket.count max=total>
+                                {format!("{}", bucket.count)}
+                            </progress>
+                        </td>
+                    </tr>
+                })}
+                </tbody></table>
+            </div>
+        }
+    }
+}
+
+fn parse_label<T: std::str::FromStr + Default>(key: &str) -> T {
+    key.parse().unwrap_or_default()
+}
+
+impl Renderable<PageStats> for PageStats {
+    fn view(&self) -> Html<Self> {
+        let content = match &self.stats {
+            StatsStatus::Receiving => html! {"Receiving"},
+            StatsStatus::Error(e) => html! {<p class="has-text-danger">{e}</p>},
+            StatsStatus::Ready(stats) => html! {
+                <div class="tile is-ancestor">
+                    <div class="tile is-parent is-vertical">
+                        {PageStats::histogram("Platform", &stats.platform, stats.count,
+                            |k| format_platform(parse_label(k)))}
+                        {PageStats::histogram("Content Category", &stats.content_category, stats.count,
+                            |k| format_category(parse_label(k)).to_owned())}
+                        {PageStats::histogram("Region", &stats.region_lockout, stats.count,
+                            |k| REGION_NAME.get(parse_label::<usize>(k)).cloned().unwrap_or("???").to_owned())}
+                        {PageStats::histogram("Memory Mode", &stats.system_mode, stats.count,
+                            |k| MEMORY_MODE.get(parse_label::<usize>(k)).cloned().unwrap_or("???").to_owned())}
+                    </div>
+                    <div class="tile is-parent is-vertical">
+                        {PageStats::histogram("Content Size", &stats.content_size, stats.count,
+                            |k| format!("≥ {}", format_content_size(parse_label(k))))}
+                        {PageStats::histogram("Save Data Size", &stats.save_data_size, stats.count,
+                            |k| format!("≥ {}", format_content_size(parse_label(k))))}
+                        {PageStats::histogram("Firmware ID", &stats.core_version, stats.count,
+                            |k| k.to_owned())}
+                    </div>
+                    <div class="tile is-parent is-vertical">
+                        {PageStats::histogram("Maker Code", &stats.maker_code, stats.count,
+                            |k| k.to_owned())}
+                    </div>
+                </div>
+            },
+        };
+
+        let count = if let StatsStatus::Ready(stats) = &self.stats {
+            format!("{} entries", stats.count)
+        } else {
+            "".to_owned()
+        };
+
+        html! {
+            <div>
+                <nav class="level">
+                    <div class="level-left">
+                        <div class="level-item">
+                            <p class="subtitle">{count}</p>
+                        </div>
+                    </div>
+                    <div class="level-right">
+                        <div class="level-item">
+                            <div class="field has-addons">
+                                <p class="control has-icons-left">
+                                    <input class="input" type="text"
+                                        value=&self.search_string
+                                        oninput=|e| Msg::UpdateSearchBox(e.value)
+                                        onkeypress=|e| {
+                                            if e.key() == "Enter" {
+                                                Msg::Search
+                                            } else {
+                                                Msg::None
+                                            }
+                                        }
+                                    />
+                                    <span class="icon is-small is-left">
+                                        <i class="fas fa-search"/>
+                                    </span>
+                                </p>
+                                <p class="control">
+                                    <button class="button" onclick=|_|Msg::Search>{"Search"}</button>
+                                </p>
+                            </div>
+                        </div>
+                    </div>
+                </nav>
+                {content}
+            </div>
+        }
+    }
+}
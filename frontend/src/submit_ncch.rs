@@ -1,4 +1,5 @@
 use index3ds_common::*;
+use index3ds_formats::{ByteFormat, NcsdHeader};
 use std::cell::*;
 use std::rc::Rc;
 use stdweb::web::event::LoadEndEvent;
@@ -62,7 +63,7 @@ pub enum Msg {
     SendNcchFirst(Rc<RefCell<SubmitEntry>>, File, u64, Vec<u8>),
     ProcessMoreNcch(Rc<RefCell<SubmitEntry>>, File, u64, u32, usize, usize),
     SendMoreNcch(Rc<RefCell<SubmitEntry>>, File, u64, Vec<u8>, u32),
-    NcsdToNcch(File, Vec<u8>),
+    NcsdToNcch(File, Box<NcsdHeader>),
     None,
 }
 
@@ -188,25 +189,18 @@ impl PageSubmitNcch {
 
     fn process_ncsd(&mut self, file: File) {
         self.reader_task.push(read_file_ex(
-            file.slice(0x120..0x160),
-            self.link.send_back(move |data: Vec<u8>| {
-                if data.len() != 0x40 {
-                    Msg::AddFailedSubmit(file.name())
-                } else {
-                    Msg::NcsdToNcch(file.clone(), data)
-                }
-            }),
+            file.slice(0..0x200),
+            self.link
+                .send_back(move |data: Vec<u8>| match NcsdHeader::from_bytes(&data) {
+                    Ok(header) => Msg::NcsdToNcch(file.clone(), Box::new(header)),
+                    Err(_) => Msg::AddFailedSubmit(file.name()),
+                }),
         ))
     }
 
-    fn ncsd_to_ncch(&mut self, file: File, partition_table: Vec<u8>) {
-        for (i, partition) in partition_table.chunks(8).enumerate() {
-            let mut temp = [0; 4];
-            temp[..].copy_from_slice(&partition[0..4]);
-            let offset = u32::from_le_bytes(temp) as u64 * 0x200;
-            if offset != 0 {
-                self.process_ncch(PARTITION_NAMES[i], file.clone(), offset);
-            }
+    fn ncsd_to_ncch(&mut self, file: File, header: &NcsdHeader) {
+        for (i, offset, _) in header.partition_ranges() {
+            self.process_ncch(PARTITION_NAMES[i], file.clone(), offset);
         }
     }
 
@@ -269,7 +263,7 @@ impl Component for PageSubmitNcch {
                 self.send_more_ncch(entry, file, offset, data, session_id);
             }
             Msg::NcsdToNcch(file, data) => {
-                self.ncsd_to_ncch(file, data);
+                self.ncsd_to_ncch(file, &data);
             }
             Msg::None => {}
         }
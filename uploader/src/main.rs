@@ -0,0 +1,249 @@
+use index3ds_client::{Client, ClientError, Uploaded};
+use index3ds_formats::{walk_containers, ContainerFile, NcchLocation};
+use serde::Serialize;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+
+const USAGE: &str =
+    "usage: index3ds-uploader [--host URL] [--jobs N] [--json] <file or directory>...";
+const MAX_RETRIES: u32 = 10;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+enum Status {
+    // Added to the database
+    Accepted,
+    // Already in the database
+    Matched,
+    Rejected,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct Outcome {
+    file: String,
+    // Partition or content of an NCSD or CIA, empty for a plain NCCH
+    label: String,
+    status: Status,
+    ncch_id: Option<String>,
+    reason: Option<String>,
+}
+
+struct Job {
+    path: PathBuf,
+    location: Option<NcchLocation>,
+    reason: Option<String>,
+}
+
+struct Options {
+    host: String,
+    jobs: usize,
+    json: bool,
+    paths: Vec<String>,
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        host: "http://127.0.0.1:8080".to_owned(),
+        jobs: 4,
+        json: false,
+        paths: vec![],
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--host" => options.host = args.next().ok_or("missing host")?,
+            "--jobs" => {
+                options.jobs = args
+                    .next()
+                    .and_then(|jobs| jobs.parse().ok())
+                    .filter(|&jobs| jobs > 0)
+                    .ok_or("invalid number of jobs")?
+            }
+            "--json" => options.json = true,
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => options.paths.push(arg),
+        }
+    }
+    if options.paths.is_empty() {
+        return Err("no file given".to_owned());
+    }
+    Ok(options)
+}
+
+fn collect_jobs(path: &str, jobs: &mut Vec<Job>) {
+    for ContainerFile { path, locations } in walk_containers(Path::new(path)) {
+        match locations {
+            Ok(locations) => jobs.extend(locations.into_iter().map(|location| Job {
+                path: path.clone(),
+                location: Some(location),
+                reason: None,
+            })),
+            Err(e) => jobs.push(Job {
+                path,
+                location: None,
+                reason: Some(e.to_string()),
+            }),
+        }
+    }
+}
+
+fn upload(client: &Client, path: &Path, location: &NcchLocation) -> Outcome {
+    let outcome = |status, ncch_id, reason: Option<String>| Outcome {
+        file: path.display().to_string(),
+        label: location.label.clone(),
+        status,
+        ncch_id,
+        reason,
+    };
+    if location.encrypted {
+        return outcome(
+            Status::Rejected,
+            None,
+            Some("encrypted with the title key".to_owned()),
+        );
+    }
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => return outcome(Status::Rejected, None, Some(e.to_string())),
+    };
+    let mut retries = 0;
+    loop {
+        match client.upload(&mut file, location.offset) {
+            Ok(Uploaded {
+                ncch_id,
+                added: true,
+            }) => return outcome(Status::Accepted, Some(ncch_id), None),
+            Ok(Uploaded {
+                ncch_id,
+                added: false,
+            }) => return outcome(Status::Matched, Some(ncch_id), None),
+            // The server is out of sessions, so wait for others to finish
+            Err(ClientError::Busy) if retries < MAX_RETRIES => {
+                retries += 1;
+                sleep(Duration::from_secs(u64::from(retries)));
+            }
+            Err(ClientError::Io(ref e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                return outcome(Status::Rejected, None, Some("file is truncated".to_owned()))
+            }
+            Err(e) => return outcome(Status::Rejected, None, Some(e.to_string())),
+        }
+    }
+}
+
+fn print_table(outcomes: &[Outcome]) {
+    let width = outcomes
+        .iter()
+        .map(|o| o.ncch_id.as_ref().map_or(0, |id| id.len()))
+        .max()
+        .unwrap_or(0)
+        .max("NCCH ID".len());
+    println!(
+        "{:<8}  {:<width$}  File",
+        "Status",
+        "NCCH ID",
+        width = width
+    );
+    for o in outcomes {
+        let mut file = o.file.clone();
+        if !o.label.is_empty() {
+            file += &format!(" ({})", o.label);
+        }
+        if let Some(reason) = &o.reason {
+            file += &format!(": {}", reason);
+        }
+        println!(
+            "{:<8}  {:<width$}  {}",
+            format!("{:?}", o.status),
+            o.ncch_id.clone().unwrap_or_else(|| "-".to_owned()),
+            file,
+            width = width
+        );
+    }
+    let count = |status| outcomes.iter().filter(|o| o.status == status).count();
+    println!(
+        "\nAccepted: {}, Matched: {}, Rejected: {}",
+        count(Status::Accepted),
+        count(Status::Matched),
+        count(Status::Rejected)
+    );
+}
+
+fn main() {
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let mut jobs = vec![];
+    for path in &options.paths {
+        collect_jobs(path, &mut jobs);
+    }
+    let total = jobs.len();
+    eprintln!(
+        "uploading {} NCCHs to {}",
+        jobs.iter().filter(|job| job.location.is_some()).count(),
+        options.host
+    );
+
+    let client = Client::new(&options.host);
+    let queue = Arc::new(Mutex::new(jobs.into_iter().enumerate()));
+    let (sender, receiver) = channel();
+    let workers: Vec<_> = (0..options.jobs)
+        .map(|_| {
+            let queue = queue.clone();
+            let sender = sender.clone();
+            let client = client.clone();
+            spawn(move || loop {
+                let next = queue.lock().unwrap().next();
+                let (index, job) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+                let outcome = match &job.location {
+                    Some(location) => upload(&client, &job.path, location),
+                    None => Outcome {
+                        file: job.path.display().to_string(),
+                        label: String::new(),
+                        status: Status::Rejected,
+                        ncch_id: None,
+                        reason: job.reason.clone(),
+                    },
+                };
+                sender.send((index, outcome)).unwrap();
+            })
+        })
+        .collect();
+    drop(sender);
+
+    // Reported in the order found, regardless of which upload finished first
+    let mut outcomes = vec![None; total];
+    for (done, (index, outcome)) in receiver.iter().enumerate() {
+        eprintln!(
+            "[{}/{}] {:?} {}",
+            done + 1,
+            total,
+            outcome.status,
+            outcome.file
+        );
+        outcomes[index] = Some(outcome);
+    }
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    let outcomes: Vec<Outcome> = outcomes.into_iter().map(Option::unwrap).collect();
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&outcomes).unwrap());
+    } else {
+        print_table(&outcomes);
+    }
+    if outcomes.iter().any(|o| o.status == Status::Rejected) {
+        std::process::exit(1);
+    }
+}